@@ -0,0 +1,373 @@
+use crate::clients::Client;
+use crate::errors::impls::ErrorRuntime;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+
+/// Default directory of the audit log if setup.toml does not set audit_path
+pub const DEFAULT_AUDIT_PATH: &str = "/etc/modbus-prometheus-api-server/audit";
+/// Default size of an audit log file before it is rotated
+pub const DEFAULT_AUDIT_MAX_FILE_SIZE_KB: u64 = 1024;
+/// Default number of rotated audit log files which are kept besides the current one
+pub const DEFAULT_AUDIT_MAX_FILES: usize = 10;
+/// Name of the current audit log file. Rotated files get the suffix .1 (newest) up to .{max_files} (oldest)
+pub const AUDIT_FILE_NAME: &str = "audit.log";
+
+/// Actions of audit entries
+pub const AUDIT_ACTION_WRITE_REGISTER: &str = "write_register";
+pub const AUDIT_ACTION_WRITE_BITFIELD: &str = "write_bitfield";
+pub const AUDIT_ACTION_WRITE_COIL: &str = "write_coil";
+pub const AUDIT_ACTION_CREATE_CLIENT: &str = "create_client";
+pub const AUDIT_ACTION_UPDATE_CLIENT: &str = "update_client";
+pub const AUDIT_ACTION_DELETE_CLIENT: &str = "delete_client";
+/// Result of successful operations. Failed operations record the error code of the response
+pub const AUDIT_RESULT_OK: &str = "ok";
+
+/// AuditEntry struct
+///
+/// One line of the audit log, written after every write and every change of a client config
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct AuditEntry {
+    /// Unix time in milliseconds when the request of the operation arrived
+    pub timestamp_ms: u64,
+    /// Who sent the request, the remote address if nothing else is known
+    pub caller: String,
+    /// write_register, write_bitfield, write_coil, create_client, update_client or delete_client
+    pub action: String,
+    pub client: String,
+    /// The register, bitfield or coil. None for changes of the client config
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+    /// The last polled value before the write. None if it was not read yet
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<String>,
+    /// The value as given in the write request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_value: Option<String>,
+    /// ok or the error code of the response
+    pub result: String,
+}
+impl AuditEntry {
+    /// Create an entry of an operation which is about to be done. The timestamp is taken now, the result is set when
+    /// it is recorded
+    ///
+    /// # Arguments
+    ///
+    /// * `action` - One of the AUDIT_ACTION constants
+    /// * `client` - The name of the client
+    /// * `item` - The register, bitfield or coil, if any
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new AuditEntry struct
+    pub fn new(action: &str, client: &str, item: Option<String>) -> Self {
+        AuditEntry {
+            timestamp_ms: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis() as u64),
+            caller: String::new(),
+            action: action.to_string(),
+            client: client.to_string(),
+            item,
+            old_value: None,
+            new_value: None,
+            result: String::new(),
+        }
+    }
+}
+
+/// AuditFilter struct
+///
+/// The filters of GET /audit
+///
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AuditFilter {
+    /// Only entries at or after this unix time in milliseconds
+    pub from_ms: Option<u64>,
+    /// Only entries at or before this unix time in milliseconds
+    pub to_ms: Option<u64>,
+    pub client: Option<String>,
+    /// Only the newest entries up to this number
+    pub limit: Option<usize>,
+}
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.from_ms.is_none_or(|from| entry.timestamp_ms >= from)
+            && self.to_ms.is_none_or(|to| entry.timestamp_ms <= to)
+            && self
+                .client
+                .as_ref()
+                .is_none_or(|client| &entry.client == client)
+    }
+}
+
+/// AuditLog struct
+///
+/// Append only log of all write operations as JSON lines. The current file is rotated when it exceeds the max
+/// file size, the oldest rotated file is deleted when there are more than max_files.
+///
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+}
+impl AuditLog {
+    /// Create the audit log in a directory. The directory is created with the first entry
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The directory of the audit log files
+    /// * `max_file_size_kb` - Size of a file in kilobytes before it is rotated
+    /// * `max_files` - Number of rotated files which are kept
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new AuditLog struct
+    pub fn new(path: &str, max_file_size_kb: u64, max_files: usize) -> Self {
+        AuditLog {
+            path: PathBuf::from(path),
+            max_file_size: max_file_size_kb * 1024,
+            max_files,
+        }
+    }
+    /// Append entries to the current file
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The AuditLog struct
+    /// * `entries` - The entries to append
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), std::io::Error>` - Error if the directory or file is not writable
+    pub fn append(&self, entries: &[AuditEntry]) -> Result<(), std::io::Error> {
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+        fs::create_dir_all(&self.path)?;
+        let current = self.get_file(0);
+        let size = fs::metadata(&current).map_or(0, |metadata| metadata.len());
+        if size > 0 && size + lines.len() as u64 > self.max_file_size {
+            self.rotate()?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)?;
+        file.write_all(lines.as_bytes())?;
+        file.sync_data()
+    }
+    /// Get the entries of all files which match a filter
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The AuditLog struct
+    /// * `filter` - The time, client and limit filter
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<AuditEntry>, ErrorRuntime>` - The entries, oldest first. Lines which are no entries are skipped
+    pub fn query(&self, filter: &AuditFilter) -> Result<Vec<AuditEntry>, ErrorRuntime> {
+        let mut entries = Vec::new();
+        for index in (0..=self.max_files).rev() {
+            let content = match fs::read_to_string(self.get_file(index)) {
+                Ok(content) => content,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    log::error!("Cannot read the audit log: {}", e);
+                    return Err(ErrorRuntime::AuditLogReadError);
+                }
+            };
+            for line in content.lines().filter(|line| !line.is_empty()) {
+                match serde_json::from_str::<AuditEntry>(line) {
+                    Ok(entry) if filter.matches(&entry) => entries.push(entry),
+                    Ok(_) => {}
+                    Err(e) => log::warn!("Skipping invalid audit log line: {}", e),
+                }
+            }
+        }
+        if let Some(limit) = filter.limit {
+            entries.drain(..entries.len().saturating_sub(limit));
+        }
+        Ok(entries)
+    }
+    // Shift all files by one. The oldest file is overwritten, so at most max_files rotated files are kept
+    fn rotate(&self) -> Result<(), std::io::Error> {
+        if self.max_files == 0 {
+            return fs::remove_file(self.get_file(0));
+        }
+        for index in (0..self.max_files).rev() {
+            let file = self.get_file(index);
+            if file.exists() {
+                fs::rename(file, self.get_file(index + 1))?;
+            }
+        }
+        Ok(())
+    }
+    fn get_file(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.path.join(AUDIT_FILE_NAME),
+            _ => self.path.join(format!("{}.{}", AUDIT_FILE_NAME, index)),
+        }
+    }
+}
+
+/// Get the last polled value of a register, bitfield or coil before it is written
+///
+/// # Arguments
+///
+/// * `client` - The client with the last polled values
+/// * `action` - AUDIT_ACTION_WRITE_REGISTER, AUDIT_ACTION_WRITE_BITFIELD or AUDIT_ACTION_WRITE_COIL
+/// * `item` - The name of the register, bitfield or coil
+///
+/// # Returns
+///
+/// * `Option<String>` - The value in the form of a write request. None if the item does not exist or was not read yet
+pub fn get_old_value(client: &Client, action: &str, item: &str) -> Option<String> {
+    match action {
+        AUDIT_ACTION_WRITE_REGISTER => client
+            .get_register_by_name(item)
+            .filter(|register| register.last_updated.is_some())
+            .and_then(|register| register.get_input_value()),
+        AUDIT_ACTION_WRITE_BITFIELD => client
+            .get_bitfield_by_name(item)
+            .filter(|(register, _)| register.last_updated.is_some())
+            .map(|(register, bitfield)| bitfield.get_value(register.value).to_string()),
+        AUDIT_ACTION_WRITE_COIL => client
+            .get_coil_by_name(item)
+            .filter(|coil| coil.last_updated.is_some())
+            .map(|coil| coil.value.to_string()),
+        _ => None,
+    }
+}
+
+/// AuditContext struct
+///
+/// The audit log and the caller of a request, given to all routes which write or change clients
+///
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub log: Arc<Mutex<AuditLog>>,
+    pub caller: String,
+}
+impl AuditContext {
+    /// Record the result of an operation
+    ///
+    /// A failing audit log does not fail the operation, which is already done. The error is logged instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The AuditContext struct
+    /// * `entries` - The entries of the operation, created before it was done
+    /// * `result` - AUDIT_RESULT_OK or the error code of the response
+    pub async fn record(&self, entries: Vec<AuditEntry>, result: &str) {
+        let results = vec![result; entries.len()];
+        self.record_each(entries, &results).await;
+    }
+    /// Record the result of each item of an operation
+    ///
+    /// Used by bulk writes, where some items may be written although the operation failed.
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The AuditContext struct
+    /// * `entries` - The entries of the operation, created before it was done
+    /// * `results` - AUDIT_RESULT_OK or an error code for each entry
+    pub async fn record_each(&self, mut entries: Vec<AuditEntry>, results: &[&str]) {
+        if entries.is_empty() {
+            return;
+        }
+        for (entry, result) in entries.iter_mut().zip(results) {
+            entry.caller = self.caller.clone();
+            entry.result = result.to_string();
+            log::info!(
+                "Audit: {} {} {}/{} {:?} -> {:?}: {}",
+                entry.caller,
+                entry.action,
+                entry.client,
+                entry.item.as_deref().unwrap_or_default(),
+                entry.old_value,
+                entry.new_value,
+                entry.result
+            );
+        }
+        if let Err(e) = self.log.lock().await.append(&entries) {
+            log::error!("Cannot write the audit log: {}", e);
+        }
+    }
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_audit {
+    use super::*;
+
+    fn audit_log(name: &str, max_file_size_kb: u64) -> AuditLog {
+        let path =
+            std::env::temp_dir().join(format!("modbus_audit_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        AuditLog::new(path.to_str().unwrap(), max_file_size_kb, 2)
+    }
+
+    fn entry(client: &str, timestamp_ms: u64) -> AuditEntry {
+        let mut entry = AuditEntry::new(
+            AUDIT_ACTION_WRITE_REGISTER,
+            client,
+            Some("setpoint".to_string()),
+        );
+        entry.timestamp_ms = timestamp_ms;
+        entry.new_value = Some("21.5".to_string());
+        entry.result = AUDIT_RESULT_OK.to_string();
+        entry
+    }
+
+    #[test]
+    fn test_append_and_query() {
+        let log = audit_log("query", 1024);
+        log.append(&[entry("heat_pump", 1000), entry("meter", 2000)])
+            .unwrap();
+        log.append(&[entry("heat_pump", 3000)]).unwrap();
+        assert_eq!(log.query(&AuditFilter::default()).unwrap().len(), 3);
+        let filter = AuditFilter {
+            client: Some("heat_pump".to_string()),
+            from_ms: Some(2000),
+            ..Default::default()
+        };
+        assert_eq!(log.query(&filter).unwrap(), vec![entry("heat_pump", 3000)]);
+        // The limit keeps the newest entries
+        let filter = AuditFilter {
+            limit: Some(2),
+            to_ms: Some(3000),
+            ..Default::default()
+        };
+        let entries = log.query(&filter).unwrap();
+        assert_eq!(entries[0].timestamp_ms, 2000);
+        assert_eq!(entries[1].timestamp_ms, 3000);
+        fs::remove_dir_all(&log.path).unwrap();
+    }
+
+    #[test]
+    fn test_rotate() {
+        // Every entry exceeds the file size of 0 kb, so every append starts a new file
+        let log = audit_log("rotate", 0);
+        for timestamp_ms in 1..=5 {
+            log.append(&[entry("heat_pump", timestamp_ms)]).unwrap();
+        }
+        assert!(log.get_file(2).exists());
+        assert!(!log.get_file(3).exists());
+        // The oldest entries were deleted with their files
+        let entries = log.query(&AuditFilter::default()).unwrap();
+        let timestamps: Vec<u64> = entries.iter().map(|entry| entry.timestamp_ms).collect();
+        assert_eq!(timestamps, vec![3, 4, 5]);
+        fs::remove_dir_all(&log.path).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+use crate::errors::impls::ErrorRuntime;
+use base64::Engine;
+use serde::Deserialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use warp::{Filter, Rejection};
+
+/// Scope of GET routes and /metrics
+pub const SCOPE_READ: &str = "read";
+/// Scope of the write routes. Includes read
+pub const SCOPE_WRITE: &str = "write";
+/// Scope of creating, changing and deleting clients and of the audit log. Includes write and read
+pub const SCOPE_ADMIN: &str = "admin";
+/// All scopes, each one includes the ones before
+pub const SCOPES: [&str; 3] = [SCOPE_READ, SCOPE_WRITE, SCOPE_ADMIN];
+/// Realm of the WWW-Authenticate challenge
+pub const AUTH_REALM: &str = "modbus-prometheus-api-server";
+
+/// ApiToken struct
+///
+/// A static API token, sent as `Authorization: Bearer <token>`
+///
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct ApiToken {
+    /// Name of the token, recorded as caller in the audit log
+    pub name: String,
+    pub token: String,
+    /// read, write or admin
+    pub scopes: Vec<String>,
+}
+
+/// BasicAuthUser struct
+///
+/// A user of HTTP basic auth, sent as `Authorization: Basic <base64 of name:password>`
+///
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct BasicAuthUser {
+    /// Name of the user, recorded as caller in the audit log
+    pub name: String,
+    pub password: String,
+    /// read, write or admin
+    pub scopes: Vec<String>,
+}
+
+/// Auth struct
+///
+/// The API tokens and basic auth users. Without any of them authentication is disabled and every request is allowed
+///
+#[derive(Debug, Default, Clone)]
+pub struct Auth {
+    tokens: Vec<ApiToken>,
+    users: Vec<BasicAuthUser>,
+}
+impl Auth {
+    /// Create the authentication of the server
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The API tokens of setup.toml and the token file
+    /// * `users` - The basic auth users
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, String>` - Error if a token or password is empty, a name is used twice or a scope is unknown
+    pub fn new(tokens: Vec<ApiToken>, users: Vec<BasicAuthUser>) -> Result<Self, String> {
+        let credentials = tokens
+            .iter()
+            .map(|token| (&token.name, &token.token, &token.scopes))
+            .chain(
+                users
+                    .iter()
+                    .map(|user| (&user.name, &user.password, &user.scopes)),
+            );
+        let mut names = Vec::new();
+        for (name, secret, scopes) in credentials {
+            if name.is_empty() || secret.is_empty() {
+                return Err(format!("The token or password of {:?} is empty", name));
+            }
+            if names.contains(&name) {
+                return Err(format!("The name {} is used twice", name));
+            }
+            if let Some(scope) = scopes
+                .iter()
+                .find(|scope| !SCOPES.contains(&scope.as_str()))
+            {
+                return Err(format!(
+                    "Unknown scope {} of {}. Please use {}",
+                    scope,
+                    name,
+                    SCOPES.join(", ")
+                ));
+            }
+            names.push(name);
+        }
+        Ok(Auth { tokens, users })
+    }
+    /// Read API tokens from a JSON file with an array of tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the token file
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<ApiToken>, String>` - Error if the file can not be read or is no array of tokens
+    pub fn read_token_file(path: &str) -> Result<Vec<ApiToken>, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Cannot read the token file {}: {}", path, e))?;
+        serde_json::from_str(&content)
+            .map_err(|e| format!("Cannot parse the token file {}: {}", path, e))
+    }
+    /// Check if any token or user is configured
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Auth struct
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - False if every request is allowed
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty() || !self.users.is_empty()
+    }
+    /// Check the Authorization header of a request for a scope
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Auth struct
+    /// * `authorization` - The value of the Authorization header
+    /// * `scope` - The scope the route requires
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), ErrorRuntime>` - 401 errors for missing or invalid credentials, 403 if the scope is missing
+    pub fn check(&self, authorization: Option<&str>, scope: &str) -> Result<(), ErrorRuntime> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let (_, scopes) = self.authenticate(authorization)?;
+        match has_scope(scopes, scope) {
+            true => Ok(()),
+            false => Err(ErrorRuntime::AuthScopeMissing(Some(scope.to_string()))),
+        }
+    }
+    /// Get the caller of a request for the audit log
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Auth struct
+    /// * `authorization` - The value of the Authorization header
+    /// * `remote` - The remote address of the request
+    ///
+    /// # Returns
+    ///
+    /// * `String` - name@address for valid credentials, the address otherwise
+    pub fn get_caller(&self, authorization: Option<&str>, remote: Option<SocketAddr>) -> String {
+        let address = remote.map_or("unknown".to_string(), |remote| remote.ip().to_string());
+        match self.authenticate(authorization) {
+            Ok((name, _)) if self.is_enabled() => format!("{}@{}", name, address),
+            _ => address,
+        }
+    }
+    // The name and scopes of the token or user of an Authorization header
+    fn authenticate(&self, authorization: Option<&str>) -> Result<(&str, &[String]), ErrorRuntime> {
+        let challenge = || Some(self.get_challenge());
+        let authorization = match authorization {
+            Some(authorization) => authorization.trim(),
+            None => return Err(ErrorRuntime::AuthenticationRequired(challenge())),
+        };
+        let (scheme, credentials) = authorization.split_once(' ').unwrap_or((authorization, ""));
+        let credentials = credentials.trim();
+        let found = match scheme.to_ascii_lowercase().as_str() {
+            "bearer" => self
+                .tokens
+                .iter()
+                .find(|token| is_equal(token.token.as_bytes(), credentials.as_bytes()))
+                .map(|token| (token.name.as_str(), token.scopes.as_slice())),
+            "basic" => decode_basic(credentials).and_then(|(name, password)| {
+                self.users
+                    .iter()
+                    .find(|user| {
+                        user.name == name && is_equal(user.password.as_bytes(), password.as_bytes())
+                    })
+                    .map(|user| (user.name.as_str(), user.scopes.as_slice()))
+            }),
+            _ => None,
+        };
+        found.ok_or(ErrorRuntime::AuthenticationNotValid(challenge()))
+    }
+    // The WWW-Authenticate challenge of 401 responses. Basic auth is only offered if there are users
+    fn get_challenge(&self) -> String {
+        let bearer = format!("Bearer realm=\"{}\"", AUTH_REALM);
+        match self.users.is_empty() {
+            true => bearer,
+            false => format!("{}, Basic realm=\"{}\"", bearer, AUTH_REALM),
+        }
+    }
+}
+
+/// Filter which rejects requests without the scope of a route
+///
+/// # Arguments
+///
+/// * `auth` - The authentication of the server
+/// * `scope` - SCOPE_READ, SCOPE_WRITE or SCOPE_ADMIN
+///
+/// # Returns
+///
+/// * `impl Filter` - The filter, extracting nothing
+pub fn with_scope(
+    auth: Arc<Auth>,
+    scope: &'static str,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |authorization: Option<String>| {
+            let checked = auth.check(authorization.as_deref(), scope);
+            async move { checked.map_err(warp::reject::custom) }
+        })
+        .untuple_one()
+}
+
+// Scopes include the scopes before them in SCOPES, e.g. admin includes write and read
+fn has_scope(scopes: &[String], scope: &str) -> bool {
+    let rank = |scope: &str| SCOPES.iter().position(|known| *known == scope);
+    let required = rank(scope);
+    scopes
+        .iter()
+        .any(|granted| rank(granted).is_some() && rank(granted) >= required)
+}
+
+// Decode name and password of basic auth credentials
+fn decode_basic(credentials: &str) -> Option<(String, String)> {
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(credentials)
+        .ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (name, password) = decoded.split_once(':')?;
+    Some((name.to_string(), password.to_string()))
+}
+
+// Compare secrets in constant time, so the time of the answer does not tell how much of a guess was right
+fn is_equal(secret: &[u8], guess: &[u8]) -> bool {
+    if secret.len() != guess.len() {
+        return false;
+    }
+    secret
+        .iter()
+        .zip(guess)
+        .fold(0, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_auth {
+    use super::*;
+
+    fn auth() -> Auth {
+        Auth::new(
+            vec![
+                ApiToken {
+                    name: "grafana".to_string(),
+                    token: "read-token".to_string(),
+                    scopes: vec![SCOPE_READ.to_string()],
+                },
+                ApiToken {
+                    name: "scada".to_string(),
+                    token: "write-token".to_string(),
+                    scopes: vec![SCOPE_WRITE.to_string()],
+                },
+            ],
+            vec![BasicAuthUser {
+                name: "admin".to_string(),
+                password: "secret".to_string(),
+                scopes: vec![SCOPE_ADMIN.to_string()],
+            }],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_check_scopes() {
+        let auth = auth();
+        assert!(auth.check(Some("Bearer read-token"), SCOPE_READ).is_ok());
+        assert!(matches!(
+            auth.check(Some("Bearer read-token"), SCOPE_WRITE),
+            Err(ErrorRuntime::AuthScopeMissing(_))
+        ));
+        // Write includes read, but not admin
+        assert!(auth.check(Some("Bearer write-token"), SCOPE_READ).is_ok());
+        assert!(auth.check(Some("Bearer write-token"), SCOPE_ADMIN).is_err());
+        // admin:secret
+        assert!(auth
+            .check(Some("Basic YWRtaW46c2VjcmV0"), SCOPE_ADMIN)
+            .is_ok());
+        assert!(matches!(
+            auth.check(None, SCOPE_READ),
+            Err(ErrorRuntime::AuthenticationRequired(_))
+        ));
+        // admin:wrong
+        assert!(matches!(
+            auth.check(Some("Basic YWRtaW46d3Jvbmc="), SCOPE_READ),
+            Err(ErrorRuntime::AuthenticationNotValid(_))
+        ));
+        assert!(auth.check(Some("Bearer read"), SCOPE_READ).is_err());
+        // Without tokens and users everything is allowed
+        assert!(Auth::default().check(None, SCOPE_ADMIN).is_ok());
+    }
+
+    #[test]
+    fn test_get_caller() {
+        let auth = auth();
+        let remote = Some(SocketAddr::from(([192, 168, 1, 20], 51234)));
+        assert_eq!(
+            auth.get_caller(Some("Bearer write-token"), remote),
+            "scada@192.168.1.20"
+        );
+        assert_eq!(auth.get_caller(None, remote), "192.168.1.20");
+        assert_eq!(Auth::default().get_caller(None, None), "unknown");
+    }
+
+    #[test]
+    fn test_new_not_valid() {
+        let token = ApiToken {
+            name: "grafana".to_string(),
+            token: "read-token".to_string(),
+            scopes: vec!["owner".to_string()],
+        };
+        assert!(Auth::new(vec![token.clone()], vec![]).is_err());
+        let token = ApiToken {
+            scopes: vec![SCOPE_READ.to_string()],
+            ..token
+        };
+        assert!(Auth::new(vec![token.clone(), token.clone()], vec![]).is_err());
+        let empty = ApiToken {
+            token: String::new(),
+            ..token
+        };
+        assert!(Auth::new(vec![empty], vec![]).is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use super::{Client, Coil, Register};
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+use utoipa::ToSchema;
+
+/// The last read was successful
+pub const QUALITY_GOOD: &str = "good";
+/// The last read failed, but the value is not stale yet
+pub const QUALITY_UNCERTAIN: &str = "uncertain";
+/// The value is stale according to the stale policy of the client
+pub const QUALITY_STALE: &str = "stale";
+/// The register or coil was not read yet
+pub const QUALITY_UNKNOWN: &str = "unknown";
+
+/// RegisterValue struct
+///
+/// The live value of a register as returned by GET /clients/{name}/registers
+///
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct RegisterValue {
+    pub name: String,
+    pub objecttype: String,
+    pub address: u16,
+    pub datatype: String,
+    /// Decoded and scaled value. None for strings and registers which were not read yet
+    pub value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Words of the last successful read as received from the modbus client
+    pub words: Vec<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Unix time in milliseconds of the last successful read
+    pub timestamp_ms: Option<u64>,
+    /// good, uncertain, stale or unknown
+    pub quality: String,
+}
+impl RegisterValue {
+    /// Create the live value of a register
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client of the register
+    /// * `register` - The register
+    /// * `now` - The current time, used to check the max age of the stale policy
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new RegisterValue struct
+    pub fn new(client: &Client, register: &Register, now: SystemTime) -> Self {
+        let value = match register.last_updated {
+            Some(_) if !register.is_string() => register.calc_final_value_for_registry().ok(),
+            _ => None,
+        };
+        Self {
+            name: register.name.clone(),
+            objecttype: register.objecttype.clone(),
+            address: register.address,
+            datatype: register.datatype.clone(),
+            value,
+            state: register.state.clone(),
+            text: register.text.clone(),
+            words: register.words.clone(),
+            unit: register.unit.clone(),
+            timestamp_ms: get_timestamp_ms(register.last_updated),
+            quality: get_quality(client, register.failed_reads, register.last_updated, now),
+        }
+    }
+}
+
+/// CoilValue struct
+///
+/// The live value of a coil or discrete input as returned by GET /clients/{name}/coils
+///
+#[derive(Serialize, Debug, Clone, PartialEq, ToSchema)]
+pub struct CoilValue {
+    pub name: String,
+    pub objecttype: String,
+    pub address: u16,
+    /// None if the coil was not read yet
+    pub value: Option<bool>,
+    /// Unix time in milliseconds of the last successful read
+    pub timestamp_ms: Option<u64>,
+    /// good, uncertain, stale or unknown
+    pub quality: String,
+}
+impl CoilValue {
+    /// Create the live value of a coil
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The client of the coil
+    /// * `coil` - The coil
+    /// * `now` - The current time, used to check the max age of the stale policy
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new CoilValue struct
+    pub fn new(client: &Client, coil: &Coil, now: SystemTime) -> Self {
+        Self {
+            name: coil.name.clone(),
+            objecttype: coil.objecttype.clone(),
+            address: coil.address,
+            value: coil.last_updated.map(|_| coil.value),
+            timestamp_ms: get_timestamp_ms(coil.last_updated),
+            quality: get_quality(client, coil.failed_reads, coil.last_updated, now),
+        }
+    }
+}
+
+// The quality of a value follows the stale policy of the client. Values which were never read have no quality
+fn get_quality(
+    client: &Client,
+    failed_reads: u32,
+    last_updated: Option<SystemTime>,
+    now: SystemTime,
+) -> String {
+    let quality = if last_updated.is_none() {
+        QUALITY_UNKNOWN
+    } else if client
+        .get_stale_policy()
+        .is_stale(failed_reads, last_updated, now)
+    {
+        QUALITY_STALE
+    } else if failed_reads > 0 {
+        QUALITY_UNCERTAIN
+    } else {
+        QUALITY_GOOD
+    };
+    quality.to_string()
+}
+
+fn get_timestamp_ms(time: Option<SystemTime>) -> Option<u64> {
+    time?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|duration| duration.as_millis() as u64)
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_live_values {
+    use super::*;
+    use crate::clients::staleness::STALE_POLICY_NAN;
+    use std::time::Duration;
+
+    fn client() -> Client {
+        Client::new(
+            r#"{
+                "name": "meter",
+                "ip_address": "127.0.0.1",
+                "port": 502,
+                "protocol": "tcp",
+                "stale_policy": "nan",
+                "stale_after_cycles": 3,
+                "registers": [
+                    {
+                        "name": "temperature",
+                        "objecttype": "input",
+                        "address": 1,
+                        "length": 2,
+                        "datatype": "int32",
+                        "factor": -1,
+                        "value": 0,
+                        "unit": "celsius",
+                        "byteorder": "cdab"
+                    },
+                    {
+                        "name": "serial_number",
+                        "objecttype": "holding",
+                        "address": 10,
+                        "length": 2,
+                        "datatype": "string",
+                        "factor": 0,
+                        "value": 0
+                    }
+                ],
+                "coils": [
+                    { "name": "alarm", "objecttype": "discrete", "address": 1, "value": false }
+                ]
+            }"#
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_register_value() {
+        let mut client = client();
+        assert_eq!(client.get_stale_policy().policy, STALE_POLICY_NAN);
+        let now = SystemTime::now();
+        let value = RegisterValue::new(&client, &client.registers[0], now);
+        assert_eq!(value.value, None);
+        assert_eq!(value.timestamp_ms, None);
+        assert_eq!(value.quality, QUALITY_UNKNOWN);
+        // -25.3 degrees with the low word first
+        let words = [0xFF03, 0xFFFF];
+        client.registers[0].set_value_from_words(&words, "abcd");
+        client.registers[0].last_updated = Some(UNIX_EPOCH + Duration::from_millis(1500));
+        let value = RegisterValue::new(&client, &client.registers[0], now);
+        assert!((value.value.unwrap() + 25.3).abs() < 1e-9);
+        assert_eq!(value.words, words);
+        assert_eq!(value.unit.as_deref(), Some("celsius"));
+        assert_eq!(value.timestamp_ms, Some(1500));
+        assert_eq!(value.quality, QUALITY_GOOD);
+        let json = serde_json::to_value(&value).unwrap();
+        assert_eq!(json["words"], serde_json::json!([0xFF03, 0xFFFF]));
+        assert!(json.get("state").is_none());
+        // The value is uncertain until the stale policy says it is stale
+        client.registers[0].failed_reads = 2;
+        let value = RegisterValue::new(&client, &client.registers[0], now);
+        assert_eq!(value.quality, QUALITY_UNCERTAIN);
+        client.registers[0].failed_reads = 3;
+        let value = RegisterValue::new(&client, &client.registers[0], now);
+        assert_eq!(value.quality, QUALITY_STALE);
+    }
+
+    #[test]
+    fn test_string_register_value() {
+        let mut client = client();
+        client.registers[1].set_value_from_words(&[0x4142, 0x4300], "abcd");
+        client.registers[1].last_updated = Some(SystemTime::now());
+        let value = RegisterValue::new(&client, &client.registers[1], SystemTime::now());
+        assert_eq!(value.value, None);
+        assert_eq!(value.text.as_deref(), Some("ABC"));
+        assert_eq!(value.words, vec![0x4142, 0x4300]);
+        assert_eq!(value.quality, QUALITY_GOOD);
+    }
+
+    #[test]
+    fn test_coil_value() {
+        let mut client = client();
+        let now = SystemTime::now();
+        let value = CoilValue::new(&client, &client.coils[0], now);
+        assert_eq!(value.value, None);
+        assert_eq!(value.quality, QUALITY_UNKNOWN);
+        client.coils[0].value = true;
+        client.coils[0].last_updated = Some(now);
+        let value = CoilValue::new(&client, &client.coils[0], now);
+        assert_eq!(value.value, Some(true));
+        assert_eq!(value.quality, QUALITY_GOOD);
+    }
+}
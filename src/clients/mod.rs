@@ -1,9 +1,32 @@
+use crate::connections::RequestPolicy;
 use crate::errors::impls::ErrorRuntime;
+use crate::prometheus::RESERVED_LABELS;
+use staleness::StalePolicy;
+use write_guard::Interlock;
 use crate::utils;
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+use std::collections::BTreeMap;
 use std::{collections::HashMap, fs};
+use utoipa::ToSchema;
+use tokio_serial::{DataBits, Parity, StopBits};
 
+pub mod live_values;
 pub mod read_data;
+pub mod read_plan;
+pub mod schedule;
+pub mod staleness;
+pub mod write_guard;
+pub mod write_plan;
+
+/// Default unit id for tcp clients. Addresses the device itself and not a slave behind a gateway
+pub const TCP_DEFAULT_UNIT_ID: u8 = 255;
+/// Default unit id for rtu clients
+pub const RTU_DEFAULT_UNIT_ID: u8 = 1;
+/// Default byte order of multi byte values. Most significant word and byte first as defined by the modbus spec
+pub const DEFAULT_BYTEORDER: &str = "abcd";
+/// Default time between a write and its read back, if the write is verified
+pub const DEFAULT_VERIFY_DELAY_MS: u64 = 100;
 
 /// Clients struct
 ///
@@ -71,12 +94,49 @@ impl Clients {
         &self.config
     }
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct Client {
     pub name: String,
+    #[serde(default)]
     pub ip_address: String,
+    #[serde(default)]
     pub port: u16,
     pub protocol: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub serial: Option<Serial>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_id: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byteorder: Option<String>,
+    /// Max number of unused addresses between two registers or coils which are still read in one request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_gap: Option<u16>,
+    /// Timeouts and retries of the client. Fall back to the global settings in setup.toml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect_timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_timeout_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_backoff_ms: Option<u64>,
+    /// Poll interval of the client. Falls back to read_data_interval_ms in setup.toml. 0 reads the items only once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    /// Static prometheus labels of all registers and coils of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    /// What happens with the metrics of registers and coils which can not be read: keep, nan or remove
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_policy: Option<String>,
+    /// Number of failed reads in a row after which a value is stale
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_after_cycles: Option<u32>,
+    /// Max age of the last successful read after which a value is stale
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_max_age_ms: Option<u64>,
     pub registers: Vec<Register>,
     pub coils: Vec<Coil>,
 }
@@ -102,27 +162,133 @@ impl Client {
     /// # Returns
     ///
     /// * `Result<(), ErrorRuntime>` - The result of the verification
-    fn verify(&self) -> Result<(), ErrorRuntime> {
+    pub fn verify(&self) -> Result<(), ErrorRuntime> {
         let re = regex::Regex::new(r"^[a-z0-9_]+$").unwrap();
         if !re.is_match(&self.name) {
             return Err(ErrorRuntime::RegexError);
         }
-        // Check if protocol is supported
+        // Check if protocol is supported. RTU clients need valid serial line settings
         match self.protocol.as_str() {
-            "tcp" => {}
+            // The connection needs an IPv4 address and a port, both are optional for rtu clients in the JSON config
+            "tcp" => {
+                if self.ip_address.parse::<std::net::Ipv4Addr>().is_err() || self.port == 0 {
+                    return Err(ErrorRuntime::ClientAddressNotValid(Some(format!(
+                        "{}:{}",
+                        self.ip_address, self.port
+                    ))));
+                }
+            }
+            "rtu" => match &self.serial {
+                Some(serial) => serial.verify()?,
+                None => {
+                    return Err(ErrorRuntime::ClientSerialSettingsMissing);
+                }
+            },
             _ => {
                 return Err(ErrorRuntime::ClientProtocolNotSupported);
             }
         }
+        if !self.is_unit_id_valid(self.unit_id) {
+            return Err(ErrorRuntime::ClientUnitIdNotValid(Some(self.name.to_owned())));
+        }
+        for (field, timeout) in [
+            ("connect_timeout_ms", self.connect_timeout_ms),
+            ("read_timeout_ms", self.read_timeout_ms),
+            ("write_timeout_ms", self.write_timeout_ms),
+        ] {
+            if timeout == Some(0) {
+                return Err(ErrorRuntime::ClientTimeoutNotValid(Some(field.to_string())));
+            }
+        }
+        if let Some(byteorder) = &self.byteorder {
+            if Register::get_byteorder_swaps(byteorder).is_none() {
+                return Err(ErrorRuntime::ClientByteorderNotSupported(Some(
+                    self.name.to_owned(),
+                )));
+            }
+        }
+        if let Some(policy) = &self.stale_policy {
+            if !StalePolicy::is_policy_supported(policy) {
+                return Err(ErrorRuntime::ClientStalePolicyNotValid(Some(
+                    policy.to_owned(),
+                )));
+            }
+        }
+        if self.stale_after_cycles == Some(0) {
+            return Err(ErrorRuntime::ClientStalePolicyNotValid(Some(
+                "stale_after_cycles".to_string(),
+            )));
+        }
+        if self.stale_max_age_ms == Some(0) {
+            return Err(ErrorRuntime::ClientStalePolicyNotValid(Some(
+                "stale_max_age_ms".to_string(),
+            )));
+        }
+        // Check if the static labels are valid prometheus label names and do not replace the labels of the server
+        let labels = self
+            .labels
+            .iter()
+            .chain(
+                self.registers
+                    .iter()
+                    .filter_map(|register| register.labels.as_ref()),
+            )
+            .chain(self.coils.iter().filter_map(|coil| coil.labels.as_ref()));
+        for name in labels.flat_map(|labels| labels.keys()) {
+            if !is_label_name_valid(name) {
+                return Err(ErrorRuntime::ClientLabelNotValid(Some(name.to_owned())));
+            }
+        }
+        // Names of all registers, coils and bitfields. Bitfields must not reuse the name of another metric
+        let mut names: std::collections::HashSet<String> = self
+            .registers
+            .iter()
+            .map(|register| register.name.clone())
+            .chain(self.coils.iter().map(|coil| coil.name.clone()))
+            .collect();
+        // State names start with a letter, so they can not be mistaken for a value when writing
+        let state_re = regex::Regex::new(r"^[a-z][a-z0-9_]*$").unwrap();
         // Check if the names of the registers follow the naming convention
         for register in &self.registers {
             if !re.is_match(&register.name) {
                 return Err(ErrorRuntime::RegexError);
             }
-            match register.datatype.as_str() {
-                "int16" => {}
-                "uint16" => {}
-                _ => {
+            if !self.is_unit_id_valid(register.unit_id) {
+                return Err(ErrorRuntime::ClientUnitIdNotValid(Some(
+                    register.name.to_owned(),
+                )));
+            }
+            if let Some(byteorder) = &register.byteorder {
+                if Register::get_byteorder_swaps(byteorder).is_none() {
+                    return Err(ErrorRuntime::ClientByteorderNotSupported(Some(
+                        register.name.to_owned(),
+                    )));
+                }
+            }
+            // Check if the datatype is supported and the length matches the number of words of the datatype.
+            // Strings can have any length which can be read in one request
+            match Register::get_datatype_length(&register.datatype) {
+                _ if register.is_string() => {
+                    if register.length == 0 || register.length > read_plan::MAX_READ_REGISTERS {
+                        return Err(ErrorRuntime::ClientRegisterLengthNotValid(Some(
+                            register.name.to_owned(),
+                        )));
+                    }
+                    // The name of a string register is a label of the device info metric
+                    if !is_label_name_valid(&register.name) {
+                        return Err(ErrorRuntime::ClientLabelNotValid(Some(
+                            register.name.to_owned(),
+                        )));
+                    }
+                }
+                Some(length) => {
+                    if register.length != length {
+                        return Err(ErrorRuntime::ClientRegisterLengthNotValid(Some(
+                            register.name.to_owned(),
+                        )));
+                    }
+                }
+                None => {
                     return Err(ErrorRuntime::ClientRegisterDatatypeNotSupported);
                 }
             }
@@ -133,11 +299,77 @@ impl Client {
                     return Err(ErrorRuntime::ClientRegisterObjecttypeNotSupported);
                 }
             }
+            // Bitfields must be inside the raw value of the register, which has at most 64 bits. Strings have no
+            // raw value. Their metrics are named {register}_{bitfield}
+            for bitfield in register.bitfields.iter().flatten() {
+                let name = format!("{}_{}", register.name, bitfield.name);
+                if !re.is_match(&bitfield.name)
+                    || register.is_string()
+                    || bitfield.length == 0
+                    || bitfield.bit as u16 + bitfield.length as u16 > 16 * register.length.min(4)
+                    || !names.insert(name.clone())
+                {
+                    return Err(ErrorRuntime::ClientRegisterBitfieldNotValid(Some(name)));
+                }
+            }
+            // States are named integer values. The state set is exported as {register}_state in the compat mode
+            if let Some(states) = &register.states {
+                let state_names: std::collections::HashSet<&String> = states.values().collect();
+                if !register.is_integer()
+                    || states.is_empty()
+                    || state_names.len() != states.len()
+                    || !states.values().all(|name| state_re.is_match(name))
+                    || states.keys().any(|value| {
+                        register
+                            .calc_raw_value_from_decoded(*value as f64)
+                            .is_none()
+                    })
+                    || !names.insert(format!("{}_state", register.name))
+                {
+                    return Err(ErrorRuntime::ClientRegisterStatesNotValid(Some(
+                        register.name.to_owned(),
+                    )));
+                }
+            }
+            // A gain of 0 can not be reversed when writing. Units follow the naming convention of prometheus
+            if register
+                .gain
+                .is_some_and(|gain| gain == 0.0 || !gain.is_finite())
+                || register.offset.is_some_and(|offset| !offset.is_finite())
+                || register
+                    .unit
+                    .as_ref()
+                    .is_some_and(|unit| !re.is_match(unit))
+            {
+                return Err(ErrorRuntime::ClientRegisterScalingNotValid(Some(
+                    register.name.to_owned(),
+                )));
+            }
+            // Limits need a number to compare. Bitfields only write a part of the register and could bypass them
+            let limits: Vec<f64> = register
+                .min
+                .into_iter()
+                .chain(register.max)
+                .chain(register.allowed_values.iter().flatten().copied())
+                .collect();
+            if limits.iter().any(|limit| !limit.is_finite())
+                || register.min.zip(register.max).is_some_and(|(min, max)| min > max)
+                || register.allowed_values.as_ref().is_some_and(Vec::is_empty)
+                || (!limits.is_empty() && (register.is_string() || register.bitfields.is_some()))
+                || !self.are_interlocks_valid(register.interlocks.as_ref())
+            {
+                return Err(ErrorRuntime::ClientWriteConstraintsNotValid(Some(
+                    register.name.to_owned(),
+                )));
+            }
         }
         for coil in &self.coils {
             if !re.is_match(&coil.name) {
                 return Err(ErrorRuntime::RegexError);
             }
+            if !self.is_unit_id_valid(coil.unit_id) {
+                return Err(ErrorRuntime::ClientUnitIdNotValid(Some(coil.name.to_owned())));
+            }
             match coil.objecttype.as_str() {
                 "coil" => {}
                 "discrete" => {}
@@ -145,9 +377,76 @@ impl Client {
                     return Err(ErrorRuntime::ClientRegisterObjecttypeNotSupported);
                 }
             }
+            if !self.are_interlocks_valid(coil.interlocks.as_ref()) {
+                return Err(ErrorRuntime::ClientWriteConstraintsNotValid(Some(
+                    coil.name.to_owned(),
+                )));
+            }
         }
         Ok(())
     }
+    // Check if all interlocks of a register or coil refer to a register or coil of this client
+    fn are_interlocks_valid(&self, interlocks: Option<&Vec<Interlock>>) -> bool {
+        interlocks
+            .into_iter()
+            .flatten()
+            .all(|interlock| interlock.is_valid(self))
+    }
+    // Check if a configured unit id is valid. 0 is the broadcast address and never answers reads,
+    // rtu only allows 1-247
+    fn is_unit_id_valid(&self, unit_id: Option<u8>) -> bool {
+        match unit_id {
+            None => true,
+            Some(0) => false,
+            Some(unit_id) => self.protocol != "rtu" || unit_id <= 247,
+        }
+    }
+    // Get the unit id of the client. Falls back to the protocol default if none is configured
+    pub fn get_unit_id(&self) -> u8 {
+        match self.unit_id {
+            Some(unit_id) => unit_id,
+            None if self.protocol == "rtu" => RTU_DEFAULT_UNIT_ID,
+            None => TCP_DEFAULT_UNIT_ID,
+        }
+    }
+    // Get the unit id for a register or coil. The override of the item wins over the client unit id
+    pub fn resolve_unit_id(&self, unit_id: Option<u8>) -> u8 {
+        unit_id.unwrap_or_else(|| self.get_unit_id())
+    }
+    // Get the max gap for reading registers or coils in one request. Defaults to 0, so only contiguous addresses are combined
+    pub fn get_max_gap(&self) -> u16 {
+        self.max_gap.unwrap_or(0)
+    }
+    // Get the timeouts and retries of the client. Every value which is not set falls back to the global policy
+    pub fn get_request_policy(&self, global: &RequestPolicy) -> RequestPolicy {
+        let millis =
+            |value: Option<u64>, default: Duration| value.map_or(default, Duration::from_millis);
+        RequestPolicy {
+            connect_timeout: millis(self.connect_timeout_ms, global.connect_timeout),
+            read_timeout: millis(self.read_timeout_ms, global.read_timeout),
+            write_timeout: millis(self.write_timeout_ms, global.write_timeout),
+            retries: self.retries.unwrap_or(global.retries),
+            retry_backoff: millis(self.retry_backoff_ms, global.retry_backoff),
+        }
+    }
+    // Get the stale policy of the client. Defaults to keeping the last value
+    pub fn get_stale_policy(&self) -> StalePolicy {
+        StalePolicy {
+            policy: self
+                .stale_policy
+                .clone()
+                .unwrap_or_else(|| staleness::STALE_POLICY_KEEP.to_string()),
+            after_cycles: self.stale_after_cycles,
+            max_age: self.stale_max_age_ms.map(Duration::from_millis),
+        }
+    }
+    // Get the default byte order for the registers of the client
+    pub fn get_byteorder(&self) -> String {
+        match &self.byteorder {
+            Some(byteorder) => byteorder.to_owned(),
+            None => DEFAULT_BYTEORDER.to_string(),
+        }
+    }
     pub fn get_register_by_name(&self, name: &str) -> Option<&Register> {
         for register in &self.registers {
             if register.name == name {
@@ -156,6 +455,17 @@ impl Client {
         }
         None
     }
+    // Get a bitfield and its register by the name of its metric: {register}_{bitfield}
+    pub fn get_bitfield_by_name(&self, name: &str) -> Option<(&Register, &Bitfield)> {
+        self.registers.iter().find_map(|register| {
+            register
+                .bitfields
+                .iter()
+                .flatten()
+                .find(|bitfield| format!("{}_{}", register.name, bitfield.name) == name)
+                .map(|bitfield| (register, bitfield))
+        })
+    }
     // get coil by name
     pub fn get_coil_by_name(&self, name: &str) -> Option<&Coil> {
         for coil in &self.coils {
@@ -187,12 +497,142 @@ impl Client {
         }
         false
     }
+    /// Keep the read values of the registers and coils which did not change when the client is replaced
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The new Client struct
+    /// * `old` - The replaced Client struct
+    pub fn keep_values(&mut self, old: &Client) {
+        for register in self.registers.iter_mut() {
+            if let Some(old_register) = old.get_register_by_name(&register.name) {
+                if register.has_same_config(old_register) {
+                    register.value = old_register.value;
+                    register.state = old_register.state.clone();
+                    register.text = old_register.text.clone();
+                    register.words = old_register.words.clone();
+                    register.last_updated = old_register.last_updated;
+                    register.failed_reads = old_register.failed_reads;
+                }
+            }
+        }
+        for coil in self.coils.iter_mut() {
+            if let Some(old_coil) = old.get_coil_by_name(&coil.name) {
+                if coil.has_same_config(old_coil) {
+                    coil.value = old_coil.value;
+                    coil.last_updated = old_coil.last_updated;
+                    coil.failed_reads = old_coil.failed_reads;
+                }
+            }
+        }
+    }
     // Get ip address of client
     pub fn get_ip_address(&self) -> String {
         self.ip_address.to_owned()
     }
+    // Get the endpoint of the client: <ip>:<port> for tcp, the serial device path for rtu
+    pub fn get_endpoint(&self) -> String {
+        match &self.serial {
+            Some(serial) if self.protocol == "rtu" => serial.device.to_owned(),
+            _ => format!("{}:{}", self.ip_address, self.port),
+        }
+    }
 }
-#[derive(Serialize, Deserialize, Debug)]
+// Static labels must be valid prometheus label names. Names starting with __ are reserved by prometheus
+fn is_label_name_valid(name: &str) -> bool {
+    let re = regex::Regex::new(r"^[a-zA-Z_][a-zA-Z0-9_]*$").unwrap();
+    re.is_match(name) && !name.starts_with("__") && !RESERVED_LABELS.contains(&name)
+}
+/// Serial line settings for clients with protocol rtu
+///
+/// parity, data_bits and stop_bits are optional in the JSON config and default to 8N1
+#[derive(Serialize, Deserialize, Debug, Clone, ToSchema)]
+pub struct Serial {
+    pub device: String,
+    pub baud_rate: u32,
+    #[serde(default = "Serial::default_parity")]
+    pub parity: String,
+    #[serde(default = "Serial::default_data_bits")]
+    pub data_bits: u8,
+    #[serde(default = "Serial::default_stop_bits")]
+    pub stop_bits: u8,
+}
+impl Serial {
+    fn default_parity() -> String {
+        "none".to_string()
+    }
+    fn default_data_bits() -> u8 {
+        8
+    }
+    fn default_stop_bits() -> u8 {
+        1
+    }
+    /// Verify the serial line settings
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Serial struct
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), ErrorRuntime>` - ClientSerialSettingsInvalid with the name of the first invalid setting
+    fn verify(&self) -> Result<(), ErrorRuntime> {
+        if self.device.is_empty() {
+            return Err(ErrorRuntime::ClientSerialSettingsInvalid(Some(
+                "device".to_string(),
+            )));
+        }
+        if self.baud_rate == 0 {
+            return Err(ErrorRuntime::ClientSerialSettingsInvalid(Some(
+                "baud_rate".to_string(),
+            )));
+        }
+        if self.get_parity().is_none() {
+            return Err(ErrorRuntime::ClientSerialSettingsInvalid(Some(
+                "parity".to_string(),
+            )));
+        }
+        if self.get_data_bits().is_none() {
+            return Err(ErrorRuntime::ClientSerialSettingsInvalid(Some(
+                "data_bits".to_string(),
+            )));
+        }
+        if self.get_stop_bits().is_none() {
+            return Err(ErrorRuntime::ClientSerialSettingsInvalid(Some(
+                "stop_bits".to_string(),
+            )));
+        }
+        Ok(())
+    }
+    // Parity of the serial line. Supported: none, even, odd
+    pub fn get_parity(&self) -> Option<Parity> {
+        match self.parity.as_str() {
+            "none" => Some(Parity::None),
+            "even" => Some(Parity::Even),
+            "odd" => Some(Parity::Odd),
+            _ => None,
+        }
+    }
+    // Data bits of the serial line. Supported: 5, 6, 7, 8
+    pub fn get_data_bits(&self) -> Option<DataBits> {
+        match self.data_bits {
+            5 => Some(DataBits::Five),
+            6 => Some(DataBits::Six),
+            7 => Some(DataBits::Seven),
+            8 => Some(DataBits::Eight),
+            _ => None,
+        }
+    }
+    // Stop bits of the serial line. Supported: 1, 2
+    pub fn get_stop_bits(&self) -> Option<StopBits> {
+        match self.stop_bits {
+            1 => Some(StopBits::One),
+            2 => Some(StopBits::Two),
+            _ => None,
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Default, Clone, ToSchema)]
 pub struct Register {
     pub name: String,
     pub objecttype: String,
@@ -200,48 +640,554 @@ pub struct Register {
     length: u16,
     pub datatype: String,
     pub factor: i8,
-    pub value: u16,
+    /// Raw value of the register. Multi word datatypes are combined with the most significant word first
+    pub value: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_id: Option<u8>,
+    /// Byte order of the register. Overrides the byte order of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub byteorder: Option<String>,
+    /// Poll interval of the register. Overrides the interval of the client. 0 reads the register only once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    /// Static prometheus labels of the register. Override the labels of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Linear scaling of the value after the factor: value * 10 ^ factor * gain + offset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gain: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub offset: Option<f64>,
+    /// Unit of the final value as prometheus base unit, e.g. celsius or volts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// Named bits or bit ranges of the raw value. Every bitfield is exported as its own metric
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bitfields: Option<Vec<Bitfield>>,
+    /// Write bitfields with a mask write (FC22) instead of reading and writing the whole register
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mask_write: Option<bool>,
+    /// Names of the values of a state register, e.g. 0 = off. The register is exported as a state set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub states: Option<BTreeMap<i64, String>>,
+    /// Smallest value which can be written. Compared with the final value of scaled registers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<f64>,
+    /// Largest value which can be written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<f64>,
+    /// The only values which can be written, e.g. the values of some states
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_values: Option<Vec<f64>>,
+    /// Reject all writes of the register
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+    /// Writes must be confirmed with confirm=true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires_confirmation: Option<bool>,
+    /// Conditions on other registers or coils of the client which must hold to write the register
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interlocks: Option<Vec<Interlock>>,
+    /// Read the register back after every write and check if the device took the value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_write: Option<bool>,
+    /// Time between the write and the read back. Defaults to DEFAULT_VERIFY_DELAY_MS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_delay_ms: Option<u64>,
+    /// Name of the current state. Only set for registers with states
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+    /// Decoded text of string registers, e.g. a serial number
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Words of the last successful read as received from the modbus client
+    #[serde(skip)]
+    pub words: Vec<u16>,
+    /// Time of the last successful read
+    #[serde(skip)]
+    pub last_updated: Option<SystemTime>,
+    /// Number of failed reads since the last successful read
+    #[serde(skip)]
+    pub failed_reads: u32,
 }
 impl Register {
-    /// Calculate the final value for the prometheus registry
+    /// Get the number of 16 bit words of a datatype
     ///
-    /// The value is calculated by the following formula:
-    /// self.vale * 10 ^ self.factor
+    /// # Arguments
+    ///
+    /// * `datatype` - The datatype of the register
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u16>` - The number of words. None if the datatype is not supported
+    pub fn get_datatype_length(datatype: &str) -> Option<u16> {
+        match datatype {
+            "int16" | "uint16" => Some(1),
+            "int32" | "uint32" | "float32" => Some(2),
+            "int64" | "uint64" | "float64" => Some(4),
+            _ => None,
+        }
+    }
+    /// Get the number of 16 bit words to read for this register
+    pub fn get_length(&self) -> u16 {
+        self.length
+    }
+    /// Get the swapping of a byte order, where A is the most significant byte of the value
+    ///
+    /// * `abcd` - Most significant word and byte first (modbus default)
+    /// * `cdab` - Least significant word first (word swap)
+    /// * `badc` - Least significant byte of each word first (byte swap)
+    /// * `dcba` - Least significant word and byte first (word and byte swap)
+    ///
+    /// # Arguments
+    ///
+    /// * `byteorder` - The byte order of the register
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(bool, bool)>` - Swap words and swap bytes. None if the byte order is not supported
+    pub fn get_byteorder_swaps(byteorder: &str) -> Option<(bool, bool)> {
+        match byteorder {
+            "abcd" => Some((false, false)),
+            "cdab" => Some((true, false)),
+            "badc" => Some((false, true)),
+            "dcba" => Some((true, true)),
+            _ => None,
+        }
+    }
+    // Get the byte order of the register. Falls back to the byte order of the client
+    fn get_byteorder<'a>(&'a self, client_byteorder: &'a str) -> &'a str {
+        match &self.byteorder {
+            Some(byteorder) => byteorder,
+            None => client_byteorder,
+        }
+    }
+    /// Set the raw value from the words read from the modbus client
+    ///
+    /// The words are reordered by the byte order of the register, so the raw value has the most significant word first
     ///
     /// # Arguments
     ///
     /// * `self` - The Register struct
+    /// * `words` - The words read from the modbus client. Must hold at most 4 words, except for strings
+    /// * `client_byteorder` - The byte order of the client, used if the register has none
+    pub fn set_value_from_words(&mut self, words: &[u16], client_byteorder: &str) {
+        let (swap_words, swap_bytes) =
+            Register::get_byteorder_swaps(self.get_byteorder(client_byteorder))
+                .unwrap_or((false, false));
+        self.words = words.to_vec();
+        if self.is_string() {
+            self.text = Some(decode_string(words, swap_words, swap_bytes));
+            return;
+        }
+        let mut words = words.to_vec();
+        if swap_words {
+            words.reverse();
+        }
+        self.value = words.iter().fold(0_u64, |value, word| {
+            let word = if swap_bytes { word.swap_bytes() } else { *word };
+            (value << 16) | word as u64
+        });
+        self.state = self.get_state_name().map(String::from);
+    }
+    /// Encode a raw value into the words to write to the modbus client
+    ///
+    /// This is the reverse of set_value_from_words. The number of words is the length of the register
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Register struct
+    /// * `value` - The raw value with the most significant word first
+    /// * `client_byteorder` - The byte order of the client, used if the register has none
     ///
     /// # Returns
     ///
-    /// * `Result<f64, std::io::Error>` - The final value for the prometheus registry
-    fn calc_final_value_for_registry(&self) -> Result<f64, std::io::Error> {
+    /// * `Vec<u16>` - The words in the byte order of the register
+    pub fn encode_value(&self, value: u64, client_byteorder: &str) -> Vec<u16> {
+        let (swap_words, swap_bytes) =
+            Register::get_byteorder_swaps(self.get_byteorder(client_byteorder))
+                .unwrap_or((false, false));
+        let mut words: Vec<u16> = (0..self.length)
+            .rev()
+            .map(|index| {
+                let word = (value >> (16 * index as u64)) as u16;
+                if swap_bytes {
+                    word.swap_bytes()
+                } else {
+                    word
+                }
+            })
+            .collect();
+        if swap_words {
+            words.reverse();
+        }
+        words
+    }
+    /// Check if the register has a factor, a gain or an offset. The values of these registers are written as final values
+    pub fn is_scaled(&self) -> bool {
+        self.factor != 0 || self.gain.is_some() || self.offset.is_some()
+    }
+    /// Calculate the raw value from a final value
+    ///
+    /// This is the reverse of calc_final_value_for_registry. Integer datatypes are rounded to the next integer
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Register struct
+    /// * `value` - The final value
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The raw value. None if the value does not fit into the datatype of the register
+    pub fn calc_raw_value_from_final(&self, value: f64) -> Option<u64> {
+        let value = (value - self.offset.unwrap_or(0.0))
+            / self.gain.unwrap_or(1.0)
+            / 10_f64.powf(self.factor as f64);
+        self.calc_raw_value_from_decoded(value)
+    }
+    /// Encode a value of a write request into the words to write to the modbus client
+    ///
+    /// Registers with states take the name of a state, registers with factor, gain or offset the final value and
+    /// string registers the text. All other registers take a number of their datatype, e.g. -5 for int16
+    /// or 21.5 for float32.
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Register struct
+    /// * `input` - The value as given in the write request
+    /// * `client_byteorder` - The byte order of the client, used if the register has none
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u16>, ErrorRuntime>` - The words in the byte order of the register. An error if the input
+    ///   can not be parsed or does not fit into the register
+    pub fn encode_input(&self, input: &str, client_byteorder: &str) -> Result<Vec<u16>, ErrorRuntime> {
+        let out_of_range = || ErrorRuntime::ClientRegisterValueOutOfRange(Some(self.name.clone()));
+        if self.is_string() {
+            let (swap_words, swap_bytes) =
+                Register::get_byteorder_swaps(self.get_byteorder(client_byteorder))
+                    .unwrap_or((false, false));
+            return encode_string(input, self.length, swap_words, swap_bytes).ok_or_else(out_of_range);
+        }
+        let value = if let Some(value) = self.calc_raw_value_from_state(input) {
+            value
+        } else if self.is_scaled() || !self.is_integer() {
+            let value = input
+                .parse::<f64>()
+                .map_err(|_| ErrorRuntime::ValueNotParsableToF64(Some(input.to_string())))?;
+            match self.is_scaled() {
+                true => self.calc_raw_value_from_final(value),
+                false => self.calc_raw_value_from_decoded(value),
+            }
+            .ok_or_else(out_of_range)?
+        } else {
+            let value = input
+                .parse::<i128>()
+                .map_err(|_| ErrorRuntime::ValueNotParsableToInteger(Some(input.to_string())))?;
+            self.calc_raw_value_from_integer(value)
+                .ok_or_else(out_of_range)?
+        };
+        Ok(self.encode_value(value, client_byteorder))
+    }
+    // Encode an integer into the raw value of an integer datatype. Negative values are stored as two's complement
+    fn calc_raw_value_from_integer(&self, value: i128) -> Option<u64> {
+        let (min, max) = match self.datatype.as_str() {
+            "int16" => (i16::MIN as i128, i16::MAX as i128),
+            "uint16" => (0, u16::MAX as i128),
+            "int32" => (i32::MIN as i128, i32::MAX as i128),
+            "uint32" => (0, u32::MAX as i128),
+            "int64" => (i64::MIN as i128, i64::MAX as i128),
+            "uint64" => (0, u64::MAX as i128),
+            _ => return None,
+        };
+        if value < min || value > max {
+            return None;
+        }
+        let bits = 16 * self.length as u32;
+        let mask = if bits >= 64 { u64::MAX } else { (1 << bits) - 1 };
+        Some(value as u64 & mask)
+    }
+    /// Calculate the raw value from the name of a state
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Register struct
+    /// * `name` - The name of the state
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The raw value. None if the register has no state with this name
+    pub fn calc_raw_value_from_state(&self, name: &str) -> Option<u64> {
+        let (value, _) = self
+            .states
+            .iter()
+            .flatten()
+            .find(|(_, state)| state.as_str() == name)?;
+        self.calc_raw_value_from_decoded(*value as f64)
+    }
+    // Encode a decoded value into the raw value of the datatype. Integer datatypes are rounded
+    fn calc_raw_value_from_decoded(&self, value: f64) -> Option<u64> {
+        if !value.is_finite() {
+            return None;
+        }
+        let rounded = value.round();
+        let in_range = |min: f64, max: f64| rounded >= min && rounded <= max;
         match self.datatype.as_str() {
-            "int16" => {
-                let value_int16 = self.value as i16;
-                let return_value = (value_int16 as f64) * (10_f64.powf(self.factor as f64));
-                Ok(return_value)
+            "int16" if in_range(i16::MIN as f64, i16::MAX as f64) => {
+                Some(rounded as i16 as u16 as u64)
+            }
+            "uint16" if in_range(0.0, u16::MAX as f64) => Some(rounded as u64),
+            "int32" if in_range(i32::MIN as f64, i32::MAX as f64) => {
+                Some(rounded as i32 as u32 as u64)
             }
-            "uint16" => {
-                let value_uint16 = self.value as u16;
-                let return_value = (value_uint16 as f64) * (10_f64.powf(self.factor as f64));
-                Ok(return_value)
+            "uint32" if in_range(0.0, u32::MAX as f64) => Some(rounded as u64),
+            // i64::MAX and u64::MAX round up to 2^63 and 2^64 as f64, so the upper bounds are exclusive
+            "int64" if (-9223372036854775808.0..9223372036854775808.0).contains(&rounded) => {
+                Some(rounded as i64 as u64)
             }
+            "uint64" if (0.0..18446744073709551616.0).contains(&rounded) => Some(rounded as u64),
+            "float32" if value.abs() <= f32::MAX as f64 => Some((value as f32).to_bits() as u64),
+            "float64" => Some(value.to_bits()),
+            _ => None,
+        }
+    }
+    /// Check if two registers have the same config. The read values are not part of the config
+    pub fn has_same_config(&self, other: &Register) -> bool {
+        let config = |register: &Register| {
+            let mut register = register.clone();
+            register.value = 0;
+            register.state = None;
+            register.text = None;
+            serde_json::to_value(register).ok()
+        };
+        config(self) == config(other)
+    }
+    /// Check if the register holds packed ASCII text instead of a number
+    pub fn is_string(&self) -> bool {
+        self.datatype == "string"
+    }
+    /// Check if the datatype of the register is an integer datatype
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self.datatype.as_str(),
+            "int16" | "uint16" | "int32" | "uint32" | "int64" | "uint64"
+        )
+    }
+    /// Get the raw value interpreted as the integer datatype of the register. The factor is not applied
+    ///
+    /// # Returns
+    ///
+    /// * `Option<i64>` - The value. None if the datatype is not an integer datatype
+    pub fn get_integer_value(&self) -> Option<i64> {
+        match self.datatype.as_str() {
+            "int16" => Some(self.value as u16 as i16 as i64),
+            "uint16" => Some(self.value as u16 as i64),
+            "int32" => Some(self.value as u32 as i32 as i64),
+            "uint32" => Some(self.value as u32 as i64),
+            "int64" | "uint64" => Some(self.value as i64),
+            _ => None,
+        }
+    }
+    /// Get the value in the form of a write request, the reverse of encode_input
+    ///
+    /// # Returns
+    ///
+    /// * `Option<String>` - The text of strings, the name of the current state and the final value of all others.
+    ///   None if the datatype is not supported
+    pub fn get_input_value(&self) -> Option<String> {
+        if self.is_string() {
+            return self.text.clone();
+        }
+        if let Some(state) = self.get_state_name() {
+            return Some(state.to_string());
+        }
+        self.calc_final_value_for_registry()
+            .ok()
+            .map(|value| value.to_string())
+    }
+    /// Get the name of the current state of the register
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&str>` - The name of the state. None if the register has no states or the value is not a state
+    pub fn get_state_name(&self) -> Option<&str> {
+        let value = self.get_integer_value()?;
+        self.states.as_ref()?.get(&value).map(String::as_str)
+    }
+    /// Calculate the final value for the prometheus registry
+    ///
+    /// The raw value is interpreted as the datatype of the register and then calculated by the following formula:
+    /// value * 10 ^ self.factor * self.gain + self.offset. Gain and offset default to 1 and 0
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Register struct
+    ///
+    /// # Returns
+    ///
+    /// * `Result<f64, std::io::Error>` - The final value for the prometheus registry
+    fn calc_final_value_for_registry(&self) -> Result<f64, std::io::Error> {
+        let value = match self.datatype.as_str() {
+            "int16" => self.value as u16 as i16 as f64,
+            "uint16" => self.value as u16 as f64,
+            "int32" => self.value as u32 as i32 as f64,
+            "uint32" => self.value as u32 as f64,
+            "float32" => f32::from_bits(self.value as u32) as f64,
+            "int64" => self.value as i64 as f64,
+            "uint64" => self.value as f64,
+            "float64" => f64::from_bits(self.value),
             _ => {
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::Other,
                     "Unknown datatype",
                 ));
             }
+        };
+        Ok(
+            value * (10_f64.powf(self.factor as f64)) * self.gain.unwrap_or(1.0)
+                + self.offset.unwrap_or(0.0),
+        )
+    }
+}
+// Decode packed ASCII text with two characters per word. The text ends at the first NUL, trailing spaces are removed.
+// Swapped words are swapped in pairs, like the words of 32 bit values
+fn decode_string(words: &[u16], swap_words: bool, swap_bytes: bool) -> String {
+    let mut words = words.to_vec();
+    if swap_words {
+        for pair in words.chunks_exact_mut(2) {
+            pair.swap(0, 1);
+        }
+    }
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| {
+            let [high, low] = word.to_be_bytes();
+            if swap_bytes {
+                [low, high]
+            } else {
+                [high, low]
+            }
+        })
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).trim_end().to_string()
+}
+// Encode text as packed ASCII with two characters per word, the reverse of decode_string.
+// The rest of the register is filled with NUL. None if the text is not ASCII or too long
+fn encode_string(text: &str, length: u16, swap_words: bool, swap_bytes: bool) -> Option<Vec<u16>> {
+    if !text.is_ascii() || text.len() > 2 * length as usize {
+        return None;
+    }
+    let mut bytes = text.as_bytes().to_vec();
+    bytes.resize(2 * length as usize, 0);
+    let mut words: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| match swap_bytes {
+            true => u16::from_be_bytes([pair[1], pair[0]]),
+            false => u16::from_be_bytes([pair[0], pair[1]]),
+        })
+        .collect();
+    if swap_words {
+        for pair in words.chunks_exact_mut(2) {
+            pair.swap(0, 1);
         }
     }
+    Some(words)
 }
-#[derive(Serialize, Deserialize, Debug)]
+/// Bitfield struct
+///
+/// One bit or a range of bits of the raw value of a register, e.g. an alarm bit of a status word
+///
+#[derive(Serialize, Deserialize, Debug, Default, Clone, ToSchema)]
+pub struct Bitfield {
+    pub name: String,
+    /// Position of the lowest bit of the field. 0 is the least significant bit of the raw value
+    pub bit: u8,
+    /// Number of bits of the field
+    #[serde(default = "Bitfield::default_length")]
+    pub length: u8,
+}
+impl Bitfield {
+    fn default_length() -> u8 {
+        1
+    }
+    /// Get the mask of the field in the raw value
+    pub fn get_mask(&self) -> u64 {
+        let bits = if self.length >= 64 {
+            u64::MAX
+        } else {
+            (1_u64 << self.length) - 1
+        };
+        bits << self.bit
+    }
+    /// Get the value of the field from the raw value of its register
+    pub fn get_value(&self, raw_value: u64) -> u64 {
+        (raw_value & self.get_mask()) >> self.bit
+    }
+    /// Set the value of the field in the raw value of its register
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Bitfield struct
+    /// * `raw_value` - The raw value of the register
+    /// * `value` - The new value of the field
+    ///
+    /// # Returns
+    ///
+    /// * `Option<u64>` - The new raw value. None if the value does not fit into the field
+    pub fn set_value(&self, raw_value: u64, value: u64) -> Option<u64> {
+        if value > self.get_mask() >> self.bit {
+            return None;
+        }
+        Some((raw_value & !self.get_mask()) | (value << self.bit))
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Default, Clone, ToSchema)]
 pub struct Coil {
     pub name: String,
     pub objecttype: String,
     pub address: u16,
     pub value: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit_id: Option<u8>,
+    /// Poll interval of the coil. Overrides the interval of the client. 0 reads the coil only once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_ms: Option<u64>,
+    /// Static prometheus labels of the coil. Override the labels of the client
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub labels: Option<BTreeMap<String, String>>,
+    /// Reject all writes of the coil
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub read_only: Option<bool>,
+    /// Writes must be confirmed with confirm=true
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires_confirmation: Option<bool>,
+    /// Conditions on other registers or coils of the client which must hold to switch the coil on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interlocks: Option<Vec<Interlock>>,
+    /// Read the coil back after every write and check if the device took the value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_write: Option<bool>,
+    /// Time between the write and the read back. Defaults to DEFAULT_VERIFY_DELAY_MS
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verify_delay_ms: Option<u64>,
+    /// Time of the last successful read
+    #[serde(skip)]
+    pub last_updated: Option<SystemTime>,
+    /// Number of failed reads since the last successful read
+    #[serde(skip)]
+    pub failed_reads: u32,
+}
+impl Coil {
+    /// Check if two coils have the same config. The read value is not part of the config
+    pub fn has_same_config(&self, other: &Coil) -> bool {
+        let config = |coil: &Coil| {
+            let mut coil = coil.clone();
+            coil.value = false;
+            serde_json::to_value(coil).ok()
+        };
+        config(self) == config(other)
+    }
 }
 // ----------------- TESTS -----------------
 #[cfg(test)]
@@ -502,6 +1448,57 @@ mod test_client {
       ]
     }"#;
 
+    /// Test object as input JSON for a rtu client on a serial line
+    const TEST_CLIENT_JSON_OK_RTU: &str = r#"{
+      "name": "test_client_rtu",
+      "protocol": "rtu",
+      "serial": {
+        "device": "/dev/ttyUSB0",
+        "baud_rate": 9600,
+        "parity": "even",
+        "data_bits": 8,
+        "stop_bits": 1
+      },
+      "registers": [
+        {
+          "name": "test_register_1",
+          "objecttype": "holding",
+          "address": 0,
+          "length": 1,
+          "datatype": "int16",
+          "factor": 0,
+          "value": 0
+        }
+      ],
+      "coils": [
+        {
+          "name": "test_coil_1",
+          "objecttype": "coil",
+          "address": 0,
+          "value": false
+        }
+      ]
+    }"#;
+    /// Test object as input JSON for a rtu client without serial settings
+    const TEST_CLIENT_JSON_NOT_OK_RTU_NO_SERIAL: &str = r#"{
+      "name": "test_client_rtu",
+      "protocol": "rtu",
+      "registers": [],
+      "coils": []
+    }"#;
+    /// Test object as input JSON for a rtu client with a not supported parity
+    const TEST_CLIENT_JSON_NOT_OK_RTU_WRONG_PARITY: &str = r#"{
+      "name": "test_client_rtu",
+      "protocol": "rtu",
+      "serial": {
+        "device": "/dev/ttyUSB0",
+        "baud_rate": 9600,
+        "parity": "mark"
+      },
+      "registers": [],
+      "coils": []
+    }"#;
+
     #[test]
     fn test_client_ok() {
         let client = Client::new(TEST_CLIENT_JSON_OK.to_string());
@@ -524,6 +1521,7 @@ mod test_client {
             datatype: "int16".to_string(),
             factor: 0,
             value: 65408,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -539,6 +1537,7 @@ mod test_client {
             datatype: "int16".to_string(),
             factor: 1,
             value: 65408,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -554,6 +1553,7 @@ mod test_client {
             datatype: "int16".to_string(),
             factor: -1,
             value: 65408,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -569,6 +1569,7 @@ mod test_client {
             datatype: "int16".to_string(),
             factor: -126,
             value: 65408,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -584,6 +1585,7 @@ mod test_client {
             datatype: "uint16".to_string(),
             factor: 0,
             value: 128,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -599,6 +1601,7 @@ mod test_client {
             datatype: "uint16".to_string(),
             factor: 1,
             value: 128,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -614,6 +1617,7 @@ mod test_client {
             datatype: "uint16".to_string(),
             factor: -1,
             value: 128,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -629,6 +1633,7 @@ mod test_client {
             datatype: "uint16".to_string(),
             factor: -126,
             value: 128,
+            ..Default::default()
         };
         let result = register.calc_final_value_for_registry();
         assert_eq!(result.is_ok(), true);
@@ -661,4 +1666,845 @@ mod test_client {
         println!("{:?}", client);
         assert_eq!(client.is_err(), true);
     }
+    #[test]
+    fn test_client_verify_tcp_not_ok_no_address() {
+        let client = Client::new(
+            r#"{ "name": "meter", "protocol": "tcp", "registers": [], "coils": [] }"#.to_string(),
+        );
+        assert!(matches!(
+            client,
+            Err(ErrorRuntime::ClientAddressNotValid(_))
+        ));
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.ip_address = "meter.local".to_string();
+        assert!(client.verify().is_err());
+        client.ip_address = "127.0.0.1".to_string();
+        client.port = 0;
+        assert!(client.verify().is_err());
+    }
+    #[test]
+    fn test_client_verify_rtu_ok() {
+        let client = Client::new(TEST_CLIENT_JSON_OK_RTU.to_string());
+        assert!(client.is_ok());
+        let client = client.unwrap();
+        assert_eq!(client.get_endpoint(), "/dev/ttyUSB0");
+        assert_eq!(client.serial.unwrap().get_parity(), Some(Parity::Even));
+    }
+    #[test]
+    fn test_client_verify_rtu_not_ok_no_serial() {
+        let client = Client::new(TEST_CLIENT_JSON_NOT_OK_RTU_NO_SERIAL.to_string());
+        assert!(matches!(
+            client,
+            Err(ErrorRuntime::ClientSerialSettingsMissing)
+        ));
+    }
+    #[test]
+    fn test_client_verify_rtu_not_ok_wrong_parity() {
+        let client = Client::new(TEST_CLIENT_JSON_NOT_OK_RTU_WRONG_PARITY.to_string());
+        assert!(matches!(
+            client,
+            Err(ErrorRuntime::ClientSerialSettingsInvalid(Some(setting))) if setting == "parity"
+        ));
+    }
+    #[test]
+    fn test_client_unit_id_defaults_and_override() {
+        let client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        assert_eq!(client.get_unit_id(), TCP_DEFAULT_UNIT_ID);
+        assert_eq!(client.resolve_unit_id(Some(3)), 3);
+        let client = Client::new(TEST_CLIENT_JSON_OK_RTU.to_string()).unwrap();
+        assert_eq!(client.get_unit_id(), RTU_DEFAULT_UNIT_ID);
+        assert_eq!(client.resolve_unit_id(None), RTU_DEFAULT_UNIT_ID);
+    }
+    #[test]
+    fn test_client_request_policy_overrides_global() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        let global = RequestPolicy::default();
+        assert_eq!(client.get_request_policy(&global), global);
+        client.read_timeout_ms = Some(250);
+        client.retries = Some(0);
+        let policy = client.get_request_policy(&global);
+        assert_eq!(policy.read_timeout, Duration::from_millis(250));
+        assert_eq!(policy.retries, 0);
+        assert_eq!(policy.connect_timeout, global.connect_timeout);
+        assert_eq!(policy.write_timeout, global.write_timeout);
+    }
+    #[test]
+    fn test_client_verify_not_ok_zero_timeout() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.write_timeout_ms = Some(0);
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientTimeoutNotValid(Some(field))) if field == "write_timeout_ms"
+        ));
+    }
+    #[test]
+    fn test_client_verify_stale_policy() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        assert_eq!(client.get_stale_policy(), StalePolicy::default());
+        client.stale_policy = Some("remove".to_string());
+        client.stale_max_age_ms = Some(30_000);
+        assert!(client.verify().is_ok());
+        assert_eq!(
+            client.get_stale_policy().max_age,
+            Some(Duration::from_secs(30))
+        );
+        client.stale_after_cycles = Some(0);
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientStalePolicyNotValid(Some(field))) if field == "stale_after_cycles"
+        ));
+        client.stale_after_cycles = None;
+        client.stale_policy = Some("zero".to_string());
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientStalePolicyNotValid(Some(policy))) if policy == "zero"
+        ));
+    }
+    #[test]
+    fn test_client_verify_labels() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.labels = Some(BTreeMap::from([(
+            "Site".to_string(),
+            "Building A".to_string(),
+        )]));
+        assert!(client.verify().is_ok());
+        for name in ["client", "__name", "1st", "site-id"] {
+            client.registers[0].labels =
+                Some(BTreeMap::from([(name.to_string(), "x".to_string())]));
+            assert!(matches!(
+                client.verify(),
+                Err(ErrorRuntime::ClientLabelNotValid(Some(label))) if label == name
+            ));
+        }
+    }
+    #[test]
+    fn test_client_verify_not_ok_wrong_unit_id() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK_RTU.to_string()).unwrap();
+        client.registers[0].unit_id = Some(248);
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientUnitIdNotValid(Some(item))) if item == "test_register_1"
+        ));
+        client.registers[0].unit_id = None;
+        client.unit_id = Some(0);
+        assert!(client.verify().is_err());
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_int32_minus100000_factor0_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "int32".to_string(),
+            factor: 0,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0xFFFE, 0x7960], DEFAULT_BYTEORDER);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), -100000.0);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint32_100000_factorminus1_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "uint32".to_string(),
+            factor: -1,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x0001, 0x86A0], DEFAULT_BYTEORDER);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 10000.0);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_float32_1_5_factor0_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "float32".to_string(),
+            factor: 0,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x3FC0, 0x0000], DEFAULT_BYTEORDER);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 1.5);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_int64_minus2_factor0_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 4,
+            datatype: "int64".to_string(),
+            factor: 0,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0xFFFF, 0xFFFF, 0xFFFF, 0xFFFE], DEFAULT_BYTEORDER);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), -2.0);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint64_4294967296_factor1_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 4,
+            datatype: "uint64".to_string(),
+            factor: 1,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x0000, 0x0001, 0x0000, 0x0000], DEFAULT_BYTEORDER);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 42949672960.0);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_float64_minus2_5_factor0_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 4,
+            datatype: "float64".to_string(),
+            factor: 0,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0xC004, 0x0000, 0x0000, 0x0000], DEFAULT_BYTEORDER);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), -2.5);
+    }
+    #[test]
+    fn test_client_verify_not_ok_wrong_register_length() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].datatype = "float32".to_string();
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientRegisterLengthNotValid(Some(register))) if register == "test_register_1"
+        ));
+        client.registers[0].length = 2;
+        assert!(client.verify().is_ok());
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint16_byteorder_abcd_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 1,
+            datatype: "uint16".to_string(),
+            factor: 0,
+            byteorder: Some("abcd".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x1122], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x1122);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4386.0);
+        assert_eq!(register.encode_value(0x1122, DEFAULT_BYTEORDER), vec![0x1122]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint16_byteorder_cdab_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 1,
+            datatype: "uint16".to_string(),
+            factor: 0,
+            byteorder: Some("cdab".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x1122], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x1122);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4386.0);
+        assert_eq!(register.encode_value(0x1122, DEFAULT_BYTEORDER), vec![0x1122]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint16_byteorder_badc_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 1,
+            datatype: "uint16".to_string(),
+            factor: 0,
+            byteorder: Some("badc".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x2211], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x1122);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4386.0);
+        assert_eq!(register.encode_value(0x1122, DEFAULT_BYTEORDER), vec![0x2211]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint16_byteorder_dcba_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 1,
+            datatype: "uint16".to_string(),
+            factor: 0,
+            byteorder: Some("dcba".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x2211], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x1122);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4386.0);
+        assert_eq!(register.encode_value(0x1122, DEFAULT_BYTEORDER), vec![0x2211]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint32_byteorder_abcd_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "uint32".to_string(),
+            factor: 0,
+            byteorder: Some("abcd".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x1122, 0x3344], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x11223344);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 287454020.0);
+        assert_eq!(register.encode_value(0x11223344, DEFAULT_BYTEORDER), vec![0x1122, 0x3344]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint32_byteorder_cdab_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "uint32".to_string(),
+            factor: 0,
+            byteorder: Some("cdab".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x3344, 0x1122], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x11223344);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 287454020.0);
+        assert_eq!(register.encode_value(0x11223344, DEFAULT_BYTEORDER), vec![0x3344, 0x1122]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint32_byteorder_badc_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "uint32".to_string(),
+            factor: 0,
+            byteorder: Some("badc".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x2211, 0x4433], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x11223344);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 287454020.0);
+        assert_eq!(register.encode_value(0x11223344, DEFAULT_BYTEORDER), vec![0x2211, 0x4433]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_uint32_byteorder_dcba_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "uint32".to_string(),
+            factor: 0,
+            byteorder: Some("dcba".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x4433, 0x2211], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x11223344);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 287454020.0);
+        assert_eq!(register.encode_value(0x11223344, DEFAULT_BYTEORDER), vec![0x4433, 0x2211]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_int64_byteorder_abcd_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 4,
+            datatype: "int64".to_string(),
+            factor: 0,
+            byteorder: Some("abcd".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x0011, 0x2233, 0x4455, 0x6677], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x0011223344556677);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4822678189205111.0);
+        assert_eq!(register.encode_value(0x0011223344556677, DEFAULT_BYTEORDER), vec![0x0011, 0x2233, 0x4455, 0x6677]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_int64_byteorder_cdab_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 4,
+            datatype: "int64".to_string(),
+            factor: 0,
+            byteorder: Some("cdab".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x6677, 0x4455, 0x2233, 0x0011], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x0011223344556677);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4822678189205111.0);
+        assert_eq!(register.encode_value(0x0011223344556677, DEFAULT_BYTEORDER), vec![0x6677, 0x4455, 0x2233, 0x0011]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_int64_byteorder_badc_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 4,
+            datatype: "int64".to_string(),
+            factor: 0,
+            byteorder: Some("badc".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x1100, 0x3322, 0x5544, 0x7766], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x0011223344556677);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4822678189205111.0);
+        assert_eq!(register.encode_value(0x0011223344556677, DEFAULT_BYTEORDER), vec![0x1100, 0x3322, 0x5544, 0x7766]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_int64_byteorder_dcba_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 4,
+            datatype: "int64".to_string(),
+            factor: 0,
+            byteorder: Some("dcba".to_string()),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x7766, 0x5544, 0x3322, 0x1100], DEFAULT_BYTEORDER);
+        assert_eq!(register.value, 0x0011223344556677);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 4822678189205111.0);
+        assert_eq!(register.encode_value(0x0011223344556677, DEFAULT_BYTEORDER), vec![0x7766, 0x5544, 0x3322, 0x1100]);
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_float32_client_byteorder_cdab_ok() {
+        // The register has no byte order of its own and uses the one of the client
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 2,
+            datatype: "float32".to_string(),
+            factor: 0,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[0x0000, 0x3FC0], "cdab");
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 1.5);
+    }
+    #[test]
+    fn test_client_verify_not_ok_wrong_byteorder() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.byteorder = Some("dcab".to_string());
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientByteorderNotSupported(Some(item))) if item == "test_client"
+        ));
+        client.byteorder = None;
+        client.registers[1].byteorder = Some("ABCD".to_string());
+        assert!(client.verify().is_err());
+    }
+    #[test]
+    fn test_calc_final_value_for_registry_int16_gain_offset_ok() {
+        let mut register = Register {
+            name: "test".to_string(),
+            objecttype: "holding".to_string(),
+            address: 0,
+            length: 1,
+            datatype: "int16".to_string(),
+            factor: 0,
+            gain: Some(0.0625),
+            offset: Some(-40.0),
+            ..Default::default()
+        };
+        register.set_value_from_words(&[1000], DEFAULT_BYTEORDER);
+        let result = register.calc_final_value_for_registry();
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap(), 22.5);
+        // Writing reverses the scaling
+        assert_eq!(register.calc_raw_value_from_final(22.5), Some(1000));
+        assert_eq!(register.calc_raw_value_from_final(-41.0), Some(0xFFF0));
+        assert_eq!(register.calc_raw_value_from_final(3000.0), None);
+    }
+    #[test]
+    fn test_calc_raw_value_from_final_factor_and_datatypes() {
+        let mut register = Register {
+            name: "test".to_string(),
+            length: 2,
+            datatype: "uint32".to_string(),
+            factor: -1,
+            gain: Some(2.0),
+            ..Default::default()
+        };
+        assert!(register.is_scaled());
+        assert_eq!(register.calc_raw_value_from_final(20000.0), Some(100000));
+        assert_eq!(register.calc_raw_value_from_final(-1.0), None);
+        register.datatype = "float32".to_string();
+        assert_eq!(
+            register.calc_raw_value_from_final(3.0),
+            Some(15.0_f32.to_bits() as u64)
+        );
+        register.gain = None;
+        assert!(register.is_scaled());
+        register.factor = 0;
+        assert!(!register.is_scaled());
+    }
+    #[test]
+    fn test_calc_raw_value_from_final_64_bit_bounds() {
+        let mut register = Register {
+            name: "counter".to_string(),
+            length: 4,
+            datatype: "uint64".to_string(),
+            gain: Some(1.0),
+            ..Default::default()
+        };
+        // 2^64 does not fit, the largest f64 below it does
+        assert_eq!(
+            register.calc_raw_value_from_final(18446744073709551616.0),
+            None
+        );
+        assert_eq!(
+            register.calc_raw_value_from_final(18446744073709549568.0),
+            Some(18446744073709549568)
+        );
+        register.datatype = "int64".to_string();
+        assert_eq!(
+            register.calc_raw_value_from_final(9223372036854775808.0),
+            None
+        );
+        assert_eq!(
+            register.calc_raw_value_from_final(-9223372036854775808.0),
+            Some(i64::MIN as u64)
+        );
+    }
+    #[test]
+    fn test_encode_input_factor_round_trip() {
+        let mut register = Register {
+            name: "voltage".to_string(),
+            objecttype: "holding".to_string(),
+            length: 1,
+            datatype: "uint16".to_string(),
+            factor: -1,
+            ..Default::default()
+        };
+        register.set_value_from_words(&[2305], DEFAULT_BYTEORDER);
+        assert_eq!(register.get_input_value().as_deref(), Some("230.5"));
+        let words = register.encode_input("230.5", DEFAULT_BYTEORDER).unwrap();
+        assert_eq!(words, vec![2305]);
+        register.set_value_from_words(&words, DEFAULT_BYTEORDER);
+        assert_eq!(register.get_input_value().as_deref(), Some("230.5"));
+    }
+    #[test]
+    fn test_encode_input_datatypes() {
+        let mut register = Register {
+            name: "setpoint".to_string(),
+            objecttype: "holding".to_string(),
+            length: 1,
+            datatype: "int16".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(register.encode_input("-5", "abcd").unwrap(), vec![0xFFFB]);
+        assert!(register.encode_input("40000", "abcd").is_err());
+        assert!(matches!(
+            register.encode_input("1.5", "abcd"),
+            Err(ErrorRuntime::ValueNotParsableToInteger(_))
+        ));
+        register.length = 2;
+        register.datatype = "uint32".to_string();
+        assert_eq!(
+            register.encode_input("100000", "abcd").unwrap(),
+            vec![0x0001, 0x86A0]
+        );
+        assert_eq!(
+            register.encode_input("100000", "cdab").unwrap(),
+            vec![0x86A0, 0x0001]
+        );
+        assert!(register.encode_input("-1", "abcd").is_err());
+        register.datatype = "float32".to_string();
+        assert_eq!(
+            register.encode_input("21.5", "abcd").unwrap(),
+            vec![0x41AC, 0x0000]
+        );
+        register.factor = -1;
+        assert_eq!(
+            register.encode_input("2.15", "abcd").unwrap(),
+            vec![0x41AC, 0x0000]
+        );
+        register.length = 4;
+        register.datatype = "int64".to_string();
+        register.factor = 0;
+        assert_eq!(
+            register.encode_input("-2", "abcd").unwrap(),
+            vec![0xFFFF, 0xFFFF, 0xFFFF, 0xFFFE]
+        );
+    }
+    #[test]
+    fn test_encode_input_string() {
+        let register = Register {
+            name: "location".to_string(),
+            objecttype: "holding".to_string(),
+            length: 2,
+            datatype: "string".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            register.encode_input("ABC", "abcd").unwrap(),
+            vec![0x4142, 0x4300]
+        );
+        assert_eq!(
+            register.encode_input("ABC", "dcba").unwrap(),
+            vec![0x0043, 0x4241]
+        );
+        let mut decoded = register.clone();
+        decoded.set_value_from_words(&register.encode_input("AB", "badc").unwrap(), "badc");
+        assert_eq!(decoded.text.as_deref(), Some("AB"));
+        assert!(register.encode_input("ABCDE", "abcd").is_err());
+        assert!(register.encode_input("Grüße", "abcd").is_err());
+    }
+    #[test]
+    fn test_get_input_value() {
+        let mut register = Register {
+            name: "setpoint".to_string(),
+            objecttype: "holding".to_string(),
+            length: 1,
+            datatype: "int16".to_string(),
+            factor: -1,
+            value: 0xFFFB,
+            ..Default::default()
+        };
+        assert_eq!(register.get_input_value().as_deref(), Some("-0.5"));
+        register.gain = Some(2.0);
+        assert_eq!(register.get_input_value().as_deref(), Some("-1"));
+        register.states = Some(BTreeMap::from([(-5, "defrost".to_string())]));
+        assert_eq!(register.get_input_value().as_deref(), Some("defrost"));
+    }
+    #[test]
+    fn test_bitfield_get_and_set_value() {
+        let bitfield = Bitfield {
+            name: "mode".to_string(),
+            bit: 4,
+            length: 3,
+        };
+        assert_eq!(bitfield.get_mask(), 0x70);
+        assert_eq!(bitfield.get_value(0xA5), 2);
+        assert_eq!(bitfield.set_value(0xA5, 7), Some(0xF5));
+        assert_eq!(bitfield.set_value(0xA5, 8), None);
+    }
+    #[test]
+    fn test_client_verify_bitfields() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].bitfields = Some(vec![Bitfield {
+            name: "alarm".to_string(),
+            bit: 15,
+            length: 1,
+        }]);
+        assert!(client.verify().is_ok());
+        assert!(client
+            .get_bitfield_by_name("test_register_1_alarm")
+            .is_some());
+        // The bitfield must be inside the register
+        client.registers[0].bitfields.as_mut().unwrap()[0].length = 2;
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientRegisterBitfieldNotValid(Some(item))) if item == "test_register_1_alarm"
+        ));
+        // The name must not be used by another register
+        client.registers[0].bitfields.as_mut().unwrap()[0].length = 1;
+        client.registers[1].name = "test_register_1_alarm".to_string();
+        assert!(client.verify().is_err());
+        client.registers[1].name = "test_register_2".to_string();
+        // The raw value has at most 64 bits
+        client.registers[0].length = 4;
+        client.registers[0].datatype = "uint64".to_string();
+        client.registers[0].bitfields.as_mut().unwrap()[0].bit = 63;
+        assert!(client.verify().is_ok());
+        client.registers[0].bitfields.as_mut().unwrap()[0].length = 2;
+        assert!(client.verify().is_err());
+        // Strings have no raw value, so long string registers have no bitfields either
+        client.registers[0].length = 8;
+        client.registers[0].datatype = "string".to_string();
+        client.registers[0].bitfields.as_mut().unwrap()[0].bit = 64;
+        client.registers[0].bitfields.as_mut().unwrap()[0].length = 1;
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientRegisterBitfieldNotValid(_))
+        ));
+    }
+    #[test]
+    fn test_register_states() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].states = Some(BTreeMap::from([
+            (-1, "fault".to_string()),
+            (0, "off".to_string()),
+            (3, "heating".to_string()),
+        ]));
+        assert!(client.verify().is_ok());
+        let register = &mut client.registers[0];
+        register.set_value_from_words(&[0xffff], DEFAULT_BYTEORDER);
+        assert_eq!(register.get_state_name(), Some("fault"));
+        assert_eq!(register.state.as_deref(), Some("fault"));
+        register.set_value_from_words(&[7], DEFAULT_BYTEORDER);
+        assert_eq!(register.state, None);
+        assert_eq!(register.calc_raw_value_from_state("heating"), Some(3));
+        assert_eq!(register.calc_raw_value_from_state("fault"), Some(0xffff));
+        assert_eq!(register.calc_raw_value_from_state("cooling"), None);
+    }
+    #[test]
+    fn test_client_verify_states() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].states = Some(BTreeMap::from([
+            (0, "off".to_string()),
+            (1, "off".to_string()),
+        ]));
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientRegisterStatesNotValid(Some(item))) if item == "test_register_1"
+        ));
+        // State names can not be mistaken for values
+        client.registers[0].states = Some(BTreeMap::from([(0, "1st".to_string())]));
+        assert!(client.verify().is_err());
+        // The values must fit into the datatype
+        client.registers[0].states = Some(BTreeMap::from([(40000, "on".to_string())]));
+        assert!(client.verify().is_err());
+        client.registers[0].states = Some(BTreeMap::from([(0, "off".to_string())]));
+        client.registers[0].datatype = "float32".to_string();
+        client.registers[0].length = 2;
+        assert!(client.verify().is_err());
+    }
+    #[test]
+    fn test_client_verify_write_constraints() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].min = Some(10.0);
+        client.registers[0].max = Some(60.0);
+        client.registers[0].interlocks = Some(vec![Interlock {
+            register: Some("test_register_3".to_string()),
+            operator: "<".to_string(),
+            value: 50.0,
+            ..Default::default()
+        }]);
+        assert!(client.verify().is_ok());
+        client.registers[0].max = Some(5.0);
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientWriteConstraintsNotValid(Some(item))) if item == "test_register_1"
+        ));
+        client.registers[0].max = None;
+        client.registers[0].allowed_values = Some(vec![]);
+        assert!(client.verify().is_err());
+        // Bitfields could bypass the limits
+        client.registers[0].allowed_values = None;
+        client.registers[0].bitfields = Some(vec![Bitfield {
+            name: "mode".to_string(),
+            bit: 0,
+            length: 2,
+        }]);
+        assert!(client.verify().is_err());
+        client.registers[0].min = None;
+        assert!(client.verify().is_ok());
+        // Interlocks must refer to a register or coil of the client
+        client.coils[0].interlocks = Some(vec![Interlock {
+            register: Some("power".to_string()),
+            operator: "<".to_string(),
+            value: 50.0,
+            ..Default::default()
+        }]);
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientWriteConstraintsNotValid(Some(item))) if item == "test_coil_1"
+        ));
+    }
+    #[test]
+    fn test_string_register() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].datatype = "string".to_string();
+        client.registers[0].length = 4;
+        client.registers[0].name = "serial".to_string();
+        assert!(client.verify().is_ok());
+        let register = &mut client.registers[0];
+        // "SN-12" padded with NUL and a space before
+        register.set_value_from_words(&[0x534e, 0x2d31, 0x3220, 0x0000], DEFAULT_BYTEORDER);
+        assert_eq!(register.text.as_deref(), Some("SN-12"));
+        register.set_value_from_words(&[0x4e53, 0x312d, 0x2032, 0x0000], "badc");
+        assert_eq!(register.text.as_deref(), Some("SN-12"));
+        register.set_value_from_words(&[0x2d31, 0x534e, 0x0000, 0x3220], "cdab");
+        assert_eq!(register.text.as_deref(), Some("SN-12"));
+    }
+    #[test]
+    fn test_client_verify_string_register() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].datatype = "string".to_string();
+        client.registers[0].length = 126;
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientRegisterLengthNotValid(Some(item))) if item == "test_register_1"
+        ));
+        // The name is a label of the device info metric
+        client.registers[0].length = 8;
+        client.registers[0].name = "client".to_string();
+        assert!(client.verify().is_err());
+    }
+    #[test]
+    fn test_client_verify_not_ok_register_scaling() {
+        let mut client = Client::new(TEST_CLIENT_JSON_OK.to_string()).unwrap();
+        client.registers[0].unit = Some("celsius".to_string());
+        client.registers[0].offset = Some(-40.0);
+        assert!(client.verify().is_ok());
+        client.registers[0].gain = Some(0.0);
+        assert!(matches!(
+            client.verify(),
+            Err(ErrorRuntime::ClientRegisterScalingNotValid(Some(item))) if item == "test_register_1"
+        ));
+        client.registers[0].gain = None;
+        client.registers[0].unit = Some("°C".to_string());
+        assert!(client.verify().is_err());
+    }
 }
@@ -1,193 +1,537 @@
-use super::Clients;
+use super::schedule::Schedule;
+use super::staleness::StalePolicy;
+use super::{read_plan, Client, Clients, Coil, Register};
+use crate::connections::{Connection, ConnectionManager};
+use crate::errors::impls::ErrorRuntimeNoRejection;
+use crate::prometheus::exporter::{READ_ERROR_DECODE, READ_ERROR_SHORT_RESPONSE};
 use crate::prometheus::PrometheusMetrics;
-use crate::utils;
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::SystemTime;
 use tokio::sync::Mutex;
-use tokio_modbus::prelude::*;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
 
-// Side thread for gathering data of all registered modbus client. The data is then stored in the prometheus Variables
-pub async fn read_data(registry: Arc<Mutex<PrometheusMetrics>>, clients: Arc<Mutex<Clients>>, intervall: u64) {
-    let mut read_data_interval =
-        tokio::time::interval(Duration::from_millis(intervall));
+/// Pollers struct
+///
+/// Every client is polled in its own task, so an unreachable client does not delay the others.
+/// The tasks are started and stopped together with the clients.
+///
+pub struct Pollers {
+    tasks: HashMap<String, JoinHandle<()>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    clients: Arc<Mutex<Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    intervall: u64,
+}
+impl Pollers {
+    /// Create a new Pollers struct without any running task
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The prometheus registry to store the values in
+    /// * `clients` - The clients to poll
+    /// * `connections` - The connections to the clients, shared with the write routes
+    /// * `intervall` - The poll interval in ms
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new Pollers struct
+    pub fn new(
+        registry: Arc<Mutex<PrometheusMetrics>>,
+        clients: Arc<Mutex<Clients>>,
+        connections: Arc<Mutex<ConnectionManager>>,
+        intervall: u64,
+    ) -> Self {
+        Self {
+            tasks: HashMap::new(),
+            registry,
+            clients,
+            connections,
+            intervall,
+        }
+    }
+    /// Start a polling task for every client
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Pollers struct
+    pub async fn start_all(&mut self) {
+        let names: Vec<String> = self.clients.lock().await.clients.keys().cloned().collect();
+        for name in names {
+            self.start(&name);
+        }
+    }
+    /// Start the polling task of a client. A running task of the client is replaced
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Pollers struct
+    /// * `name` - The name of the client
+    pub fn start(&mut self, name: &str) {
+        self.stop(name);
+        log::debug!("Start polling client: {}", name);
+        let task = tokio::spawn(read_data(
+            name.to_string(),
+            self.registry.clone(),
+            self.clients.clone(),
+            self.connections.clone(),
+            self.intervall,
+        ));
+        self.tasks.insert(name.to_string(), task);
+    }
+    /// Stop the polling task of a client
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Pollers struct
+    /// * `name` - The name of the client
+    pub fn stop(&mut self, name: &str) {
+        if let Some(task) = self.tasks.remove(name) {
+            log::debug!("Stop polling client: {}", name);
+            task.abort();
+        }
+    }
+    /// Check if a client is polled
+    pub fn is_running(&self, name: &str) -> bool {
+        self.tasks.get(name).is_some_and(|task| !task.is_finished())
+    }
+}
+
+// Side thread for gathering data of one modbus client. The data is then stored in the prometheus Variables.
+// The clients are only locked to copy the config and to store the values, never while waiting for the device.
+// The task ends when the client is deleted
+pub async fn read_data(
+    name: String,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    clients: Arc<Mutex<Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    intervall: u64,
+) {
+    let mut schedule = Schedule::new(intervall);
     loop {
-        read_data_interval.tick().await;
-        for client in clients.lock().await.clients.values_mut() {
-            log::debug!(
-                "Reading from client: {} with IP address: {} on port: {}",
+        let mut client = match clients.lock().await.clients.get(&name) {
+            Some(client) => client.clone(),
+            None => {
+                log::debug!("Client: {} was removed. Stop polling", &name);
+                return;
+            }
+        };
+        // Only the due registers and coils are read. Items which are due at the same time are read together
+        let mut due_client = schedule.take_due(&client, Instant::now());
+        if !due_client.registers.is_empty() || !due_client.coils.is_empty() {
+            let connection = connections.lock().await.get_connection(&name);
+            let start = Instant::now();
+            let up = read_client(&mut due_client, &registry, &mut *connection.lock().await).await;
+            registry
+                .lock()
+                .await
+                .exporter
+                .observe_poll(&name, start.elapsed(), up);
+            // Store the read values in the shared client
+            if let Some(shared_client) = clients.lock().await.clients.get_mut(&name) {
+                store_values(shared_client, &due_client);
+                client = shared_client.clone();
+            }
+        }
+        tokio::time::sleep_until(schedule.next_cycle(&client, Instant::now())).await;
+    }
+}
+
+/// Read registers and coils of a client at once, outside of its poll schedule
+///
+/// The values are stored in the prometheus registry and in the shared client, just like the values of the poller.
+///
+/// # Arguments
+///
+/// * `client` - Copy of the client with only the registers and coils to read. Gets the read values
+/// * `registry` - The prometheus registry
+/// * `clients` - The shared clients
+/// * `connections` - The modbus connections, shared with the poller
+///
+/// # Returns
+///
+/// * `Result<(), ErrorRuntimeNoRejection>` - Error if the client could not be connected. Failed reads of single
+///   registers or coils are counted in their failed reads
+pub async fn read_now(
+    client: &mut Client,
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    clients: &Arc<Mutex<Clients>>,
+    connections: &Arc<Mutex<ConnectionManager>>,
+) -> Result<(), ErrorRuntimeNoRejection> {
+    let connection = connections.lock().await.get_connection(&client.name);
+    {
+        let mut connection = connection.lock().await;
+        connection.connect(client).await?;
+        read_client(client, registry, &mut connection).await;
+    }
+    if let Some(shared_client) = clients.lock().await.clients.get_mut(&client.name) {
+        store_values(shared_client, client);
+    }
+    Ok(())
+}
+
+// Read all registers and coils of one client and update the prometheus registry.
+// Returns true if the client was reachable. Modbus exceptions are answers of the device, so they do not count as down
+async fn read_client(
+    client: &mut Client,
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    connection: &mut Connection,
+) -> bool {
+    let endpoint = client.get_endpoint();
+    let stale_policy = client.get_stale_policy();
+    log::debug!(
+        "Reading from client: {} on {} via {}",
+        &client.name,
+        &endpoint,
+        &client.protocol
+    );
+    if let Err(e) = connection.connect(client).await {
+        log::error!(
+            "Could not connect to modbus client: {}. Skip reading from this modbus client. Error: {:?}",
+            &endpoint,
+            e
+        );
+        for register in client.registers.iter_mut() {
+            fail_read(
+                registry,
                 &client.name,
-                &client.ip_address,
-                &client.port
-            );
-            let mut ctx = match utils::create_ctx(client).await {
-                Ok(ctx) => ctx,
-                Err(e) => {
+                &stale_policy,
+                &register.name,
+                &mut register.failed_reads,
+                register.last_updated,
+                e.get_kind(),
+            )
+            .await;
+        }
+        for coil in client.coils.iter_mut() {
+            fail_read(
+                registry,
+                &client.name,
+                &stale_policy,
+                &coil.name,
+                &mut coil.failed_reads,
+                coil.last_updated,
+                e.get_kind(),
+            )
+            .await;
+        }
+        return false;
+    }
+    let mut up = true;
+    let client_byteorder = client.get_byteorder();
+    // Read all registers from the client in as few requests as possible. Depending on the register objecttype
+    for block in read_plan::plan_register_reads(client) {
+        log::debug!(
+            "Reading {} registers: {} to {} from {}",
+            &block.objecttype,
+            block.address,
+            block.address as u32 + block.quantity as u32 - 1,
+            &client.name
+        );
+        // Registers can override the unit id of the client
+        let data = connection
+            .read_registers(
+                block.unit_id,
+                &block.objecttype,
+                block.address,
+                block.quantity,
+            )
+            .await;
+        let data_to_write: Vec<u16> = match data {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!(
+                    "Could not read data from modbus client: {} on {} registers: {} to {}. Skip reading from these registers. Error: {:?}",
+                    &endpoint,
+                    &block.objecttype,
+                    block.address,
+                    block.address as u32 + block.quantity as u32 - 1,
+                    e
+                );
+                for index in block.items.iter() {
+                    let item = &mut client.registers[*index];
+                    fail_read(
+                        registry,
+                        &client.name,
+                        &stale_policy,
+                        &item.name,
+                        &mut item.failed_reads,
+                        item.last_updated,
+                        e.get_kind(),
+                    )
+                    .await;
+                }
+                up &= matches!(e, ErrorRuntimeNoRejection::ModbusException);
+                continue;
+            }
+        };
+        // Split the data of the block back onto the registers
+        for index in block.items.iter() {
+            let register = &mut client.registers[*index];
+            let words = match block.slice(&data_to_write, register.address, register.length) {
+                Some(words) => words,
+                None => {
                     log::error!(
-                        "Could not connect to modbus client: {}. Skip reading from this modbus client. Error: {:?}",
-                        &client.ip_address,
-                        e
+                        "Not enough data from modbus client: {} for register: {}_{}. Skip writing to prometheus registry",
+                        &endpoint,
+                        client.name,
+                        register.name
                     );
+                    fail_read(
+                        registry,
+                        &client.name,
+                        &stale_policy,
+                        &register.name,
+                        &mut register.failed_reads,
+                        register.last_updated,
+                        READ_ERROR_SHORT_RESPONSE,
+                    )
+                    .await;
                     continue;
                 }
             };
-            // Read all registers from the client. Depending on the register objecttype
-            for register in client.registers.iter_mut() {
-                let mut data_to_write: Vec<u16> = Vec::new();
-                // check if register is input or holding register
-                match register.objecttype.as_str() {
-                    "input" => {
-                        log::debug!(
-                            "Reading input register: {}_{}",
-                            &client.name,
-                            &register.name
-                        );
-                        data_to_write = match ctx
-                            .read_input_registers(register.address, register.length)
-                            .await
-                        {
-                            Ok(data) => data,
-                            Err(_) => {
-                                log::error!(
-                                    "Could not read data from modbus client: {} on register: {}. Skip reading from this register",
-                                    &client.ip_address,
-                                    &register.address
-                                );
-                                continue;
-                            }
-                        };
-                    }
-                    "holding" => {
-                        log::debug!(
-                            "Reading holding register: {}_{}",
-                            &client.name,
-                            &register.name
-                        );
-                        data_to_write = match ctx
-                            .read_holding_registers(register.address, register.length)
-                            .await
-                        {
-                            Ok(data) => data,
-                            Err(_) => {
-                                log::error!(
-                                    "Could not read data from modbus client: {} on register: {}. Skip reading from this register",
-                                    &client.ip_address,
-                                    &register.address
-                                );
-                                continue;
-                            }
-                        };
-                    }
-                    _ => {
-                        log::error!(
-                            "Invalid objecttype: {} for register: {}_{}. Skip reading from this register",
-                            &register.objecttype,
-                            &client.name,
-                            &register.name
-                        );
-                        continue;
-                    }
-                };
-                log::debug!(
-                    "Data: {:?} from {}_{}",
-                    data_to_write,
+            log::debug!("Data: {:?} from {}_{}", words, client.name, register.name);
+            register.set_value_from_words(words, &client_byteorder);
+            if !update_register_metrics(registry, &client.name, register).await {
+                log::error!(
+                    "Could not calculate final value for register: {}_{}. Skip writing to prometheus registry",
                     client.name,
                     register.name
                 );
-                register.value = data_to_write[0];
-                // Final value for registry is calculated by the register itself
-                let value_final = match register.calc_final_value_for_registry() {
-                    Ok(value) => value,
-                    Err(_) => {
-                        log::error!(
-                            "Could not calculate final value for register: {}_{}. Skip writing to prometheus registry",
-                            client.name,
-                            register.name
-                        );
-                        continue;
-                    }
-                };
-                registry
-                    .lock()
-                    .await
-                    .counters
-                    .get_mut(&format!("{}_{}", client.name, register.name))
-                    .unwrap()
-                    .set(value_final);
+                fail_read(
+                    registry,
+                    &client.name,
+                    &stale_policy,
+                    &register.name,
+                    &mut register.failed_reads,
+                    register.last_updated,
+                    READ_ERROR_DECODE,
+                )
+                .await;
+                continue;
             }
-            // Read all coils from the client. Depending on the objecttype
-            for coil in client.coils.iter_mut() {
-                let mut data_to_write: Vec<bool> = Vec::new();
-                // check if coil is coil or discrete
-                match coil.objecttype.as_str() {
-                    "coil" => {
-                        log::debug!(
-                            "Reading coil: {}_{}",
-                            &client.name,
-                            &coil.name
-                        );
-                        data_to_write = match ctx
-                            .read_coils(coil.address, 1)
-                            .await
-                        {
-                            Ok(data) => data,
-                            Err(_) => {
-                                log::error!(
-                                            "Could not read data from modbus client: {} on coil: {}. Skip reading from this coil",
-                                            &client.ip_address,
-                                            &coil.address
-                                        );
-                                continue;
-                            }
-                        };
-                    }
-                    "discrete" => {
-                        log::debug!(
-                            "Reading discrete input: {}_{}",
-                            &client.name,
-                            &coil.name
-                        );
-                        data_to_write = match ctx
-                            .read_discrete_inputs(coil.address, 1)
-                            .await
-                        {
-                            Ok(data) => data,
-                            Err(_) => {
-                                log::error!(
-                                            "Could not read data from modbus client: {} on discrete input: {}. Skip reading from this coil",
-                                            &client.ip_address,
-                                            &coil.address
-                                        );
-                                continue;
-                            }
-                        };
-                    }
-                    _ => {
-                        log::error!(
-                                    "Invalid objecttype: {} for coil: {}_{}. Skip reading from this coil",
-                                    &coil.objecttype,
-                                    &client.name,
-                                    &coil.name
-                                );
-                        continue;
-                    }
-                };
-                log::debug!(
-                    "Data: {:?} from {}_{}",
-                    data_to_write,
-                    client.name,
-                    coil.name
+            register.last_updated = Some(SystemTime::now());
+            register.failed_reads = 0;
+        }
+    }
+    // Read all coils from the client in as few requests as possible. Depending on the objecttype
+    for block in read_plan::plan_coil_reads(client) {
+        log::debug!(
+            "Reading {}s: {} to {} from {}",
+            &block.objecttype,
+            block.address,
+            block.address as u32 + block.quantity as u32 - 1,
+            &client.name
+        );
+        // Coils can override the unit id of the client
+        let data = connection
+            .read_coils(
+                block.unit_id,
+                &block.objecttype,
+                block.address,
+                block.quantity,
+            )
+            .await;
+        let data_to_write: Vec<bool> = match data {
+            Ok(data) => data,
+            Err(e) => {
+                log::error!(
+                    "Could not read data from modbus client: {} on {}s: {} to {}. Skip reading from these coils. Error: {:?}",
+                    &endpoint,
+                    &block.objecttype,
+                    block.address,
+                    block.address as u32 + block.quantity as u32 - 1,
+                    e
                 );
-                coil.value = data_to_write[0];
-
-                registry
-                    .lock()
-                    .await
-                    .counters
-                    .get_mut(&format!("{}_{}", client.name, coil.name))
-                    .unwrap()
-                    .set(convert_bool_to_f64(data_to_write[0]));
+                for index in block.items.iter() {
+                    let item = &mut client.coils[*index];
+                    fail_read(
+                        registry,
+                        &client.name,
+                        &stale_policy,
+                        &item.name,
+                        &mut item.failed_reads,
+                        item.last_updated,
+                        e.get_kind(),
+                    )
+                    .await;
+                }
+                up &= matches!(e, ErrorRuntimeNoRejection::ModbusException);
+                continue;
+            }
+        };
+        // Split the data of the block back onto the coils
+        for index in block.items.iter() {
+            let coil = &mut client.coils[*index];
+            let value = match block.slice(&data_to_write, coil.address, 1) {
+                Some(value) => value[0],
+                None => {
+                    log::error!(
+                        "Not enough data from modbus client: {} for coil: {}_{}. Skip writing to prometheus registry",
+                        &endpoint,
+                        client.name,
+                        coil.name
+                    );
+                    fail_read(
+                        registry,
+                        &client.name,
+                        &stale_policy,
+                        &coil.name,
+                        &mut coil.failed_reads,
+                        coil.last_updated,
+                        READ_ERROR_SHORT_RESPONSE,
+                    )
+                    .await;
+                    continue;
+                }
+            };
+            log::debug!("Data: {:?} from {}_{}", value, client.name, coil.name);
+            coil.value = value;
+
+            registry.lock().await.update_gauge(
+                &format!("{}_{}", client.name, coil.name),
+                convert_bool_to_f64(value),
+            );
+            coil.last_updated = Some(SystemTime::now());
+            coil.failed_reads = 0;
+        }
+    }
+    up
+}
+
+// Update the metrics of a register with its new value. Strings are no numbers, their text is a label of
+// the device info metric. Returns false if the value can not be decoded
+async fn update_register_metrics(
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    client_name: &str,
+    register: &Register,
+) -> bool {
+    if let Some(text) = &register.text {
+        registry
+            .lock()
+            .await
+            .update_info(client_name, &register.name, text);
+        return true;
+    }
+    // Final value for registry is calculated by the register itself
+    let value_final = match register.calc_final_value_for_registry() {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    // The gauge is gone if the client was deleted while reading
+    let mut registry = registry.lock().await;
+    registry.update_gauge(&format!("{}_{}", client_name, register.name), value_final);
+    registry.update_state(
+        &format!("{}_{}", client_name, register.name),
+        register.get_integer_value(),
+    );
+    // Bitfields are taken from the raw value
+    for bitfield in register.bitfields.iter().flatten() {
+        registry.update_gauge(
+            &format!("{}_{}_{}", client_name, register.name, bitfield.name),
+            bitfield.get_value(register.value) as f64,
+        );
+    }
+    true
+}
+
+/// Store registers and coils which were read back after a write
+///
+/// The metrics and the values of the shared client are updated right away, so the routes and /metrics
+/// show the value of the device before the next poll.
+///
+/// # Arguments
+///
+/// * `client_name` - The name of the client
+/// * `registers` - The registers with the values read back
+/// * `coils` - The coils with the values read back
+/// * `registry` - The prometheus registry
+/// * `clients` - The shared clients
+pub async fn store_read_back(
+    client_name: &str,
+    registers: &[Register],
+    coils: &[Coil],
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    clients: &Arc<Mutex<Clients>>,
+) {
+    let now = SystemTime::now();
+    let mut stored = Vec::new();
+    for register in registers {
+        if update_register_metrics(registry, client_name, register).await {
+            stored.push(register);
+        }
+    }
+    for coil in coils {
+        registry.lock().await.update_gauge(
+            &format!("{}_{}", client_name, coil.name),
+            convert_bool_to_f64(coil.value),
+        );
+    }
+    let mut clients = clients.lock().await;
+    let Some(shared_client) = clients.clients.get_mut(client_name) else {
+        return;
+    };
+    for register in shared_client.registers.iter_mut() {
+        if let Some(read_register) = stored.iter().find(|read| read.name == register.name) {
+            register.value = read_register.value;
+            register.state = read_register.state.clone();
+            register.text = read_register.text.clone();
+            register.words = read_register.words.clone();
+            register.last_updated = Some(now);
+            register.failed_reads = 0;
+        }
+    }
+    for coil in shared_client.coils.iter_mut() {
+        if let Some(read_coil) = coils.iter().find(|read| read.name == coil.name) {
+            coil.value = read_coil.value;
+            coil.last_updated = Some(now);
+            coil.failed_reads = 0;
+        }
+    }
+}
+
+// Count a failed read of a register or coil. Its metric is marked as stale if the stale policy of the client says so
+async fn fail_read(
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    client_name: &str,
+    stale_policy: &StalePolicy,
+    name: &str,
+    failed_reads: &mut u32,
+    last_updated: Option<SystemTime>,
+    kind: &str,
+) {
+    *failed_reads += 1;
+    let mut registry = registry.lock().await;
+    registry.exporter.inc_read_error(client_name, name, kind);
+    if stale_policy.is_stale(*failed_reads, last_updated, SystemTime::now()) {
+        registry.mark_stale(&format!("{}_{}", client_name, name), &stale_policy.policy);
+    }
+}
+
+// Copy the values of the registers and coils to the shared client. The config of the shared client
+// may have changed while reading, so the items are matched by name. Only values which are newer than
+// the values of the shared client are copied. The failed reads are always copied, because all items were read
+fn store_values(shared_client: &mut Client, client: &Client) {
+    for register in shared_client.registers.iter_mut() {
+        if let Some(read_register) = client.get_register_by_name(&register.name) {
+            register.failed_reads = read_register.failed_reads;
+            if read_register.last_updated > register.last_updated {
+                register.value = read_register.value;
+                register.state = read_register.state.clone();
+                register.text = read_register.text.clone();
+                register.words = read_register.words.clone();
+                register.last_updated = read_register.last_updated;
+            }
+        }
+    }
+    for coil in shared_client.coils.iter_mut() {
+        if let Some(read_coil) = client.get_coil_by_name(&coil.name) {
+            coil.failed_reads = read_coil.failed_reads;
+            if read_coil.last_updated > coil.last_updated {
+                coil.value = read_coil.value;
+                coil.last_updated = read_coil.last_updated;
             }
-            ctx.disconnect().await.unwrap();
         }
     }
 }
@@ -197,4 +541,172 @@ fn convert_bool_to_f64(input: bool) -> f64 {
         true => 1.0,
         false => 0.0,
     }
-}
\ No newline at end of file
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_read_data {
+    use super::*;
+    use crate::clients::{Coil, Register};
+    use crate::connections::RequestPolicy;
+    use crate::utils::simulator::*;
+    use prometheus::Encoder;
+    use std::time::Duration;
+
+    fn polled_client(name: &str, device: &str) -> Client {
+        let mut client = rtu_client(device, "");
+        client.name = name.to_string();
+        client.registers.push(Register {
+            name: "setpoint".to_string(),
+            objecttype: "holding".to_string(),
+            address: 7,
+            length: 1,
+            datatype: "uint16".to_string(),
+            ..Default::default()
+        });
+        client.coils.push(Coil {
+            name: "alarm".to_string(),
+            objecttype: "discrete".to_string(),
+            address: 1,
+            ..Default::default()
+        });
+        client
+    }
+
+    async fn setup(
+        clients_to_poll: Vec<Client>,
+    ) -> (Pollers, Arc<Mutex<PrometheusMetrics>>, Arc<Mutex<Clients>>) {
+        let registry = Arc::new(Mutex::new(PrometheusMetrics::new(
+            crate::prometheus::METRICS_MODE_LABELED,
+            false,
+        )));
+        let clients = Arc::new(Mutex::new(Clients::new("")));
+        for client in clients_to_poll {
+            registry.lock().await.register_client(&client).unwrap();
+            clients.lock().await.add_client(client.name.clone(), client);
+        }
+        let connections = Arc::new(Mutex::new(ConnectionManager::new(RequestPolicy::default())));
+        let pollers = Pollers::new(registry.clone(), clients.clone(), connections, 10);
+        (pollers, registry, clients)
+    }
+
+    #[test]
+    fn test_store_values_matches_by_name() {
+        let mut shared_client = polled_client("shared", "/dev/null");
+        let mut client = polled_client("shared", "/dev/null");
+        client.registers[0].value = 42;
+        client.registers[0].last_updated = Some(SystemTime::now());
+        client.coils[0].value = true;
+        client.coils[0].last_updated = Some(SystemTime::now());
+        // A register which was removed from the shared client while reading is ignored
+        client.registers.insert(
+            0,
+            Register {
+                name: "removed".to_string(),
+                value: 1,
+                ..Default::default()
+            },
+        );
+        store_values(&mut shared_client, &client);
+        assert_eq!(shared_client.registers[0].value, 42);
+        assert!(shared_client.coils[0].value);
+        // Values which were not read are not copied
+        client.registers[1].value = 0;
+        client.registers[1].last_updated = None;
+        store_values(&mut shared_client, &client);
+        assert_eq!(shared_client.registers[0].value, 42);
+    }
+
+    #[tokio::test]
+    async fn test_poller_reads_once() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let mut client = polled_client("live_client", &device);
+        client.coils.clear();
+        client.registers[0].interval_ms = Some(crate::clients::schedule::READ_ONCE);
+        let (mut pollers, _, clients) = setup(vec![client]).await;
+        pollers.start("live_client");
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(simulated_slave.memory.lock().unwrap().requests, 1);
+        let clients = clients.lock().await;
+        assert!(clients.clients["live_client"].registers[0]
+            .last_updated
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_pollers_poll_clients_independently() {
+        let (_slave, device, _) = spawn_rtu_simulated_slave();
+        let (mut pollers, registry, clients) = setup(vec![
+            polled_client("dead_client", "/dev/does_not_exist"),
+            polled_client("live_client", &device),
+        ])
+        .await;
+        pollers.start_all().await;
+        assert!(pollers.is_running("dead_client"));
+        assert!(pollers.is_running("live_client"));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let registry = registry.lock().await;
+        assert_eq!(registry.counters["live_client_setpoint"].get(), 7.0);
+        assert_eq!(registry.counters["live_client_alarm"].get(), 1.0);
+        assert_eq!(registry.counters["dead_client_setpoint"].get(), 0.0);
+        let mut buffer = Vec::new();
+        prometheus::TextEncoder::new()
+            .encode(&registry.registry.gather(), &mut buffer)
+            .unwrap();
+        let text = String::from_utf8(buffer).unwrap();
+        assert!(text.contains(r#"modbus_client_up{client="dead_client"} 0"#));
+        assert!(text.contains(r#"modbus_client_up{client="live_client"} 1"#));
+        assert!(text.contains(
+            r#"modbus_read_errors_total{client="dead_client",kind="connect",register="setpoint"}"#
+        ));
+        assert!(!text.contains(r#"modbus_read_errors_total{client="live_client""#));
+        assert!(text.contains(r#"modbus_last_success_timestamp{client="live_client"}"#));
+        assert!(!text.contains(r#"modbus_last_success_timestamp{client="dead_client"}"#));
+        let clients = clients.lock().await;
+        assert_eq!(clients.clients["live_client"].registers[0].value, 7);
+        assert!(clients.clients["live_client"].coils[0].value);
+    }
+
+    #[tokio::test]
+    async fn test_poller_marks_stale_values() {
+        let mut client = polled_client("dead_client", "/dev/does_not_exist");
+        client.stale_policy = Some(crate::clients::staleness::STALE_POLICY_NAN.to_string());
+        client.stale_after_cycles = Some(2);
+        let (mut pollers, registry, clients) = setup(vec![client]).await;
+        registry
+            .lock()
+            .await
+            .update_gauge("dead_client_setpoint", 7.0);
+        pollers.start("dead_client");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(registry.lock().await.counters["dead_client_setpoint"]
+            .get()
+            .is_nan());
+        assert!(clients.lock().await.clients["dead_client"].coils[0].failed_reads >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_pollers_stop() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (mut pollers, _, _) = setup(vec![polled_client("live_client", &device)]).await;
+        pollers.start("live_client");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        pollers.stop("live_client");
+        assert!(!pollers.is_running("live_client"));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let requests = simulated_slave.memory.lock().unwrap().requests;
+        assert!(requests > 0);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(simulated_slave.memory.lock().unwrap().requests, requests);
+    }
+
+    #[tokio::test]
+    async fn test_poller_ends_when_client_is_removed() {
+        let (mut pollers, _, clients) =
+            setup(vec![polled_client("dead_client", "/dev/does_not_exist")]).await;
+        pollers.start("dead_client");
+        clients.lock().await.delete_client("dead_client");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!pollers.is_running("dead_client"));
+    }
+}
@@ -0,0 +1,211 @@
+use super::Client;
+
+/// Maximum number of registers per read request as defined by the modbus spec
+pub const MAX_READ_REGISTERS: u16 = 125;
+/// Maximum number of coils or discrete inputs per read request as defined by the modbus spec
+pub const MAX_READ_COILS: u16 = 2000;
+
+/// One read request covering several registers or coils of the same objecttype and unit id
+#[derive(Debug, PartialEq)]
+pub struct ReadBlock {
+    pub objecttype: String,
+    pub unit_id: u8,
+    pub address: u16,
+    pub quantity: u16,
+    /// Indices of the registers or coils of the client which are covered by this block
+    pub items: Vec<usize>,
+}
+impl ReadBlock {
+    /// Get the part of the read data which belongs to one register or coil of the block
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The ReadBlock struct
+    /// * `data` - The data read for the whole block
+    /// * `address` - The address of the register or coil
+    /// * `length` - The number of words (registers) or bits (coils) of the item
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&[T]>` - The data of the item. None if the device returned less data than requested
+    pub fn slice<'a, T>(&self, data: &'a [T], address: u16, length: u16) -> Option<&'a [T]> {
+        let offset = address.checked_sub(self.address)? as usize;
+        data.get(offset..offset + length as usize)
+    }
+}
+
+/// An item to plan: index in the client, objecttype, unit id, address and length
+type PlanItem<'a> = (usize, &'a str, u8, u16, u16);
+
+/// Plan the read requests for all registers of a client
+///
+/// Registers with the same objecttype and unit id are grouped into as few requests as possible.
+/// Two registers are read in one request if the gap between them is not bigger than the max gap of the client
+/// and the request does not exceed 125 registers.
+///
+/// # Arguments
+///
+/// * `client` - The client to plan the reads for
+///
+/// # Returns
+///
+/// * `Vec<ReadBlock>` - The read requests
+pub fn plan_register_reads(client: &Client) -> Vec<ReadBlock> {
+    let items = client
+        .registers
+        .iter()
+        .enumerate()
+        .map(|(index, register)| {
+            (
+                index,
+                register.objecttype.as_str(),
+                client.resolve_unit_id(register.unit_id),
+                register.address,
+                register.length,
+            )
+        })
+        .collect();
+    plan(items, client.get_max_gap(), MAX_READ_REGISTERS)
+}
+
+/// Plan the read requests for all coils and discrete inputs of a client
+///
+/// Same as plan_register_reads, but a request can hold up to 2000 coils.
+///
+/// # Arguments
+///
+/// * `client` - The client to plan the reads for
+///
+/// # Returns
+///
+/// * `Vec<ReadBlock>` - The read requests
+pub fn plan_coil_reads(client: &Client) -> Vec<ReadBlock> {
+    let items = client
+        .coils
+        .iter()
+        .enumerate()
+        .map(|(index, coil)| {
+            (
+                index,
+                coil.objecttype.as_str(),
+                client.resolve_unit_id(coil.unit_id),
+                coil.address,
+                1,
+            )
+        })
+        .collect();
+    plan(items, client.get_max_gap(), MAX_READ_COILS)
+}
+
+fn plan(mut items: Vec<PlanItem>, max_gap: u16, max_quantity: u16) -> Vec<ReadBlock> {
+    // Items which can be read together are next to each other after sorting
+    items.sort_by_key(|(_, objecttype, unit_id, address, _)| (*objecttype, *unit_id, *address));
+    let mut blocks: Vec<ReadBlock> = Vec::new();
+    for (index, objecttype, unit_id, address, length) in items {
+        let item_end = address as u32 + length as u32;
+        if let Some(block) = blocks.last_mut() {
+            let block_end = block.address as u32 + block.quantity as u32;
+            let new_end = block_end.max(item_end);
+            if block.objecttype == objecttype
+                && block.unit_id == unit_id
+                && address as u32 <= block_end + max_gap as u32
+                && new_end - block.address as u32 <= max_quantity as u32
+            {
+                block.quantity = (new_end - block.address as u32) as u16;
+                block.items.push(index);
+                continue;
+            }
+        }
+        blocks.push(ReadBlock {
+            objecttype: objecttype.to_string(),
+            unit_id,
+            address,
+            quantity: length,
+            items: vec![index],
+        });
+    }
+    blocks
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_read_plan {
+    use super::*;
+
+    #[test]
+    fn test_plan_contiguous_registers_one_block() {
+        let items = vec![
+            (0, "holding", 1, 2, 2),
+            (1, "holding", 1, 0, 2),
+            (2, "holding", 1, 4, 1),
+        ];
+        let blocks = plan(items, 0, MAX_READ_REGISTERS);
+        assert_eq!(
+            blocks,
+            vec![ReadBlock {
+                objecttype: "holding".to_string(),
+                unit_id: 1,
+                address: 0,
+                quantity: 5,
+                items: vec![1, 0, 2],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_respects_max_gap() {
+        let items = vec![(0, "holding", 1, 0, 1), (1, "holding", 1, 4, 1)];
+        assert_eq!(plan(items.clone(), 2, MAX_READ_REGISTERS).len(), 2);
+        let blocks = plan(items, 3, MAX_READ_REGISTERS);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].quantity, 5);
+    }
+
+    #[test]
+    fn test_plan_separates_objecttypes_and_unit_ids() {
+        let items = vec![
+            (0, "holding", 1, 0, 1),
+            (1, "input", 1, 1, 1),
+            (2, "holding", 2, 1, 1),
+            (3, "holding", 1, 1, 1),
+        ];
+        let blocks = plan(items, 0, MAX_READ_REGISTERS);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[0].items, vec![0, 3]);
+        assert_eq!(blocks[1].items, vec![2]);
+        assert_eq!(blocks[2].items, vec![1]);
+    }
+
+    #[test]
+    fn test_plan_respects_max_quantity() {
+        let items = (0..130).map(|i| (i as usize, "input", 1, i, 1)).collect();
+        let blocks = plan(items, 0, MAX_READ_REGISTERS);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].quantity, 125);
+        assert_eq!(blocks[1].address, 125);
+        assert_eq!(blocks[1].quantity, 5);
+    }
+
+    #[test]
+    fn test_plan_overlapping_registers() {
+        let items = vec![(0, "holding", 1, 0, 4), (1, "holding", 1, 2, 1)];
+        let blocks = plan(items, 0, MAX_READ_REGISTERS);
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(blocks[0].quantity, 4);
+    }
+
+    #[test]
+    fn test_read_block_slice() {
+        let block = ReadBlock {
+            objecttype: "holding".to_string(),
+            unit_id: 1,
+            address: 10,
+            quantity: 4,
+            items: vec![0, 1],
+        };
+        let data = [1_u16, 2, 3, 4];
+        assert_eq!(block.slice(&data, 11, 2), Some(&data[1..3]));
+        assert_eq!(block.slice(&data, 13, 2), None);
+        assert_eq!(block.slice(&data, 9, 1), None);
+    }
+}
@@ -0,0 +1,256 @@
+use super::Client;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Interval of registers and coils which are only read once, e.g. nameplate data.
+/// They are read again in every cycle until the first read succeeded
+pub const READ_ONCE: u64 = 0;
+
+/// Schedule struct
+///
+/// Decides which registers and coils of a client are due in a poll cycle. Every item is polled with its own
+/// interval, which falls back to the interval of the client and then to the global interval.
+/// All items which are due at the same time are returned together, so they can still be read in blocks.
+///
+pub struct Schedule {
+    global_interval_ms: u64,
+    /// Next due time per interval in ms
+    next_due: HashMap<u64, Instant>,
+}
+impl Schedule {
+    /// Create a new Schedule struct. All items are due in the first cycle
+    ///
+    /// # Arguments
+    ///
+    /// * `global_interval_ms` - The interval for clients and items which do not set their own
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new Schedule struct
+    pub fn new(global_interval_ms: u64) -> Self {
+        Self {
+            global_interval_ms,
+            next_due: HashMap::new(),
+        }
+    }
+    /// Get the interval of the client. Used for items without their own interval
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Schedule struct
+    /// * `client` - The client
+    ///
+    /// # Returns
+    ///
+    /// * `u64` - The interval in ms
+    pub fn get_client_interval_ms(&self, client: &Client) -> u64 {
+        client.interval_ms.unwrap_or(self.global_interval_ms)
+    }
+    /// Get the registers and coils which are due and move the due time of their intervals forward
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Schedule struct
+    /// * `client` - The client with all registers and coils
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// * `Client` - A copy of the client which only contains the due registers and coils
+    pub fn take_due(&mut self, client: &Client, now: Instant) -> Client {
+        let client_interval = self.get_client_interval_ms(client);
+        let due_intervals: HashSet<u64> = self
+            .get_intervals(client)
+            .into_iter()
+            .filter(|interval| *interval != READ_ONCE)
+            .filter(|interval| self.next_due.get(interval).is_none_or(|due| *due <= now))
+            .collect();
+        for interval in due_intervals.iter() {
+            // Keep the cadence, but do not catch up cycles which were missed by a slow device
+            let next = match self.next_due.get(interval) {
+                Some(due) if *due + Duration::from_millis(*interval) > now => {
+                    *due + Duration::from_millis(*interval)
+                }
+                _ => now + Duration::from_millis(*interval),
+            };
+            self.next_due.insert(*interval, next);
+        }
+        let is_due =
+            |interval: Option<u64>, last_updated: bool| match interval.unwrap_or(client_interval) {
+                READ_ONCE => !last_updated,
+                interval => due_intervals.contains(&interval),
+            };
+        let mut due_client = client.clone();
+        due_client
+            .registers
+            .retain(|register| is_due(register.interval_ms, register.last_updated.is_some()));
+        due_client
+            .coils
+            .retain(|coil| is_due(coil.interval_ms, coil.last_updated.is_some()));
+        due_client
+    }
+    /// Get the time of the next cycle
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Schedule struct
+    /// * `client` - The client with all registers and coils
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// * `Instant` - The earliest due time of all intervals of the client. Items which should be read once, but were
+    ///   not read yet, are retried with the interval of the client. Without any due item the client is checked again
+    ///   after the interval of the client. Clients which are only read once use the global interval for both
+    pub fn next_cycle(&self, client: &Client, now: Instant) -> Instant {
+        let retry_interval = match self.get_client_interval_ms(client) {
+            READ_ONCE => self.global_interval_ms,
+            interval => interval,
+        };
+        let retry = now + Duration::from_millis(retry_interval);
+        self.get_intervals(client)
+            .iter()
+            .filter(|interval| **interval != READ_ONCE)
+            .filter_map(|interval| self.next_due.get(interval).copied())
+            .min()
+            .map_or(retry, |due| due.min(retry))
+    }
+
+    // All intervals which are used by the registers and coils of the client
+    fn get_intervals(&self, client: &Client) -> HashSet<u64> {
+        let client_interval = self.get_client_interval_ms(client);
+        client
+            .registers
+            .iter()
+            .map(|register| register.interval_ms)
+            .chain(client.coils.iter().map(|coil| coil.interval_ms))
+            .map(|interval| interval.unwrap_or(client_interval))
+            .collect()
+    }
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_schedule {
+    use super::*;
+    use crate::clients::{Coil, Register};
+    use std::time::SystemTime;
+
+    fn scheduled_client() -> Client {
+        let mut client = crate::utils::simulator::rtu_client("/dev/null", "");
+        for (name, interval_ms) in [
+            ("fast", Some(500)),
+            ("slow", Some(60_000)),
+            ("nameplate", Some(READ_ONCE)),
+        ] {
+            client.registers.push(Register {
+                name: name.to_string(),
+                interval_ms,
+                ..Default::default()
+            });
+        }
+        client.coils.push(Coil {
+            name: "default".to_string(),
+            ..Default::default()
+        });
+        client
+    }
+
+    fn names(client: &Client) -> Vec<&str> {
+        client
+            .registers
+            .iter()
+            .map(|register| register.name.as_str())
+            .chain(client.coils.iter().map(|coil| coil.name.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn test_everything_due_in_first_cycle() {
+        let mut schedule = Schedule::new(1000);
+        let client = scheduled_client();
+        let due = schedule.take_due(&client, Instant::now());
+        assert_eq!(names(&due), vec!["fast", "slow", "nameplate", "default"]);
+    }
+
+    #[test]
+    fn test_items_due_on_their_own_cadence() {
+        let mut schedule = Schedule::new(1000);
+        let mut client = scheduled_client();
+        let start = Instant::now();
+        schedule.take_due(&client, start);
+        client.registers[2].last_updated = Some(SystemTime::now());
+        assert_eq!(
+            schedule.next_cycle(&client, start),
+            start + Duration::from_millis(500)
+        );
+        let due = schedule.take_due(&client, start + Duration::from_millis(500));
+        assert_eq!(names(&due), vec!["fast"]);
+        let due = schedule.take_due(&client, start + Duration::from_millis(1000));
+        assert_eq!(names(&due), vec!["fast", "default"]);
+        let due = schedule.take_due(&client, start + Duration::from_millis(1200));
+        assert!(names(&due).is_empty());
+        let due = schedule.take_due(&client, start + Duration::from_millis(60_000));
+        assert_eq!(names(&due), vec!["fast", "slow", "default"]);
+    }
+
+    #[test]
+    fn test_read_once_retried_until_read() {
+        let mut schedule = Schedule::new(1000);
+        let mut client = scheduled_client();
+        let start = Instant::now();
+        schedule.take_due(&client, start);
+        let due = schedule.take_due(&client, start + Duration::from_millis(500));
+        assert_eq!(names(&due), vec!["fast", "nameplate"]);
+        client.registers[2].last_updated = Some(SystemTime::now());
+        let due = schedule.take_due(&client, start + Duration::from_millis(1000));
+        assert_eq!(names(&due), vec!["fast", "default"]);
+    }
+
+    #[test]
+    fn test_missed_cycles_are_not_caught_up() {
+        let mut schedule = Schedule::new(1000);
+        let client = scheduled_client();
+        let start = Instant::now();
+        schedule.take_due(&client, start);
+        let late = start + Duration::from_millis(3300);
+        schedule.take_due(&client, late);
+        assert_eq!(
+            schedule.next_cycle(&client, late),
+            late + Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn test_client_interval_used_as_default() {
+        let mut schedule = Schedule::new(1000);
+        let mut client = scheduled_client();
+        client.interval_ms = Some(200);
+        let start = Instant::now();
+        schedule.take_due(&client, start);
+        let due = schedule.take_due(&client, start + Duration::from_millis(200));
+        assert_eq!(names(&due), vec!["nameplate", "default"]);
+    }
+
+    #[test]
+    fn test_read_once_client_retried_after_global_interval() {
+        let mut schedule = Schedule::new(1000);
+        let mut client = crate::utils::simulator::rtu_client("/dev/null", "");
+        client.interval_ms = Some(READ_ONCE);
+        client.registers.push(Register {
+            name: "serial_number".to_string(),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let due = schedule.take_due(&client, start);
+        assert_eq!(names(&due), vec!["serial_number"]);
+        // The read failed, so the register is retried without polling the device in a busy loop
+        assert_eq!(
+            schedule.next_cycle(&client, start),
+            start + Duration::from_millis(1000)
+        );
+        let due = schedule.take_due(&client, start + Duration::from_millis(1000));
+        assert_eq!(names(&due), vec!["serial_number"]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::time::{Duration, SystemTime};
+
+/// Stale values stay in the exposition with their last value
+pub const STALE_POLICY_KEEP: &str = "keep";
+/// Stale values are set to NaN
+pub const STALE_POLICY_NAN: &str = "nan";
+/// Stale values are removed from the exposition until the next successful read
+pub const STALE_POLICY_REMOVE: &str = "remove";
+/// Number of failed reads after which a value is stale, if neither a number nor a max age is configured
+pub const DEFAULT_STALE_AFTER_CYCLES: u32 = 1;
+
+/// StalePolicy struct
+///
+/// Decides when the value of a register or coil is stale and what happens with its metric then.
+/// A value is stale after a number of failed reads in a row or if its last successful read is older than a max age.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct StalePolicy {
+    /// keep, nan or remove
+    pub policy: String,
+    pub after_cycles: Option<u32>,
+    pub max_age: Option<Duration>,
+}
+impl Default for StalePolicy {
+    fn default() -> Self {
+        Self {
+            policy: STALE_POLICY_KEEP.to_string(),
+            after_cycles: None,
+            max_age: None,
+        }
+    }
+}
+impl StalePolicy {
+    /// Check if a policy is supported
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The name of the policy
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the policy is keep, nan or remove
+    pub fn is_policy_supported(policy: &str) -> bool {
+        [STALE_POLICY_KEEP, STALE_POLICY_NAN, STALE_POLICY_REMOVE].contains(&policy)
+    }
+    /// Check if the value of a register or coil is stale
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The StalePolicy struct
+    /// * `failed_reads` - The number of failed reads since the last successful read
+    /// * `last_updated` - The time of the last successful read
+    /// * `now` - The current time
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the value is stale. Without a configured number of reads or max age, the value is stale
+    ///   after the first failed read
+    pub fn is_stale(
+        &self,
+        failed_reads: u32,
+        last_updated: Option<SystemTime>,
+        now: SystemTime,
+    ) -> bool {
+        let after_cycles = match (self.after_cycles, self.max_age) {
+            (None, None) => Some(DEFAULT_STALE_AFTER_CYCLES),
+            (after_cycles, _) => after_cycles,
+        };
+        let too_many_failures = after_cycles.is_some_and(|cycles| failed_reads >= cycles);
+        let too_old = self.max_age.is_some_and(|max_age| match last_updated {
+            Some(last_updated) => now
+                .duration_since(last_updated)
+                .is_ok_and(|age| age > max_age),
+            None => true,
+        });
+        too_many_failures || too_old
+    }
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_staleness {
+    use super::*;
+
+    #[test]
+    fn test_stale_after_first_failure_by_default() {
+        let policy = StalePolicy::default();
+        let now = SystemTime::now();
+        assert!(!policy.is_stale(0, Some(now), now));
+        assert!(policy.is_stale(1, Some(now), now));
+    }
+
+    #[test]
+    fn test_stale_after_cycles_or_max_age() {
+        let policy = StalePolicy {
+            policy: STALE_POLICY_NAN.to_string(),
+            after_cycles: Some(3),
+            max_age: Some(Duration::from_secs(60)),
+        };
+        let now = SystemTime::now();
+        assert!(!policy.is_stale(2, Some(now - Duration::from_secs(30)), now));
+        assert!(policy.is_stale(3, Some(now - Duration::from_secs(30)), now));
+        assert!(policy.is_stale(1, Some(now - Duration::from_secs(61)), now));
+        // Values which were never read are too old
+        assert!(policy.is_stale(1, None, now));
+    }
+
+    #[test]
+    fn test_stale_only_by_max_age() {
+        let policy = StalePolicy {
+            policy: STALE_POLICY_REMOVE.to_string(),
+            after_cycles: None,
+            max_age: Some(Duration::from_secs(60)),
+        };
+        let now = SystemTime::now();
+        assert!(!policy.is_stale(10, Some(now - Duration::from_secs(30)), now));
+        assert!(policy.is_stale(10, Some(now - Duration::from_secs(90)), now));
+    }
+}
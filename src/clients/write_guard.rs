@@ -0,0 +1,401 @@
+use super::live_values::{CoilValue, RegisterValue, QUALITY_GOOD, QUALITY_UNCERTAIN};
+use super::{Client, Coil, Register};
+use crate::errors::impls::ErrorRuntime;
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use utoipa::ToSchema;
+
+/// Operators of interlocks
+pub const INTERLOCK_OPERATORS: [&str; 6] = ["<", "<=", ">", ">=", "==", "!="];
+
+/// Interlock struct
+///
+/// A condition on the live value of another register or coil of the same client. A register or coil with
+/// interlocks is only written if all of them hold, e.g. a pump may only be switched on below a tank level of 50:
+/// ```json
+/// { "register": "tank_level", "operator": "<", "value": 50 }
+/// ```
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, ToSchema)]
+pub struct Interlock {
+    /// Name of the register to check. Either register or coil must be set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub register: Option<String>,
+    /// Name of the coil to check. Coils are compared as 0 or 1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub coil: Option<String>,
+    /// <, <=, >, >=, == or !=
+    pub operator: String,
+    pub value: f64,
+}
+impl Interlock {
+    /// Check if the interlock can be evaluated for a client
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Interlock struct
+    /// * `client` - The client of the register or coil with the interlock
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the operator is supported and exactly one existing register or coil is checked.
+    ///   String registers have no value to compare
+    pub fn is_valid(&self, client: &Client) -> bool {
+        let item_exists = match (&self.register, &self.coil) {
+            (Some(register), None) => client
+                .get_register_by_name(register)
+                .is_some_and(|register| !register.is_string()),
+            (None, Some(coil)) => client.get_coil_by_name(coil).is_some(),
+            _ => false,
+        };
+        item_exists
+            && INTERLOCK_OPERATORS.contains(&self.operator.as_str())
+            && self.value.is_finite()
+    }
+    /// Check if the interlock holds for the live values of a client
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Interlock struct
+    /// * `client` - The client with the last polled values
+    /// * `now` - The current time, used to check if the value is stale
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - True if the condition holds. False if the checked value was not read yet or is stale
+    pub fn is_satisfied(&self, client: &Client, now: SystemTime) -> bool {
+        match self.get_live_value(client, now) {
+            Some(current) => match self.operator.as_str() {
+                "<" => current < self.value,
+                "<=" => current <= self.value,
+                ">" => current > self.value,
+                ">=" => current >= self.value,
+                "==" => current == self.value,
+                "!=" => current != self.value,
+                _ => false,
+            },
+            None => false,
+        }
+    }
+    // The value of the checked item as returned by GET /clients/{name}/registers. Only good and uncertain values count
+    fn get_live_value(&self, client: &Client, now: SystemTime) -> Option<f64> {
+        let is_usable = |quality: &str| quality == QUALITY_GOOD || quality == QUALITY_UNCERTAIN;
+        if let Some(register) = &self.register {
+            let value = RegisterValue::new(client, client.get_register_by_name(register)?, now);
+            return value.value.filter(|_| is_usable(&value.quality));
+        }
+        let value = CoilValue::new(client, client.get_coil_by_name(self.coil.as_ref()?)?, now);
+        match is_usable(&value.quality) {
+            true => value.value.map(|value| value as u8 as f64),
+            false => None,
+        }
+    }
+    // The condition for error messages, e.g. tank_level < 50
+    fn describe(&self) -> String {
+        format!(
+            "{} {} {}",
+            self.register
+                .as_ref()
+                .or(self.coil.as_ref())
+                .unwrap_or(&String::new()),
+            self.operator,
+            self.value
+        )
+    }
+}
+
+/// Check the write constraints of a register before its value is written
+///
+/// Limits apply to the value as given in the write request: the final value of registers with factor, gain or offset,
+/// the decoded value of all others and the value of the state for state names.
+///
+/// # Arguments
+///
+/// * `client` - The client with the last polled values, used for the interlocks
+/// * `register` - The register to write
+/// * `input` - The value of the write request. None for bitfields, which only write a part of the register
+/// * `confirmed` - True if the write request was confirmed
+///
+/// # Returns
+///
+/// * `Result<(), ErrorRuntime>` - The first violated constraint
+pub fn check_register_write(
+    client: &Client,
+    register: &Register,
+    input: Option<&str>,
+    confirmed: bool,
+) -> Result<(), ErrorRuntime> {
+    let name = || Some(register.name.clone());
+    if register.read_only == Some(true) {
+        return Err(ErrorRuntime::ClientItemReadOnly(name()));
+    }
+    if let Some(value) = input.and_then(|input| get_input_value(register, input)) {
+        if register.min.is_some_and(|min| value < min) {
+            return Err(ErrorRuntime::ClientValueBelowMin(name()));
+        }
+        if register.max.is_some_and(|max| value > max) {
+            return Err(ErrorRuntime::ClientValueAboveMax(name()));
+        }
+        if let Some(allowed_values) = &register.allowed_values {
+            if !allowed_values.contains(&value) {
+                return Err(ErrorRuntime::ClientValueNotAllowed(name()));
+            }
+        }
+    }
+    if register.requires_confirmation == Some(true) && !confirmed {
+        return Err(ErrorRuntime::ClientWriteNotConfirmed(name()));
+    }
+    check_interlocks(client, &register.name, register.interlocks.as_ref())
+}
+
+/// Check the write constraints of a coil before its value is written
+///
+/// # Arguments
+///
+/// * `client` - The client with the last polled values, used for the interlocks
+/// * `coil` - The coil to write
+/// * `value` - The value to write. Interlocks only block switching the coil on, switching it off is always allowed
+/// * `confirmed` - True if the write request was confirmed
+///
+/// # Returns
+///
+/// * `Result<(), ErrorRuntime>` - The first violated constraint
+pub fn check_coil_write(
+    client: &Client,
+    coil: &Coil,
+    value: bool,
+    confirmed: bool,
+) -> Result<(), ErrorRuntime> {
+    if coil.read_only == Some(true) {
+        return Err(ErrorRuntime::ClientItemReadOnly(Some(coil.name.clone())));
+    }
+    if coil.requires_confirmation == Some(true) && !confirmed {
+        return Err(ErrorRuntime::ClientWriteNotConfirmed(Some(
+            coil.name.clone(),
+        )));
+    }
+    match value {
+        true => check_interlocks(client, &coil.name, coil.interlocks.as_ref()),
+        false => Ok(()),
+    }
+}
+
+/// Apply the values of a bulk write on top of the last polled values of a client
+///
+/// The interlocks of a bulk write are checked against the returned client, so a register or coil and the value which
+/// guards it can not be written in one request to get around the interlock.
+///
+/// # Arguments
+///
+/// * `client` - The client with the last polled values
+/// * `registers` - The names and values of the registers to write, as given in the write request
+/// * `coils` - The names and values of the coils to write
+///
+/// # Returns
+///
+/// * `Client` - A copy of the client with the written values as current values. Values which can not be encoded
+///   are left out, they are rejected when the write is checked
+pub fn apply_pending_values<'a>(
+    client: &Client,
+    registers: impl Iterator<Item = (&'a str, &'a str)>,
+    coils: impl Iterator<Item = (&'a str, bool)>,
+) -> Client {
+    let now = SystemTime::now();
+    let byteorder = client.get_byteorder();
+    let mut pending = client.clone();
+    for (name, input) in registers {
+        let register = pending
+            .registers
+            .iter_mut()
+            .find(|register| register.name == name);
+        if let Some(register) = register {
+            if let Ok(words) = register.encode_input(input, &byteorder) {
+                register.set_value_from_words(&words, &byteorder);
+                register.last_updated = Some(now);
+                register.failed_reads = 0;
+            }
+        }
+    }
+    for (name, value) in coils {
+        if let Some(coil) = pending.coils.iter_mut().find(|coil| coil.name == name) {
+            coil.value = value;
+            coil.last_updated = Some(now);
+            coil.failed_reads = 0;
+        }
+    }
+    pending
+}
+
+fn check_interlocks(
+    client: &Client,
+    name: &str,
+    interlocks: Option<&Vec<Interlock>>,
+) -> Result<(), ErrorRuntime> {
+    let now = SystemTime::now();
+    match interlocks
+        .into_iter()
+        .flatten()
+        .find(|interlock| !interlock.is_satisfied(client, now))
+    {
+        Some(interlock) => Err(ErrorRuntime::ClientInterlockActive(Some(format!(
+            "{} requires {}",
+            name,
+            interlock.describe()
+        )))),
+        None => Ok(()),
+    }
+}
+
+// The number to compare with the limits. State names count as the value of their state
+fn get_input_value(register: &Register, input: &str) -> Option<f64> {
+    let state = register
+        .states
+        .iter()
+        .flatten()
+        .find(|(_, state)| state.as_str() == input);
+    match state {
+        Some((value, _)) => Some(*value as f64),
+        None => input.parse::<f64>().ok(),
+    }
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_write_guard {
+    use super::*;
+
+    fn client() -> Client {
+        Client::new(
+            r#"{
+                "name": "heat_pump",
+                "ip_address": "127.0.0.1",
+                "port": 502,
+                "protocol": "tcp",
+                "registers": [
+                    {
+                        "name": "setpoint",
+                        "objecttype": "holding",
+                        "address": 1,
+                        "length": 1,
+                        "datatype": "int16",
+                        "factor": 0,
+                        "value": 0,
+                        "gain": 0.1,
+                        "min": 10,
+                        "max": 60
+                    },
+                    {
+                        "name": "operating_mode",
+                        "objecttype": "holding",
+                        "address": 2,
+                        "length": 1,
+                        "datatype": "uint16",
+                        "factor": 0,
+                        "value": 0,
+                        "states": { "0": "off", "3": "heating", "7": "service" },
+                        "allowed_values": [0, 3],
+                        "requires_confirmation": true
+                    },
+                    {
+                        "name": "tank_level",
+                        "objecttype": "input",
+                        "address": 3,
+                        "length": 1,
+                        "datatype": "uint16",
+                        "factor": 0,
+                        "value": 0
+                    }
+                ],
+                "coils": [
+                    {
+                        "name": "pump",
+                        "objecttype": "coil",
+                        "address": 1,
+                        "value": false,
+                        "interlocks": [ { "register": "tank_level", "operator": "<", "value": 50 } ]
+                    },
+                    { "name": "reset", "objecttype": "coil", "address": 2, "value": false, "read_only": true }
+                ]
+            }"#
+            .to_string(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_register_limits() {
+        let client = client();
+        let setpoint = &client.registers[0];
+        assert!(check_register_write(&client, setpoint, Some("21.5"), false).is_ok());
+        assert!(matches!(
+            check_register_write(&client, setpoint, Some("5"), false),
+            Err(ErrorRuntime::ClientValueBelowMin(_))
+        ));
+        assert!(matches!(
+            check_register_write(&client, setpoint, Some("60.5"), false),
+            Err(ErrorRuntime::ClientValueAboveMax(_))
+        ));
+    }
+
+    #[test]
+    fn test_register_allowed_values_and_confirmation() {
+        let client = client();
+        let mode = &client.registers[1];
+        assert!(matches!(
+            check_register_write(&client, mode, Some("service"), true),
+            Err(ErrorRuntime::ClientValueNotAllowed(_))
+        ));
+        assert!(matches!(
+            check_register_write(&client, mode, Some("heating"), false),
+            Err(ErrorRuntime::ClientWriteNotConfirmed(_))
+        ));
+        assert!(check_register_write(&client, mode, Some("heating"), true).is_ok());
+        assert!(check_register_write(&client, mode, Some("3"), true).is_ok());
+    }
+
+    #[test]
+    fn test_coil_interlock() {
+        let mut client = client();
+        // The tank level was not read yet
+        let pump = client.coils[0].clone();
+        assert!(matches!(
+            check_coil_write(&client, &pump, true, false),
+            Err(ErrorRuntime::ClientInterlockActive(_))
+        ));
+        // Switching off is always allowed
+        assert!(check_coil_write(&client, &pump, false, false).is_ok());
+        client.registers[2].value = 42;
+        client.registers[2].last_updated = Some(SystemTime::now());
+        assert!(check_coil_write(&client, &pump, true, false).is_ok());
+        client.registers[2].value = 50;
+        assert!(check_coil_write(&client, &pump, true, false).is_err());
+        // Stale values do not satisfy an interlock
+        client.registers[2].value = 42;
+        client.registers[2].failed_reads = 1;
+        assert!(check_coil_write(&client, &pump, true, false).is_err());
+        assert!(matches!(
+            check_coil_write(&client, &client.coils[1], true, true),
+            Err(ErrorRuntime::ClientItemReadOnly(_))
+        ));
+    }
+
+    #[test]
+    fn test_interlock_is_valid() {
+        let client = client();
+        let interlock = Interlock {
+            coil: Some("pump".to_string()),
+            operator: "==".to_string(),
+            value: 1.0,
+            ..Default::default()
+        };
+        assert!(interlock.is_valid(&client));
+        let mut both = interlock.clone();
+        both.register = Some("tank_level".to_string());
+        assert!(!both.is_valid(&client));
+        let mut unknown_operator = interlock.clone();
+        unknown_operator.operator = "=~".to_string();
+        assert!(!unknown_operator.is_valid(&client));
+        let mut unknown_coil = interlock;
+        unknown_coil.coil = Some("fan".to_string());
+        assert!(!unknown_coil.is_valid(&client));
+    }
+}
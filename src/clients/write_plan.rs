@@ -0,0 +1,106 @@
+/// Maximum number of registers per write request (FC16) as defined by the modbus spec
+pub const MAX_WRITE_REGISTERS: u16 = 123;
+/// Maximum number of coils per write request (FC15) as defined by the modbus spec
+pub const MAX_WRITE_COILS: u16 = 1968;
+
+/// One write request covering registers or coils with the same unit id and consecutive addresses
+#[derive(Debug, PartialEq)]
+pub struct WriteBlock<T> {
+    pub unit_id: u8,
+    pub address: u16,
+    /// The words or bits to write, starting at the address
+    pub values: Vec<T>,
+    /// Names of the registers or coils which are written by this block
+    pub items: Vec<String>,
+}
+
+/// An item to plan: name, unit id, address and the words or bits to write
+pub type WriteItem<T> = (String, u8, u16, Vec<T>);
+
+/// Plan the write requests for several registers or coils of a client
+///
+/// Items are written in one request if they have the same unit id, follow each other without a gap
+/// and the request does not exceed the max quantity. Gaps are never filled, so no other register or coil is touched.
+///
+/// # Arguments
+///
+/// * `items` - The registers or coils to write
+/// * `max_quantity` - MAX_WRITE_REGISTERS or MAX_WRITE_COILS
+///
+/// # Returns
+///
+/// * `Vec<WriteBlock<T>>` - The write requests, sorted by unit id and address
+pub fn plan_writes<T>(mut items: Vec<WriteItem<T>>, max_quantity: u16) -> Vec<WriteBlock<T>> {
+    items.sort_by_key(|(_, unit_id, address, _)| (*unit_id, *address));
+    let mut blocks: Vec<WriteBlock<T>> = Vec::new();
+    for (name, unit_id, address, values) in items {
+        if let Some(block) = blocks.last_mut() {
+            let block_end = block.address as usize + block.values.len();
+            if block.unit_id == unit_id
+                && address as usize == block_end
+                && block.values.len() + values.len() <= max_quantity as usize
+            {
+                block.values.extend(values);
+                block.items.push(name);
+                continue;
+            }
+        }
+        blocks.push(WriteBlock {
+            unit_id,
+            address,
+            values,
+            items: vec![name],
+        });
+    }
+    blocks
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_write_plan {
+    use super::*;
+
+    #[test]
+    fn test_plan_consecutive_registers_one_block() {
+        let items = vec![
+            ("setpoint".to_string(), 1, 12, vec![0x41a8, 0x0000]),
+            ("mode".to_string(), 1, 10, vec![3]),
+            ("limit".to_string(), 1, 11, vec![80]),
+        ];
+        let blocks = plan_writes(items, MAX_WRITE_REGISTERS);
+        assert_eq!(
+            blocks,
+            vec![WriteBlock {
+                unit_id: 1,
+                address: 10,
+                values: vec![3, 80, 0x41a8, 0x0000],
+                items: vec![
+                    "mode".to_string(),
+                    "limit".to_string(),
+                    "setpoint".to_string()
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_plan_gaps_and_unit_ids_split_blocks() {
+        let items = vec![
+            ("pump".to_string(), 1, 0, vec![true]),
+            ("valve".to_string(), 1, 2, vec![false]),
+            ("heater".to_string(), 2, 1, vec![true]),
+            ("fan".to_string(), 1, 1, vec![true]),
+        ];
+        let blocks = plan_writes(items, MAX_WRITE_COILS);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].values, vec![true, true, false]);
+        assert_eq!(blocks[0].items, vec!["pump", "fan", "valve"]);
+        assert_eq!(blocks[1].unit_id, 2);
+        // Items which would exceed the max quantity start a new block
+        let items = vec![
+            ("a".to_string(), 1, 0, vec![1, 2]),
+            ("b".to_string(), 1, 2, vec![3, 4]),
+        ];
+        assert_eq!(plan_writes(items, 3).len(), 2);
+    }
+}
@@ -1,4 +1,9 @@
+use crate::audit::{self, AuditLog};
+use crate::auth::{ApiToken, Auth, BasicAuthUser};
+use crate::connections::{self, RequestPolicy};
+use crate::prometheus;
 use config::Config;
+use std::time::Duration;
 
 #[derive(Debug, Default, serde::Deserialize, PartialEq)]
 pub struct Args {
@@ -9,6 +14,77 @@ pub struct Args {
     read_data_interval_ms: u16,
     /// local path for the configuration paths
     config_path: String,
+    /// Timeouts in milliseconds for connecting to, reading from and writing to modbus clients
+    #[serde(default = "default_connect_timeout_ms")]
+    connect_timeout_ms: u64,
+    #[serde(default = "default_read_timeout_ms")]
+    read_timeout_ms: u64,
+    #[serde(default = "default_write_timeout_ms")]
+    write_timeout_ms: u64,
+    /// Number of repetitions of a modbus request after a timeout or a broken connection
+    #[serde(default = "default_retries")]
+    retries: u8,
+    /// Wait time in milliseconds before a modbus request is repeated
+    #[serde(default = "default_retry_backoff_ms")]
+    retry_backoff_ms: u64,
+    /// "labeled" exports all registers and coils with labels, "compat" as one metric per register named {client}_{register}
+    #[serde(default = "default_metrics_mode")]
+    metrics_mode: String,
+    /// Add the time of the last successful read as timestamp to the series of the registers and coils
+    #[serde(default)]
+    metrics_timestamps: bool,
+    /// Serve a Swagger UI page for the OpenAPI document at /docs
+    #[serde(default)]
+    api_docs_ui: bool,
+    /// Directory of the audit log of all writes and client changes
+    #[serde(default = "default_audit_path")]
+    audit_path: String,
+    /// Size of an audit log file in kilobytes before it is rotated
+    #[serde(default = "default_audit_max_file_size_kb")]
+    audit_max_file_size_kb: u64,
+    /// Number of rotated audit log files which are kept
+    #[serde(default = "default_audit_max_files")]
+    audit_max_files: usize,
+    /// Static API tokens with their scopes. Without tokens and users every request is allowed
+    #[serde(default)]
+    api_tokens: Vec<ApiToken>,
+    /// JSON file with an array of more API tokens
+    #[serde(default)]
+    api_token_file: Option<String>,
+    /// Users of HTTP basic auth with their scopes
+    #[serde(default)]
+    basic_auth_users: Vec<BasicAuthUser>,
+    /// Origins which may call the API from a browser. Any origin if empty
+    #[serde(default)]
+    cors_allowed_origins: Vec<String>,
+}
+
+fn default_connect_timeout_ms() -> u64 {
+    connections::DEFAULT_CONNECT_TIMEOUT_MS
+}
+fn default_read_timeout_ms() -> u64 {
+    connections::DEFAULT_READ_TIMEOUT_MS
+}
+fn default_write_timeout_ms() -> u64 {
+    connections::DEFAULT_WRITE_TIMEOUT_MS
+}
+fn default_retries() -> u8 {
+    connections::DEFAULT_RETRIES
+}
+fn default_retry_backoff_ms() -> u64 {
+    connections::DEFAULT_RETRY_BACKOFF_MS
+}
+fn default_metrics_mode() -> String {
+    prometheus::METRICS_MODE_LABELED.to_string()
+}
+fn default_audit_path() -> String {
+    audit::DEFAULT_AUDIT_PATH.to_string()
+}
+fn default_audit_max_file_size_kb() -> u64 {
+    audit::DEFAULT_AUDIT_MAX_FILE_SIZE_KB
+}
+fn default_audit_max_files() -> usize {
+    audit::DEFAULT_AUDIT_MAX_FILES
 }
 
 impl Args {
@@ -29,11 +105,36 @@ impl Args {
                 panic!("Error deserializing file: {}", e);
             }
         };
+        if config.metrics_mode != prometheus::METRICS_MODE_LABELED
+            && config.metrics_mode != prometheus::METRICS_MODE_COMPAT
+        {
+            panic!(
+                "Unknown metrics_mode: {}. Please use {} or {}",
+                config.metrics_mode,
+                prometheus::METRICS_MODE_LABELED,
+                prometheus::METRICS_MODE_COMPAT
+            );
+        }
         Args {
             log_level: config.log_level,
             port: config.port,
             read_data_interval_ms: config.read_data_interval_ms,
             config_path: config.config_path,
+            connect_timeout_ms: config.connect_timeout_ms,
+            read_timeout_ms: config.read_timeout_ms,
+            write_timeout_ms: config.write_timeout_ms,
+            retries: config.retries,
+            retry_backoff_ms: config.retry_backoff_ms,
+            metrics_mode: config.metrics_mode,
+            metrics_timestamps: config.metrics_timestamps,
+            api_docs_ui: config.api_docs_ui,
+            audit_path: config.audit_path,
+            audit_max_file_size_kb: config.audit_max_file_size_kb,
+            audit_max_files: config.audit_max_files,
+            api_tokens: config.api_tokens,
+            api_token_file: config.api_token_file,
+            basic_auth_users: config.basic_auth_users,
+            cors_allowed_origins: config.cors_allowed_origins,
         }
     }
     // Write getter for all entries
@@ -49,4 +150,40 @@ impl Args {
     pub fn get_config_path(&self) -> &str {
         &self.config_path
     }
+    pub fn get_metrics_mode(&self) -> &str {
+        &self.metrics_mode
+    }
+    pub fn get_metrics_timestamps(&self) -> bool {
+        self.metrics_timestamps
+    }
+    pub fn get_api_docs_ui(&self) -> bool {
+        self.api_docs_ui
+    }
+    pub fn get_audit_log(&self) -> AuditLog {
+        AuditLog::new(
+            &self.audit_path,
+            self.audit_max_file_size_kb,
+            self.audit_max_files,
+        )
+    }
+    pub fn get_auth(&self) -> Result<Auth, String> {
+        let mut tokens = self.api_tokens.clone();
+        if let Some(token_file) = &self.api_token_file {
+            tokens.extend(Auth::read_token_file(token_file)?);
+        }
+        Auth::new(tokens, self.basic_auth_users.clone())
+            .map_err(|e| format!("Error in the authentication config: {}", e))
+    }
+    pub fn get_cors_allowed_origins(&self) -> &[String] {
+        &self.cors_allowed_origins
+    }
+    pub fn get_request_policy(&self) -> RequestPolicy {
+        RequestPolicy {
+            connect_timeout: Duration::from_millis(self.connect_timeout_ms),
+            read_timeout: Duration::from_millis(self.read_timeout_ms),
+            write_timeout: Duration::from_millis(self.write_timeout_ms),
+            retries: self.retries,
+            retry_backoff: Duration::from_millis(self.retry_backoff_ms),
+        }
+    }
 }
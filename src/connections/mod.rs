@@ -0,0 +1,646 @@
+use crate::clients::Client;
+use crate::errors::impls::ErrorRuntimeNoRejection;
+use crate::utils;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{timeout, Instant};
+use tokio_modbus::client::Client as _;
+use tokio_modbus::prelude::*;
+
+/// Wait time after the first failed connect
+pub const RECONNECT_BACKOFF_INITIAL_MS: u64 = 500;
+/// Upper limit for the wait time between two connects
+pub const RECONNECT_BACKOFF_MAX_MS: u64 = 60_000;
+pub const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 3000;
+pub const DEFAULT_READ_TIMEOUT_MS: u64 = 3000;
+pub const DEFAULT_WRITE_TIMEOUT_MS: u64 = 3000;
+pub const DEFAULT_RETRIES: u8 = 1;
+pub const DEFAULT_RETRY_BACKOFF_MS: u64 = 100;
+
+/// ConnectionManager struct
+///
+/// Holds one long-lived connection per client. The poller and the write routes share the connections,
+/// so a device never sees more than one socket or serial session from this server.
+///
+#[derive(Default)]
+pub struct ConnectionManager {
+    connections: HashMap<String, Arc<Mutex<Connection>>>,
+    policy: RequestPolicy,
+}
+impl ConnectionManager {
+    /// Create a new ConnectionManager struct
+    ///
+    /// # Arguments
+    ///
+    /// * `policy` - The global timeouts and retries for all connections
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new ConnectionManager struct without any connection
+    pub fn new(policy: RequestPolicy) -> Self {
+        Self {
+            connections: HashMap::new(),
+            policy,
+        }
+    }
+    /// Get the connection of a client. A new unconnected connection is created if there is none yet
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The ConnectionManager struct
+    /// * `name` - The name of the client
+    ///
+    /// # Returns
+    ///
+    /// * `Arc<Mutex<Connection>>` - The connection of the client
+    pub fn get_connection(&mut self, name: &str) -> Arc<Mutex<Connection>> {
+        self.connections
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(Connection::new(self.policy))))
+            .clone()
+    }
+    /// Remove the connection of a client and close it
+    ///
+    /// The manager is only locked while the connection is removed. The connection is closed afterwards, so a poll
+    /// or write which still uses it does not block the connections of the other clients until it is done
+    ///
+    /// # Arguments
+    ///
+    /// * `manager` - The shared ConnectionManager
+    /// * `name` - The name of the client
+    pub async fn remove_connection(manager: &Mutex<ConnectionManager>, name: &str) {
+        let connection = manager.lock().await.connections.remove(name);
+        if let Some(connection) = connection {
+            connection.lock().await.disconnect().await;
+        }
+    }
+}
+
+/// RequestPolicy struct
+///
+/// Timeouts and retries for the modbus requests of a client. The global policy is set in setup.toml,
+/// every client can override single values.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RequestPolicy {
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    pub write_timeout: Duration,
+    /// Number of repetitions of a request after a timeout or a broken connection
+    pub retries: u8,
+    /// Wait time before a request is repeated
+    pub retry_backoff: Duration,
+}
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MS),
+            read_timeout: Duration::from_millis(DEFAULT_READ_TIMEOUT_MS),
+            write_timeout: Duration::from_millis(DEFAULT_WRITE_TIMEOUT_MS),
+            retries: DEFAULT_RETRIES,
+            retry_backoff: Duration::from_millis(DEFAULT_RETRY_BACKOFF_MS),
+        }
+    }
+}
+
+/// Connection struct
+///
+/// A modbus context which is kept open between requests. A broken context is dropped and
+/// connecting again is delayed with an exponential backoff.
+///
+#[derive(Default)]
+pub struct Connection {
+    ctx: Option<client::Context>,
+    /// The client of the open connection. Used to reconnect when a request is repeated
+    client: Option<Client>,
+    /// The global policy. The policy of the client is resolved on connect
+    global_policy: RequestPolicy,
+    policy: RequestPolicy,
+    failed_connects: u32,
+    next_connect: Option<Instant>,
+}
+impl Connection {
+    /// Create a new unconnected Connection struct
+    ///
+    /// # Arguments
+    ///
+    /// * `global_policy` - The timeouts and retries for clients which do not set their own
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new Connection struct
+    pub fn new(global_policy: RequestPolicy) -> Self {
+        Self {
+            global_policy,
+            policy: global_policy,
+            ..Default::default()
+        }
+    }
+    /// Make sure the connection to the client is open
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `client` - The client to connect to
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), ErrorRuntimeNoRejection>` - Ok if the connection is open.
+    ///   ReconnectBackoff if the last connect failed and the backoff is not over yet,
+    ///   ConnectTimeout if the client did not answer within the connect timeout
+    pub async fn connect(&mut self, client: &Client) -> Result<(), ErrorRuntimeNoRejection> {
+        self.policy = client.get_request_policy(&self.global_policy);
+        self.client = Some(client.clone());
+        if self.ctx.is_some() {
+            return Ok(());
+        }
+        if let Some(next_connect) = self.next_connect {
+            if Instant::now() < next_connect {
+                return Err(ErrorRuntimeNoRejection::ReconnectBackoff);
+            }
+        }
+        let result = match timeout(self.policy.connect_timeout, utils::create_ctx(client)).await {
+            Ok(result) => result,
+            Err(_) => Err(ErrorRuntimeNoRejection::ConnectTimeout),
+        };
+        match result {
+            Ok(ctx) => {
+                if self.failed_connects > 0 {
+                    log::info!(
+                        "Reconnected to modbus client: {} after {} failed attempts",
+                        &client.name,
+                        self.failed_connects
+                    );
+                }
+                self.ctx = Some(ctx);
+                self.failed_connects = 0;
+                self.next_connect = None;
+                Ok(())
+            }
+            Err(e) => {
+                self.failed_connects += 1;
+                let backoff = get_backoff(self.failed_connects);
+                log::warn!(
+                    "Could not connect to modbus client: {}. Error: {:?}. Next attempt in {:?}",
+                    &client.name,
+                    e,
+                    backoff
+                );
+                self.next_connect = Some(Instant::now() + backoff);
+                Err(e)
+            }
+        }
+    }
+    /// Close the connection. The next connect is not delayed
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    pub async fn disconnect(&mut self) {
+        self.client = None;
+        if let Some(mut ctx) = self.ctx.take() {
+            if let Err(e) = ctx.disconnect().await {
+                log::debug!("Error while closing modbus connection: {:?}", e);
+            }
+        }
+    }
+    /// Check if the connection is open
+    pub fn is_connected(&self) -> bool {
+        self.ctx.is_some()
+    }
+    /// Read a block of registers
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `unit_id` - The unit id to read from
+    /// * `objecttype` - "holding" or "input"
+    /// * `address` - The first address to read
+    /// * `quantity` - The number of registers to read
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u16>, ErrorRuntimeNoRejection>` - The words read
+    pub async fn read_registers(
+        &mut self,
+        unit_id: u8,
+        objecttype: &str,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>, ErrorRuntimeNoRejection> {
+        let input = match objecttype {
+            "input" => true,
+            "holding" => false,
+            _ => return Err(ErrorRuntimeNoRejection::ObjecttypeNotSupported),
+        };
+        self.execute(unit_id, Direction::Read, |ctx| match input {
+            true => ctx.read_input_registers(address, quantity),
+            false => ctx.read_holding_registers(address, quantity),
+        })
+        .await
+    }
+    /// Read a block of coils or discrete inputs
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `unit_id` - The unit id to read from
+    /// * `objecttype` - "coil" or "discrete"
+    /// * `address` - The first address to read
+    /// * `quantity` - The number of coils to read
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<bool>, ErrorRuntimeNoRejection>` - The values read
+    pub async fn read_coils(
+        &mut self,
+        unit_id: u8,
+        objecttype: &str,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<bool>, ErrorRuntimeNoRejection> {
+        let discrete = match objecttype {
+            "discrete" => true,
+            "coil" => false,
+            _ => return Err(ErrorRuntimeNoRejection::ObjecttypeNotSupported),
+        };
+        self.execute(unit_id, Direction::Read, |ctx| match discrete {
+            true => ctx.read_discrete_inputs(address, quantity),
+            false => ctx.read_coils(address, quantity),
+        })
+        .await
+    }
+    /// Write a single holding register
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `unit_id` - The unit id to write to
+    /// * `address` - The address of the register
+    /// * `word` - The value to write
+    pub async fn write_register(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        word: u16,
+    ) -> Result<(), ErrorRuntimeNoRejection> {
+        self.execute(unit_id, Direction::Write, |ctx| {
+            ctx.write_single_register(address, word)
+        })
+        .await
+    }
+    /// Write several holding registers in one request (FC16)
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `unit_id` - The unit id to write to
+    /// * `address` - The address of the first register
+    /// * `words` - The words to write, one per register
+    pub async fn write_registers(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        words: &[u16],
+    ) -> Result<(), ErrorRuntimeNoRejection> {
+        self.execute(unit_id, Direction::Write, |ctx| {
+            let words = words.to_vec();
+            Box::pin(async move { ctx.write_multiple_registers(address, &words).await })
+        })
+        .await
+    }
+    /// Set or clear bits of a holding register with a mask write (FC22)
+    ///
+    /// The device calculates (current & and_mask) | (or_mask & !and_mask)
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `unit_id` - The unit id to write to
+    /// * `address` - The address of the register
+    /// * `and_mask` - The bits to keep
+    /// * `or_mask` - The bits to set
+    pub async fn mask_write_register(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        and_mask: u16,
+        or_mask: u16,
+    ) -> Result<(), ErrorRuntimeNoRejection> {
+        self.execute(unit_id, Direction::Write, |ctx| {
+            ctx.masked_write_register(address, and_mask, or_mask)
+        })
+        .await
+    }
+    /// Write a single coil
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `unit_id` - The unit id to write to
+    /// * `address` - The address of the coil
+    /// * `value` - The value to write
+    pub async fn write_coil(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        value: bool,
+    ) -> Result<(), ErrorRuntimeNoRejection> {
+        self.execute(unit_id, Direction::Write, |ctx| {
+            ctx.write_single_coil(address, value)
+        })
+        .await
+    }
+    /// Write several coils in one request (FC15)
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The Connection struct
+    /// * `unit_id` - The unit id to write to
+    /// * `address` - The address of the first coil
+    /// * `values` - The values to write, one per coil
+    pub async fn write_coils(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        values: &[bool],
+    ) -> Result<(), ErrorRuntimeNoRejection> {
+        self.execute(unit_id, Direction::Write, |ctx| {
+            let values = values.to_vec();
+            Box::pin(async move { ctx.write_multiple_coils(address, &values).await })
+        })
+        .await
+    }
+
+    // Run a request with the timeout of its direction. Timeouts and broken connections are
+    // repeated as configured in the policy, modbus exceptions are answers of the device and never repeated
+    async fn execute<T, F>(
+        &mut self,
+        unit_id: u8,
+        direction: Direction,
+        mut request: F,
+    ) -> Result<T, ErrorRuntimeNoRejection>
+    where
+        F: for<'c> FnMut(&'c mut client::Context) -> ModbusFuture<'c, T>,
+    {
+        let mut attempt = 0;
+        loop {
+            let result = self.execute_once(unit_id, &direction, &mut request).await;
+            match result {
+                Err(ErrorRuntimeNoRejection::TransportError)
+                | Err(ErrorRuntimeNoRejection::ReadTimeout)
+                | Err(ErrorRuntimeNoRejection::WriteTimeout)
+                    if attempt < self.policy.retries =>
+                {
+                    attempt += 1;
+                    log::debug!(
+                        "Repeating modbus request. Attempt {} of {}",
+                        attempt,
+                        self.policy.retries
+                    );
+                    tokio::time::sleep(self.policy.retry_backoff).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn execute_once<T, F>(
+        &mut self,
+        unit_id: u8,
+        direction: &Direction,
+        request: &mut F,
+    ) -> Result<T, ErrorRuntimeNoRejection>
+    where
+        F: for<'c> FnMut(&'c mut client::Context) -> ModbusFuture<'c, T>,
+    {
+        // The connection was broken by the last attempt
+        if self.ctx.is_none() {
+            if let Some(client) = self.client.clone() {
+                self.connect(&client).await?;
+            }
+        }
+        let (duration, timeout_error) = match direction {
+            Direction::Read => (
+                self.policy.read_timeout,
+                ErrorRuntimeNoRejection::ReadTimeout,
+            ),
+            Direction::Write => (
+                self.policy.write_timeout,
+                ErrorRuntimeNoRejection::WriteTimeout,
+            ),
+        };
+        let ctx = self.get_ctx(unit_id)?;
+        match timeout(duration, request(ctx)).await {
+            Ok(result) => self.handle_result(result),
+            Err(_) => {
+                // A late answer would be taken as the answer of the next request
+                log::warn!(
+                    "Modbus request timed out after {:?}. Reconnecting on next request",
+                    duration
+                );
+                self.ctx = None;
+                Err(timeout_error)
+            }
+        }
+    }
+
+    fn get_ctx(&mut self, unit_id: u8) -> Result<&mut client::Context, ErrorRuntimeNoRejection> {
+        let ctx = self
+            .ctx
+            .as_mut()
+            .ok_or(ErrorRuntimeNoRejection::NotConnected)?;
+        ctx.set_slave(Slave(unit_id));
+        Ok(ctx)
+    }
+
+    // A modbus exception is an answer of the device, the connection is fine.
+    // Every other error means the connection is broken and has to be opened again
+    fn handle_result<T>(
+        &mut self,
+        result: Result<Result<T, ExceptionCode>, tokio_modbus::Error>,
+    ) -> Result<T, ErrorRuntimeNoRejection> {
+        match result {
+            Ok(Ok(data)) => Ok(data),
+            Ok(Err(exception)) => {
+                log::debug!("Modbus exception: {:?}", exception);
+                Err(ErrorRuntimeNoRejection::ModbusException)
+            }
+            Err(e) => {
+                log::warn!(
+                    "Modbus connection broken: {:?}. Reconnecting on next request",
+                    e
+                );
+                self.ctx = None;
+                Err(ErrorRuntimeNoRejection::TransportError)
+            }
+        }
+    }
+}
+
+/// The future of a request of tokio_modbus
+type ModbusFuture<'c, T> = Pin<Box<dyn Future<Output = tokio_modbus::Result<T>> + Send + 'c>>;
+
+/// Direction of a request. Reads and writes have their own timeout
+enum Direction {
+    Read,
+    Write,
+}
+
+/// Get the wait time before the next connect
+///
+/// # Arguments
+///
+/// * `failed_connects` - The number of connects which failed in a row
+///
+/// # Returns
+///
+/// * `Duration` - Doubles with every failed connect, starting at 500 ms and capped at 60 s
+pub fn get_backoff(failed_connects: u32) -> Duration {
+    let exponent = failed_connects.saturating_sub(1).min(16);
+    Duration::from_millis((RECONNECT_BACKOFF_INITIAL_MS << exponent).min(RECONNECT_BACKOFF_MAX_MS))
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_connections {
+    use super::*;
+    use crate::utils::simulator::*;
+
+    #[test]
+    fn test_get_backoff() {
+        assert_eq!(get_backoff(1), Duration::from_millis(500));
+        assert_eq!(get_backoff(2), Duration::from_millis(1000));
+        assert_eq!(get_backoff(4), Duration::from_millis(4000));
+        assert_eq!(get_backoff(8), Duration::from_millis(60_000));
+        assert_eq!(get_backoff(100), Duration::from_millis(60_000));
+    }
+
+    #[tokio::test]
+    async fn test_connect_failure_backoff() {
+        let client = rtu_client("/dev/does_not_exist", "");
+        let mut connection = Connection::default();
+        assert!(matches!(
+            connection.connect(&client).await,
+            Err(ErrorRuntimeNoRejection::CouldNotConnect)
+        ));
+        assert!(matches!(
+            connection.connect(&client).await,
+            Err(ErrorRuntimeNoRejection::ReconnectBackoff)
+        ));
+        assert!(!connection.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_read_and_write_over_one_connection() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let client = rtu_client(&device, "");
+        let mut connection = Connection::default();
+        connection.connect(&client).await.unwrap();
+        connection.write_register(1, 3, 42).await.unwrap();
+        assert_eq!(
+            connection.read_registers(1, "holding", 2, 2).await.unwrap(),
+            vec![2, 42]
+        );
+        connection.write_coil(1, 5, true).await.unwrap();
+        assert_eq!(
+            connection.read_coils(1, "coil", 5, 1).await.unwrap(),
+            vec![true]
+        );
+        assert!(connection.is_connected());
+        assert_eq!(simulated_slave.memory.lock().unwrap().requests, 4);
+    }
+
+    #[tokio::test]
+    async fn test_write_multiple_registers_and_coils() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let client = rtu_client(&device, "");
+        let mut connection = Connection::default();
+        connection.connect(&client).await.unwrap();
+        connection.write_registers(1, 8, &[0x4148, 0x0000]).await.unwrap();
+        connection.write_coils(1, 2, &[true, false, true]).await.unwrap();
+        let memory = simulated_slave.memory.lock().unwrap();
+        assert_eq!(memory.holding[&8], 0x4148);
+        assert_eq!(memory.holding[&9], 0);
+        assert!(memory.coils[&2] && !memory.coils[&3] && memory.coils[&4]);
+        assert_eq!(memory.requests, 2);
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_after_disconnect() {
+        let (_slave, device, _) = spawn_rtu_simulated_slave();
+        let client = rtu_client(&device, "");
+        let mut connection = Connection::default();
+        connection.connect(&client).await.unwrap();
+        connection.disconnect().await;
+        assert!(matches!(
+            connection.read_registers(1, "input", 0, 1).await,
+            Err(ErrorRuntimeNoRejection::NotConnected)
+        ));
+        connection.connect(&client).await.unwrap();
+        assert_eq!(
+            connection.read_registers(1, "input", 0, 1).await.unwrap(),
+            vec![1]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_timeout_is_retried() {
+        // Nobody answers on the other side of the pair
+        let (_master, slave) = tokio_serial::SerialStream::pair().unwrap();
+        let device = tokio_serial::SerialPort::name(&slave).unwrap();
+        let client = rtu_client(
+            &device,
+            r#""read_timeout_ms": 50, "retries": 2, "retry_backoff_ms": 10,"#,
+        );
+        let mut connection = Connection::new(RequestPolicy::default());
+        connection.connect(&client).await.unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            connection.read_registers(1, "holding", 0, 1).await,
+            Err(ErrorRuntimeNoRejection::ReadTimeout)
+        ));
+        // Three attempts with a backoff between them
+        assert!(start.elapsed() >= Duration::from_millis(170));
+        assert!(!connection.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_modbus_exception_is_not_retried() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let client = rtu_client(&device, r#""retries": 3,"#);
+        let mut connection = Connection::new(RequestPolicy::default());
+        connection.connect(&client).await.unwrap();
+        // The simulated slave has no device identification and answers with an exception
+        assert!(matches!(
+            connection
+                .execute(1, Direction::Read, |ctx| ctx
+                    .read_device_identification(ReadCode::Basic, 0))
+                .await,
+            Err(ErrorRuntimeNoRejection::ModbusException)
+        ));
+        assert_eq!(simulated_slave.memory.lock().unwrap().requests, 1);
+        assert!(connection.is_connected());
+    }
+
+    #[tokio::test]
+    async fn test_manager_shares_connection() {
+        let manager = Mutex::new(ConnectionManager::new(RequestPolicy::default()));
+        let first = manager.lock().await.get_connection("client");
+        let second = manager.lock().await.get_connection("client");
+        assert!(Arc::ptr_eq(&first, &second));
+        // A connection in use does not block the manager while it is removed
+        let in_use = first.clone().lock_owned().await;
+        let removed = ConnectionManager::remove_connection(&manager, "client");
+        tokio::pin!(removed);
+        assert!(futures::poll!(&mut removed).is_pending());
+        let third = manager.lock().await.get_connection("client");
+        assert!(!Arc::ptr_eq(&first, &third));
+        drop(in_use);
+        removed.await;
+    }
+}
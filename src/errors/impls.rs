@@ -1,3 +1,4 @@
+use warp::http::StatusCode;
 use warp::reject::Reject;
 
 #[derive(Debug)]
@@ -7,16 +8,43 @@ pub enum ErrorRuntime {
     ClientNotAbleToConnect(Option<String>),
     ClientProtocolNotSupported,
     ClientRegisterDatatypeNotSupported,
+    ClientRegisterLengthNotValid(Option<String>),
+    ClientByteorderNotSupported(Option<String>),
     ClientRegisterObjecttypeNotSupported,
     ClientCoilObjecttypeNotSupported,
+    ClientSerialSettingsMissing,
+    ClientSerialSettingsInvalid(Option<String>),
+    ClientAddressNotValid(Option<String>),
+    ClientUnitIdNotValid(Option<String>),
+    ClientTimeoutNotValid(Option<String>),
+    ClientLabelNotValid(Option<String>),
+    ClientConnectTimeout(Option<String>),
+    ClientWriteTimeout(Option<String>),
+    ClientStalePolicyNotValid(Option<String>),
     ClientExists,
+    ClientNameNotChangeable(Option<String>),
+    ClientPatchNotValid(Option<String>),
     ClientJsonParseError, // used when clients are created on init
     ClientRegisterNotFound(Option<String>),
     ClientRegisterNotWritable(Option<String>),
+    ClientRegisterMultiWordNotWritable(Option<String>),
+    ClientRegisterScalingNotValid(Option<String>),
+    ClientRegisterBitfieldNotValid(Option<String>),
+    ClientRegisterStatesNotValid(Option<String>),
+    ClientBitfieldNotFound(Option<String>),
+    ClientRegisterValueOutOfRange(Option<String>),
     ClientRegisterWriteGenericError,
     ClientCoilNotFound(Option<String>),
     ClientCoilNotInput(Option<String>),
     ClientCoilWriteGenericError,
+    ClientWriteConstraintsNotValid(Option<String>),
+    ClientItemReadOnly(Option<String>),
+    ClientValueBelowMin(Option<String>),
+    ClientValueAboveMax(Option<String>),
+    ClientValueNotAllowed(Option<String>),
+    ClientWriteNotConfirmed(Option<String>),
+    ClientInterlockActive(Option<String>),
+    ClientWriteNotVerified(Option<String>),
     FSReadToStringError,
     FSReadDirError,
     FSDirEntryError,
@@ -32,13 +60,492 @@ pub enum ErrorRuntime {
     RegexError,
     JSONSerializeError,
     ValueNotParsableToU16(Option<String>),
+    ValueNotParsableToF64(Option<String>),
     ValueNotParsableToBool(Option<String>),
+    ValueNotParsableToInteger(Option<String>),
+    ValueNotParsableToTimestamp(Option<String>),
+    AuditLogReadError,
+    AuthenticationRequired(Option<String>),
+    AuthenticationNotValid(Option<String>),
+    AuthScopeMissing(Option<String>),
     ClientRegisterWriteError(Option<String>),
     NoParametersProvided,
 }
 impl Reject for ErrorRuntime {}
+impl ErrorRuntime {
+    /// Get the stable error code of the error. Clients of the API can react on it without parsing the message
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The error
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - The error code in snake case, e.g. client_not_found
+    pub fn get_code(&self) -> &'static str {
+        match self {
+            ErrorRuntime::ClientNotFound(_) => "client_not_found",
+            ErrorRuntime::ClientNotAbleToConnect(_) => "client_not_able_to_connect",
+            ErrorRuntime::ClientProtocolNotSupported => "client_protocol_not_supported",
+            ErrorRuntime::ClientRegisterDatatypeNotSupported => "register_datatype_not_supported",
+            ErrorRuntime::ClientRegisterLengthNotValid(_) => "register_length_not_valid",
+            ErrorRuntime::ClientByteorderNotSupported(_) => "byteorder_not_supported",
+            ErrorRuntime::ClientRegisterObjecttypeNotSupported => {
+                "register_objecttype_not_supported"
+            }
+            ErrorRuntime::ClientCoilObjecttypeNotSupported => "coil_objecttype_not_supported",
+            ErrorRuntime::ClientSerialSettingsMissing => "serial_settings_missing",
+            ErrorRuntime::ClientSerialSettingsInvalid(_) => "serial_settings_not_valid",
+            ErrorRuntime::ClientAddressNotValid(_) => "address_not_valid",
+            ErrorRuntime::ClientUnitIdNotValid(_) => "unit_id_not_valid",
+            ErrorRuntime::ClientTimeoutNotValid(_) => "timeout_not_valid",
+            ErrorRuntime::ClientLabelNotValid(_) => "label_not_valid",
+            ErrorRuntime::ClientConnectTimeout(_) => "client_connect_timeout",
+            ErrorRuntime::ClientWriteTimeout(_) => "client_write_timeout",
+            ErrorRuntime::ClientStalePolicyNotValid(_) => "stale_policy_not_valid",
+            ErrorRuntime::ClientExists => "client_exists",
+            ErrorRuntime::ClientNameNotChangeable(_) => "client_name_not_changeable",
+            ErrorRuntime::ClientPatchNotValid(_) => "client_patch_not_valid",
+            ErrorRuntime::ClientJsonParseError => "client_config_corrupted",
+            ErrorRuntime::ClientRegisterNotFound(_) => "register_not_found",
+            ErrorRuntime::ClientRegisterNotWritable(_) => "register_not_writable",
+            ErrorRuntime::ClientRegisterMultiWordNotWritable(_) => {
+                "register_multi_word_not_writable"
+            }
+            ErrorRuntime::ClientRegisterScalingNotValid(_) => "register_scaling_not_valid",
+            ErrorRuntime::ClientRegisterBitfieldNotValid(_) => "bitfield_not_valid",
+            ErrorRuntime::ClientRegisterStatesNotValid(_) => "register_states_not_valid",
+            ErrorRuntime::ClientBitfieldNotFound(_) => "bitfield_not_found",
+            ErrorRuntime::ClientRegisterValueOutOfRange(_) => "value_out_of_range",
+            ErrorRuntime::ClientRegisterWriteGenericError => "register_write_failed",
+            ErrorRuntime::ClientCoilNotFound(_) => "coil_not_found",
+            ErrorRuntime::ClientCoilNotInput(_) => "coil_not_writable",
+            ErrorRuntime::ClientCoilWriteGenericError => "coil_write_failed",
+            ErrorRuntime::ClientWriteConstraintsNotValid(_) => "write_constraints_not_valid",
+            ErrorRuntime::ClientItemReadOnly(_) => "read_only",
+            ErrorRuntime::ClientValueBelowMin(_) => "value_below_min",
+            ErrorRuntime::ClientValueAboveMax(_) => "value_above_max",
+            ErrorRuntime::ClientValueNotAllowed(_) => "value_not_allowed",
+            ErrorRuntime::ClientWriteNotConfirmed(_) => "confirmation_required",
+            ErrorRuntime::ClientInterlockActive(_) => "interlock_active",
+            ErrorRuntime::ClientWriteNotVerified(_) => "write_not_verified",
+            ErrorRuntime::FSReadToStringError
+            | ErrorRuntime::FSReadDirError
+            | ErrorRuntime::FSDirEntryError
+            | ErrorRuntime::FSFileDeleteError
+            | ErrorRuntime::FSFileCreateError
+            | ErrorRuntime::FSPathNotUTF8Error => "config_storage_error",
+            ErrorRuntime::PrometheusErrorRegistry
+            | ErrorRuntime::PrometheusErrorGaugeNew
+            | ErrorRuntime::PrometheusErrorRegistryRegister
+            | ErrorRuntime::PrometheusErrorEncoder
+            | ErrorRuntime::PrometheusErrorGaugeRemove
+            | ErrorRuntime::PrometheusErrorRegistryUnregister => "metrics_error",
+            ErrorRuntime::RegexError => "name_not_valid",
+            ErrorRuntime::JSONSerializeError => "json_serialize_error",
+            ErrorRuntime::ValueNotParsableToU16(_)
+            | ErrorRuntime::ValueNotParsableToF64(_)
+            | ErrorRuntime::ValueNotParsableToBool(_)
+            | ErrorRuntime::ValueNotParsableToInteger(_)
+            | ErrorRuntime::ValueNotParsableToTimestamp(_) => "value_not_parsable",
+            ErrorRuntime::AuditLogReadError => "audit_log_error",
+            ErrorRuntime::AuthenticationRequired(_) => "authentication_required",
+            ErrorRuntime::AuthenticationNotValid(_) => "authentication_not_valid",
+            ErrorRuntime::AuthScopeMissing(_) => "scope_missing",
+            ErrorRuntime::ClientRegisterWriteError(_) => "register_write_failed",
+            ErrorRuntime::NoParametersProvided => "no_parameters_provided",
+        }
+    }
+
+    /// Get the HTTP status of the error
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The error
+    ///
+    /// # Returns
+    ///
+    /// * `StatusCode` - 404 for unknown clients and items, 409 for conflicts, 400 for unparsable parameters,
+    ///   422 for invalid configs and values, 401/403 for missing credentials or scopes,
+    ///   502/504 if the modbus client failed and 500 for internal errors
+    pub fn get_status(&self) -> StatusCode {
+        match self {
+            ErrorRuntime::ClientNotFound(_)
+            | ErrorRuntime::ClientRegisterNotFound(_)
+            | ErrorRuntime::ClientBitfieldNotFound(_)
+            | ErrorRuntime::ClientCoilNotFound(_) => StatusCode::NOT_FOUND,
+            ErrorRuntime::ClientExists
+            | ErrorRuntime::ClientNameNotChangeable(_)
+            | ErrorRuntime::ClientInterlockActive(_) => StatusCode::CONFLICT,
+            ErrorRuntime::ClientWriteNotConfirmed(_) => StatusCode::PRECONDITION_REQUIRED,
+            ErrorRuntime::AuthenticationRequired(_) | ErrorRuntime::AuthenticationNotValid(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+            ErrorRuntime::AuthScopeMissing(_) => StatusCode::FORBIDDEN,
+            ErrorRuntime::ClientNotAbleToConnect(_)
+            | ErrorRuntime::ClientRegisterWriteGenericError
+            | ErrorRuntime::ClientCoilWriteGenericError
+            | ErrorRuntime::ClientRegisterWriteError(_)
+            | ErrorRuntime::ClientWriteNotVerified(_) => StatusCode::BAD_GATEWAY,
+            ErrorRuntime::ClientConnectTimeout(_) | ErrorRuntime::ClientWriteTimeout(_) => {
+                StatusCode::GATEWAY_TIMEOUT
+            }
+            ErrorRuntime::ValueNotParsableToU16(_)
+            | ErrorRuntime::ValueNotParsableToF64(_)
+            | ErrorRuntime::ValueNotParsableToBool(_)
+            | ErrorRuntime::ValueNotParsableToInteger(_)
+            | ErrorRuntime::ValueNotParsableToTimestamp(_)
+            | ErrorRuntime::NoParametersProvided => StatusCode::BAD_REQUEST,
+            ErrorRuntime::ClientJsonParseError
+            | ErrorRuntime::FSReadToStringError
+            | ErrorRuntime::FSReadDirError
+            | ErrorRuntime::FSDirEntryError
+            | ErrorRuntime::FSFileDeleteError
+            | ErrorRuntime::FSFileCreateError
+            | ErrorRuntime::FSPathNotUTF8Error
+            | ErrorRuntime::PrometheusErrorRegistry
+            | ErrorRuntime::PrometheusErrorGaugeNew
+            | ErrorRuntime::PrometheusErrorRegistryRegister
+            | ErrorRuntime::PrometheusErrorEncoder
+            | ErrorRuntime::PrometheusErrorGaugeRemove
+            | ErrorRuntime::PrometheusErrorRegistryUnregister
+            | ErrorRuntime::JSONSerializeError
+            | ErrorRuntime::AuditLogReadError => StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorRuntime::ClientProtocolNotSupported
+            | ErrorRuntime::ClientRegisterDatatypeNotSupported
+            | ErrorRuntime::ClientRegisterLengthNotValid(_)
+            | ErrorRuntime::ClientByteorderNotSupported(_)
+            | ErrorRuntime::ClientRegisterObjecttypeNotSupported
+            | ErrorRuntime::ClientCoilObjecttypeNotSupported
+            | ErrorRuntime::ClientSerialSettingsMissing
+            | ErrorRuntime::ClientSerialSettingsInvalid(_)
+            | ErrorRuntime::ClientAddressNotValid(_)
+            | ErrorRuntime::ClientUnitIdNotValid(_)
+            | ErrorRuntime::ClientTimeoutNotValid(_)
+            | ErrorRuntime::ClientLabelNotValid(_)
+            | ErrorRuntime::ClientStalePolicyNotValid(_)
+            | ErrorRuntime::ClientPatchNotValid(_)
+            | ErrorRuntime::ClientRegisterNotWritable(_)
+            | ErrorRuntime::ClientRegisterMultiWordNotWritable(_)
+            | ErrorRuntime::ClientRegisterScalingNotValid(_)
+            | ErrorRuntime::ClientRegisterBitfieldNotValid(_)
+            | ErrorRuntime::ClientRegisterStatesNotValid(_)
+            | ErrorRuntime::ClientRegisterValueOutOfRange(_)
+            | ErrorRuntime::ClientCoilNotInput(_)
+            | ErrorRuntime::ClientWriteConstraintsNotValid(_)
+            | ErrorRuntime::ClientItemReadOnly(_)
+            | ErrorRuntime::ClientValueBelowMin(_)
+            | ErrorRuntime::ClientValueAboveMax(_)
+            | ErrorRuntime::ClientValueNotAllowed(_)
+            | ErrorRuntime::RegexError => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+
+    /// Get the WWW-Authenticate challenge of 401 errors
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The error
+    ///
+    /// # Returns
+    ///
+    /// * `Option<&str>` - The challenge, e.g. Bearer realm="modbus-prometheus-api-server". None for all other errors
+    pub fn get_challenge(&self) -> Option<&str> {
+        match self {
+            ErrorRuntime::AuthenticationRequired(challenge)
+            | ErrorRuntime::AuthenticationNotValid(challenge) => challenge.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Get the message of the error for humans
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The error
+    ///
+    /// # Returns
+    ///
+    /// * `String` - The message with the name of the client, item or value which caused the error
+    pub fn get_message(&self) -> String {
+        match self {
+            ErrorRuntime::ClientNotFound(client) => format!(
+                "Client {} not found. Please check the client name.",
+                name(client)
+            ),
+            ErrorRuntime::ClientNotAbleToConnect(client) => format!(
+                "Client {} could not be connected via modbus. Please check the connection.",
+                name(client)
+            ),
+            ErrorRuntime::ClientProtocolNotSupported => {
+                "The current client protocol is not supported. Supported protocols are: tcp, rtu".to_string()
+            }
+            ErrorRuntime::ClientRegisterDatatypeNotSupported => {
+                "One of the registers does not have a supported datatype. Supported datatypes are: uint16, int16, uint32, int32, float32, uint64, int64, float64, string".to_string()
+            }
+            ErrorRuntime::ClientRegisterLengthNotValid(register) => format!(
+                "Length of register {} does not match its datatype. Use length 1 for 16 bit, 2 for 32 bit, 4 for 64 bit datatypes and 1 to 125 for strings",
+                name(register)
+            ),
+            ErrorRuntime::ClientByteorderNotSupported(item) => format!(
+                "Byte order of {} is not supported. Supported byte orders are: abcd, cdab, badc, dcba",
+                name(item)
+            ),
+            ErrorRuntime::ClientRegisterObjecttypeNotSupported => {
+                "One of the registers does not have a supported objecttype. Supported objecttypes are: input, holding".to_string()
+            }
+            ErrorRuntime::ClientCoilObjecttypeNotSupported => {
+                "One of the coils does not have a supported objecttype. Supported objecttypes are: coil, discrete".to_string()
+            }
+            ErrorRuntime::ClientSerialSettingsMissing => {
+                "Clients with protocol rtu need serial settings: device, baud_rate and optional parity, data_bits, stop_bits".to_string()
+            }
+            ErrorRuntime::ClientAddressNotValid(address) => format!(
+                "Address {} is not valid. Clients with protocol tcp need an IPv4 ip_address and a port other than 0",
+                name(address)
+            ),
+            ErrorRuntime::ClientSerialSettingsInvalid(setting) => format!(
+                "Serial setting {} is not valid. Supported are parity: none, even, odd; data_bits: 5-8; stop_bits: 1, 2",
+                name(setting)
+            ),
+            ErrorRuntime::ClientUnitIdNotValid(item) => format!(
+                "Unit id of {} is not valid. Please provide a unit id between 1 and 247 (rtu) or 1 and 255 (tcp)",
+                name(item)
+            ),
+            ErrorRuntime::ClientTimeoutNotValid(field) => format!(
+                "Timeout {} is not valid. Please provide a timeout of at least 1 ms",
+                name(field)
+            ),
+            ErrorRuntime::ClientLabelNotValid(label) => format!(
+                "Label {} is not valid. Please use a prometheus label name which is not set by the server (client, register, coil, objecttype, address, unit_id)",
+                name(label)
+            ),
+            ErrorRuntime::ClientConnectTimeout(client) => format!(
+                "Client {} did not accept the connection within the connect timeout.",
+                name(client)
+            ),
+            ErrorRuntime::ClientWriteTimeout(item) => format!(
+                "Writing {} timed out. The device did not answer within the write timeout.",
+                name(item)
+            ),
+            ErrorRuntime::ClientStalePolicyNotValid(item) => format!(
+                "Stale policy setting {} is not valid. Please use keep, nan or remove and values greater than 0.",
+                name(item)
+            ),
+            ErrorRuntime::ClientExists => {
+                "Trying to create client, but a valid config for this client already exists. Please delete the client first or update it via PUT /clients/{name}.".to_string()
+            }
+            ErrorRuntime::ClientNameNotChangeable(client) => format!(
+                "The name of client {} can not be changed. Please create a new client instead.",
+                name(client)
+            ),
+            ErrorRuntime::ClientPatchNotValid(error) => format!(
+                "The patched client is not a valid client config: {}",
+                name(error)
+            ),
+            ErrorRuntime::ClientJsonParseError => {
+                "Trying to create client, but the local config is corrupted. Can not create the client".to_string()
+            }
+            ErrorRuntime::ClientRegisterNotFound(register) => {
+                format!("Register {} not found in client.", name(register))
+            }
+            ErrorRuntime::ClientRegisterNotWritable(register) => format!(
+                "Register {} is not writable. It is an input register.",
+                name(register)
+            ),
+            ErrorRuntime::ClientRegisterMultiWordNotWritable(register) => format!(
+                "Register {} spans multiple registers. Only 16 bit registers can be written.",
+                name(register)
+            ),
+            ErrorRuntime::ClientRegisterScalingNotValid(register) => format!(
+                "Scaling of register {} is not valid. The gain must not be 0 and the unit must match ^[a-z0-9_]+$",
+                name(register)
+            ),
+            ErrorRuntime::ClientRegisterBitfieldNotValid(bitfield) => format!(
+                "Bitfield {} is not valid. It must be inside its register and its name must be unique.",
+                name(bitfield)
+            ),
+            ErrorRuntime::ClientRegisterStatesNotValid(register) => format!(
+                "States of register {} are not valid. The register must have an integer datatype and the state names must be unique.",
+                name(register)
+            ),
+            ErrorRuntime::ClientBitfieldNotFound(bitfield) => format!(
+                "Bitfield {} not found. Please use the name of the register and the bitfield: {{register}}_{{bitfield}}",
+                name(bitfield)
+            ),
+            ErrorRuntime::ClientRegisterValueOutOfRange(register) => format!(
+                "Value is out of the range of register {}. The raw value must fit into the datatype of the register.",
+                name(register)
+            ),
+            ErrorRuntime::ClientRegisterWriteGenericError => {
+                "Generic Error while writing register".to_string()
+            }
+            ErrorRuntime::ClientCoilNotFound(coil) => {
+                format!("Coil {} not found in client.", name(coil))
+            }
+            ErrorRuntime::ClientCoilNotInput(coil) => format!(
+                "Coil {} is not writable. It is an discrete coil.",
+                name(coil)
+            ),
+            ErrorRuntime::ClientCoilWriteGenericError => {
+                "Generic Error while writing coil".to_string()
+            }
+            ErrorRuntime::ClientWriteConstraintsNotValid(item) => format!(
+                "Write constraints of {} are not valid. min must not be greater than max, allowed_values must not be empty and interlocks need one register or coil of the client and one of the operators <, <=, >, >=, ==, !=. Strings and registers with bitfields can not have limits.",
+                name(item)
+            ),
+            ErrorRuntime::ClientItemReadOnly(item) => {
+                format!("{} is read only and can not be written.", name(item))
+            }
+            ErrorRuntime::ClientValueBelowMin(register) => format!(
+                "Value is below the min of register {}. See GET /clients/{{name}} for its limits.",
+                name(register)
+            ),
+            ErrorRuntime::ClientValueAboveMax(register) => format!(
+                "Value is above the max of register {}. See GET /clients/{{name}} for its limits.",
+                name(register)
+            ),
+            ErrorRuntime::ClientValueNotAllowed(register) => format!(
+                "Value is not one of the allowed values of register {}. See GET /clients/{{name}} for its limits.",
+                name(register)
+            ),
+            ErrorRuntime::ClientWriteNotConfirmed(item) => format!(
+                "Writing {} must be confirmed. Please repeat the request with confirm=true.",
+                name(item)
+            ),
+            ErrorRuntime::ClientInterlockActive(interlock) => format!(
+                "Write blocked by an interlock: {}. The value does not meet the condition, is stale or was not read yet.",
+                name(interlock)
+            ),
+            ErrorRuntime::ClientWriteNotVerified(read_back) => format!(
+                "The device acknowledged the write, but did not take the value: {}",
+                name(read_back)
+            ),
+            ErrorRuntime::FSReadToStringError => {
+                "Cannot process local config file to string".to_string()
+            }
+            ErrorRuntime::FSReadDirError => {
+                "Cannot read configs from local FS. Please contact developer, this is a major issue!".to_string()
+            }
+            ErrorRuntime::FSDirEntryError => {
+                "Cannot process dir entry. Please contact developer, this is a major issue!".to_string()
+            }
+            ErrorRuntime::FSFileDeleteError => "Cannot delete local config file.".to_string(),
+            ErrorRuntime::FSFileCreateError => "Cannot create local config file".to_string(),
+            ErrorRuntime::FSPathNotUTF8Error => {
+                "The path of the local config is not valid UTF-8".to_string()
+            }
+            ErrorRuntime::PrometheusErrorRegistry => {
+                "The Prometheus Registry couldnt be processed".to_string()
+            }
+            ErrorRuntime::PrometheusErrorGaugeNew => {
+                "Could not create a new Gauge value".to_string()
+            }
+            ErrorRuntime::PrometheusErrorRegistryRegister => {
+                "Could not register the new Gauge value to the registry".to_string()
+            }
+            ErrorRuntime::PrometheusErrorEncoder => {
+                "The Prometheus Encoder couldnt be processed".to_string()
+            }
+            ErrorRuntime::PrometheusErrorGaugeRemove => {
+                "Could not get the target Gauge value to be removed".to_string()
+            }
+            ErrorRuntime::PrometheusErrorRegistryUnregister => {
+                "Cannot unregister the Gauge value from registry".to_string()
+            }
+            ErrorRuntime::RegexError => {
+                "No valid String is provided. Please check the fields name, register.name if you just have: lowercase, number or underscores".to_string()
+            }
+            ErrorRuntime::JSONSerializeError => {
+                "Cannot serialize the provided JSON for creating local config file".to_string()
+            }
+            ErrorRuntime::ValueNotParsableToU16(value) => format!(
+                "Value {} is not parsable. Please provide a number between 0 and 65535",
+                name(value)
+            ),
+            ErrorRuntime::ValueNotParsableToF64(value) => format!(
+                "Value {} is not parsable. Please provide a number, e.g. 21.5",
+                name(value)
+            ),
+            ErrorRuntime::ValueNotParsableToBool(value) => format!(
+                "Value {} is not parsable. Please provide either: true or false",
+                name(value)
+            ),
+            ErrorRuntime::ValueNotParsableToInteger(value) => format!(
+                "Value {} is not parsable. Please provide an integer in the range of the datatype of the register, e.g. -5",
+                name(value)
+            ),
+            ErrorRuntime::ValueNotParsableToTimestamp(value) => format!(
+                "Value {} is not parsable. Please provide a unix time in milliseconds, e.g. 1767225600000",
+                name(value)
+            ),
+            ErrorRuntime::AuditLogReadError => {
+                "Cannot read the audit log. Please check the error logs for more information".to_string()
+            }
+            ErrorRuntime::AuthenticationRequired(_) => {
+                "This route requires authentication. Please send an API token as Authorization: Bearer <token>".to_string()
+            }
+            ErrorRuntime::AuthenticationNotValid(_) => {
+                "The API token or the user name and password are not valid".to_string()
+            }
+            ErrorRuntime::AuthScopeMissing(scope) => format!(
+                "The API token or user is not allowed to use this route. It requires the scope {}",
+                name(scope)
+            ),
+            ErrorRuntime::ClientRegisterWriteError(register) => format!(
+                "Register {} is not writable. Please check the error logs for more information",
+                name(register)
+            ),
+            ErrorRuntime::NoParametersProvided => {
+                "If you want to write a register, please provide a parameter with: register_name=value. Value must be between 0:65535".to_string()
+            }
+        }
+    }
+}
+// Most errors carry the name of the client, item or value which caused them
+fn name(item: &Option<String>) -> &str {
+    item.as_deref().unwrap_or("")
+}
 #[derive(Debug)]
 pub enum ErrorRuntimeNoRejection {
     InvalidIpAddress,
+    InvalidSerialSettings,
+    ProtocolNotSupported,
     CouldNotConnect,
-}
\ No newline at end of file
+    NotConnected,
+    ReconnectBackoff,
+    ModbusException,
+    TransportError,
+    ObjecttypeNotSupported,
+    ConnectTimeout,
+    ReadTimeout,
+    WriteTimeout,
+}
+impl ErrorRuntimeNoRejection {
+    /// Get the kind of the error as used in the labels of the exporter metrics
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The error
+    ///
+    /// # Returns
+    ///
+    /// * `&str` - connect, timeout, exception, transport or config
+    pub fn get_kind(&self) -> &'static str {
+        match self {
+            ErrorRuntimeNoRejection::InvalidIpAddress
+            | ErrorRuntimeNoRejection::InvalidSerialSettings
+            | ErrorRuntimeNoRejection::ProtocolNotSupported
+            | ErrorRuntimeNoRejection::ObjecttypeNotSupported => "config",
+            ErrorRuntimeNoRejection::CouldNotConnect
+            | ErrorRuntimeNoRejection::ReconnectBackoff => "connect",
+            ErrorRuntimeNoRejection::ConnectTimeout
+            | ErrorRuntimeNoRejection::ReadTimeout
+            | ErrorRuntimeNoRejection::WriteTimeout => "timeout",
+            ErrorRuntimeNoRejection::ModbusException => "exception",
+            ErrorRuntimeNoRejection::NotConnected | ErrorRuntimeNoRejection::TransportError => {
+                "transport"
+            }
+        }
+    }
+}
@@ -1,7 +1,10 @@
 pub mod clients;
+pub mod connections;
 pub mod errors;
 pub mod routes;
 pub mod prometheus;
 pub mod logging;
 pub mod utils;
 pub mod configuration;
+pub mod audit;
+pub mod auth;
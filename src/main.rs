@@ -1,11 +1,15 @@
+use modbus_prometheus_api_server::audit as Audit;
+use modbus_prometheus_api_server::auth as Auth;
 use modbus_prometheus_api_server::clients as Clients;
 use modbus_prometheus_api_server::configuration as Configuration;
+use modbus_prometheus_api_server::connections as Connections;
 use modbus_prometheus_api_server::errors as Errors;
 use modbus_prometheus_api_server::logging as CustomLog;
 use modbus_prometheus_api_server::prometheus as Prometheus;
 use modbus_prometheus_api_server::routes as Route;
 
 use env_logger::Env;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use warp::{http::Method, Filter};
@@ -17,6 +21,18 @@ async fn main() {
     // Set up logging
     env_logger::Builder::from_env(Env::default().default_filter_or(config.get_log_level())).init();
     let log_filter = warp::log::custom(|info| {
+        // Credentials of the Authorization header are not logged
+        let headers: Vec<_> = info
+            .request_headers()
+            .iter()
+            .map(|(name, value)| {
+                let redacted = warp::http::HeaderValue::from_static("[redacted]");
+                match name == warp::http::header::AUTHORIZATION {
+                    true => (name, redacted),
+                    false => (name, value.clone()),
+                }
+            })
+            .collect();
         log::info!(
             "{} {} {} {:?} from {} with {:?}",
             info.method(),
@@ -24,14 +40,21 @@ async fn main() {
             info.status(),
             info.elapsed(),
             info.remote_addr().unwrap(),
-            info.request_headers()
+            headers
         );
     });
     // Strat logging
     CustomLog::print_start(config.get_config_path().to_string());
     // Gloabl clients and prometheus registry
     let clients = Arc::new(Mutex::new(Clients::Clients::new(config.get_config_path())));
-    let prometheus_registry = Arc::new(Mutex::new(Prometheus::PrometheusMetrics::new()));
+    let prometheus_registry = Arc::new(Mutex::new(Prometheus::PrometheusMetrics::new(
+        config.get_metrics_mode(),
+        config.get_metrics_timestamps(),
+    )));
+    // Global modbus connections. Shared by the side thread and the write routes
+    let connections = Arc::new(Mutex::new(Connections::ConnectionManager::new(
+        config.get_request_policy(),
+    )));
     // Initializing clients and prometheus registry
     if let Err(e) = clients.lock().await.init() {
         log::error!("Error initializing clients: {:?}", e);
@@ -39,15 +62,49 @@ async fn main() {
     if let Err(e) = prometheus_registry.lock().await.init(clients.clone()).await {
         log::error!("Error initializing prometheus registry: {:?}", e);
     }
-    // Spawn a side thread for reading all modbus clients and set values in the local registers
-    tokio::spawn(Clients::read_data::read_data(
+    // Spawn a side thread for every modbus client, which reads the client and sets the values in the local registers
+    let pollers = Arc::new(Mutex::new(Clients::read_data::Pollers::new(
         prometheus_registry.clone(),
         clients.clone(),
+        connections.clone(),
         config.get_read_data_interval_ms() as u64,
-    ));
+    )));
+    pollers.lock().await.start_all().await;
+    // Audit log of all writes and client changes
+    let audit_log = Arc::new(Mutex::new(config.get_audit_log()));
     // Filter for Prometheus Registry. That means add the registry to the filter chain so it can be used as funtion parameter
     let prometheus_registry_filter = warp::any().map(move || prometheus_registry.clone());
     let clients_filter = warp::any().map(move || clients.clone());
+    let connections_filter = warp::any().map(move || connections.clone());
+    let pollers_filter = warp::any().map(move || pollers.clone());
+    let audit_log_filter = warp::any().map(move || audit_log.clone());
+    // Authentication with API tokens and basic auth. Every route requires a scope
+    let auth = match config.get_auth() {
+        Ok(auth) => Arc::new(auth),
+        Err(e) => {
+            log::error!("Server not started: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if !auth.is_enabled() {
+        log::warn!("No API tokens or basic auth users configured, every request is allowed");
+    }
+    let read_scope = Auth::with_scope(auth.clone(), Auth::SCOPE_READ);
+    let write_scope = Auth::with_scope(auth.clone(), Auth::SCOPE_WRITE);
+    let admin_scope = Auth::with_scope(auth.clone(), Auth::SCOPE_ADMIN);
+    // The audit log with the caller of the request, known by its token or user and its remote address
+    let audit_filter = audit_log_filter
+        .clone()
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::addr::remote())
+        .map(
+            move |log, authorization: Option<String>, remote: Option<SocketAddr>| {
+                Audit::AuditContext {
+                    log,
+                    caller: auth.get_caller(authorization.as_deref(), remote),
+                }
+            },
+        );
     // Service got started
     log::info!("Idle state...");
     /*
@@ -55,25 +112,35 @@ async fn main() {
     - POST /clients
     - GET /clients
     - DELETE /clients
+    - PUT/PATCH /clients/{name}
+    - GET /clients/{name}/registers[/{register}]
+    - GET /clients/{name}/coils[/{coil}]
     - GET /metrics
+    - GET /audit
+    - GET /openapi.json, /docs
     */
     let metrics_route = warp::get()
         .and(warp::path("metrics"))
         .and(warp::path::end())
+        .and(read_scope.clone())
         .and(prometheus_registry_filter.clone())
         .and_then(Route::metrics_handler);
 
     let create_client = warp::post()
         .and(warp::path("clients"))
         .and(warp::path::end())
+        .and(admin_scope.clone())
         .and(prometheus_registry_filter.clone())
         .and(clients_filter.clone())
+        .and(pollers_filter.clone())
+        .and(audit_filter.clone())
         .and(warp::body::json())
         .and_then(Route::create_client);
 
     let get_clients = warp::get()
         .and(warp::path("clients"))
         .and(warp::path::end())
+        .and(read_scope.clone())
         .and(clients_filter.clone())
         .and_then(Route::get_clients);
 
@@ -81,6 +148,7 @@ async fn main() {
         .and(warp::path("clients"))
         .and(warp::path::param::<String>())
         .and(warp::path::end())
+        .and(read_scope.clone())
         .and(clients_filter.clone())
         .and_then(Route::get_client);
 
@@ -88,41 +156,200 @@ async fn main() {
         .and(warp::path("clients"))
         .and(warp::path::param::<String>())
         .and(warp::path::end())
+        .and(admin_scope.clone())
         .and(clients_filter.clone())
         .and(prometheus_registry_filter.clone())
+        .and(connections_filter.clone())
+        .and(pollers_filter.clone())
+        .and(audit_filter.clone())
         .and_then(Route::delete_client);
 
+    let update_client = warp::put()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(admin_scope.clone())
+        .and(clients_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(connections_filter.clone())
+        .and(pollers_filter.clone())
+        .and(audit_filter.clone())
+        .and(warp::body::json())
+        .and_then(Route::update_client);
+
+    let patch_client = warp::patch()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(admin_scope.clone())
+        .and(clients_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(connections_filter.clone())
+        .and(pollers_filter.clone())
+        .and(audit_filter.clone())
+        .and(warp::body::json())
+        .and_then(Route::patch_client);
+
+    let get_registers = warp::get()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("registers"))
+        .and(warp::path::end())
+        .and(read_scope.clone())
+        .and(warp::query())
+        .and(clients_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(connections_filter.clone())
+        .and_then(Route::get_registers);
+
+    let get_register = warp::get()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("registers"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(read_scope.clone())
+        .and(warp::query())
+        .and(clients_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(connections_filter.clone())
+        .and_then(Route::get_register);
+
+    let get_coils = warp::get()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("coils"))
+        .and(warp::path::end())
+        .and(read_scope.clone())
+        .and(warp::query())
+        .and(clients_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(connections_filter.clone())
+        .and_then(Route::get_coils);
+
+    let get_coil = warp::get()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("coils"))
+        .and(warp::path::param::<String>())
+        .and(warp::path::end())
+        .and(read_scope.clone())
+        .and(warp::query())
+        .and(clients_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(connections_filter.clone())
+        .and_then(Route::get_coil);
+
     let set_reg = warp::put()
         .and(warp::path("clients"))
         .and(warp::path::param::<String>())
         .and(warp::path("set-register"))
         .and(warp::path::end())
+        .and(write_scope.clone())
         .and(warp::query())
         .and(clients_filter.clone())
+        .and(connections_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(audit_filter.clone())
         .and_then(Route::write_register);
 
+    let set_bitfield = warp::put()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("set-bitfield"))
+        .and(warp::path::end())
+        .and(write_scope.clone())
+        .and(warp::query())
+        .and(clients_filter.clone())
+        .and(connections_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(audit_filter.clone())
+        .and_then(Route::write_bitfield);
+
     let set_coil = warp::put()
         .and(warp::path("clients"))
         .and(warp::path::param::<String>())
         .and(warp::path("set-coil"))
         .and(warp::path::end())
+        .and(write_scope.clone())
         .and(warp::query())
         .and(clients_filter.clone())
+        .and(connections_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(audit_filter.clone())
         .and_then(Route::write_coil);
 
+    let set_values = warp::put()
+        .and(warp::path("clients"))
+        .and(warp::path::param::<String>())
+        .and(warp::path("set-values"))
+        .and(warp::path::end())
+        .and(write_scope.clone())
+        .and(clients_filter.clone())
+        .and(connections_filter.clone())
+        .and(prometheus_registry_filter.clone())
+        .and(audit_filter.clone())
+        .and(warp::body::json())
+        .and_then(Route::write_values);
+
+    let get_audit = warp::get()
+        .and(warp::path("audit"))
+        .and(warp::path::end())
+        .and(admin_scope.clone())
+        .and(warp::query())
+        .and(audit_log_filter.clone())
+        .and_then(Route::get_audit);
+
+    let openapi = warp::get()
+        .and(warp::path("openapi.json"))
+        .and(warp::path::end())
+        .and(read_scope.clone())
+        .and_then(Route::openapi::openapi_handler);
+
+    let api_docs_ui = config.get_api_docs_ui();
+    let docs = warp::get()
+        .and(warp::path("docs"))
+        .and(warp::path::end())
+        .and(read_scope.clone())
+        .and(warp::any().map(move || api_docs_ui))
+        .and_then(Route::openapi::docs_handler);
+
     let cors = warp::cors()
-        .allow_any_origin()
         .allow_header("not-in-the-request")
         .allow_header("content-type")
-        .allow_methods(&[Method::PUT, Method::DELETE, Method::GET, Method::POST]);
+        .allow_header("authorization")
+        .allow_methods(&[
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+            Method::GET,
+            Method::POST,
+        ]);
+    // No cross-origin access without configured origins, any origin only with "*"
+    let origins = config.get_cors_allowed_origins();
+    let cors = match origins.iter().any(|origin| origin == "*") {
+        true => cors.allow_any_origin(),
+        false => cors.allow_origins(origins.iter().map(String::as_str)),
+    };
 
     let routes = get_clients
         .or(create_client)
         .or(metrics_route)
         .or(get_client)
         .or(delete_client)
+        .or(update_client)
+        .or(patch_client)
+        .or(get_registers)
+        .or(get_register)
+        .or(get_coils)
+        .or(get_coil)
         .or(set_reg)
+        .or(set_bitfield)
         .or(set_coil)
+        .or(set_values)
+        .or(get_audit)
+        .or(openapi)
+        .or(docs)
         .with(cors)
         .with(log_filter)
         .recover(Errors::return_error);
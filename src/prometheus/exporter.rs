@@ -0,0 +1,226 @@
+use prometheus::core::{Collector, MetricVec, MetricVecBuilder};
+use prometheus::{GaugeVec, HistogramOpts, HistogramVec, IntCounterVec, Opts, Registry};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Result label of a successful write
+pub const WRITE_RESULT_OK: &str = "ok";
+/// Kind label of a register or coil which was read, but could not be split from the response
+pub const READ_ERROR_SHORT_RESPONSE: &str = "short_response";
+/// Kind label of a register which was read, but could not be decoded
+pub const READ_ERROR_DECODE: &str = "decode";
+
+/// ExporterMetrics struct
+///
+/// Metrics about the exporter itself. They show if the devices are reachable, how long the poll cycles take and
+/// how often reads and writes fail. The metrics of a client are removed when the client is unregistered.
+///
+#[derive(Clone, Debug)]
+pub struct ExporterMetrics {
+    client_up: GaugeVec,
+    poll_duration: HistogramVec,
+    read_errors: IntCounterVec,
+    writes: IntCounterVec,
+    last_success: GaugeVec,
+}
+impl ExporterMetrics {
+    /// Create the exporter metrics and register them in the registry
+    ///
+    /// # Arguments
+    ///
+    /// * `registry` - The registry of the server
+    ///
+    /// # Returns
+    ///
+    /// * `Self` - The new ExporterMetrics struct
+    pub fn new(registry: &Registry) -> Self {
+        let metrics = Self {
+            client_up: GaugeVec::new(
+                Opts::new(
+                    "modbus_client_up",
+                    "1 if the last poll cycle reached the modbus client, 0 otherwise",
+                ),
+                &["client"],
+            )
+            .unwrap(),
+            poll_duration: HistogramVec::new(
+                HistogramOpts::new(
+                    "modbus_poll_duration_seconds",
+                    "Duration of the poll cycles of a modbus client",
+                ),
+                &["client"],
+            )
+            .unwrap(),
+            read_errors: IntCounterVec::new(
+                Opts::new(
+                    "modbus_read_errors_total",
+                    "Failed reads of registers and coils by kind of error",
+                ),
+                &["client", "register", "kind"],
+            )
+            .unwrap(),
+            writes: IntCounterVec::new(
+                Opts::new(
+                    "modbus_write_total",
+                    "Writes to registers and coils by result",
+                ),
+                &["client", "result"],
+            )
+            .unwrap(),
+            last_success: GaugeVec::new(
+                Opts::new(
+                    "modbus_last_success_timestamp",
+                    "Unix time of the last poll cycle which reached the modbus client",
+                ),
+                &["client"],
+            )
+            .unwrap(),
+        };
+        let collectors: [Box<dyn Collector>; 5] = [
+            Box::new(metrics.client_up.clone()),
+            Box::new(metrics.poll_duration.clone()),
+            Box::new(metrics.read_errors.clone()),
+            Box::new(metrics.writes.clone()),
+            Box::new(metrics.last_success.clone()),
+        ];
+        for collector in collectors {
+            if let Err(e) = registry.register(collector) {
+                log::error!("Could not register exporter metrics. Error: {:?}", e);
+            }
+        }
+        metrics
+    }
+
+    /// Record a finished poll cycle of a client
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The ExporterMetrics struct
+    /// * `client` - The name of the client
+    /// * `duration` - The duration of the poll cycle
+    /// * `up` - If the client was reachable in the poll cycle
+    pub fn observe_poll(&self, client: &str, duration: Duration, up: bool) {
+        self.poll_duration
+            .with_label_values(&[client])
+            .observe(duration.as_secs_f64());
+        if up {
+            self.client_up.with_label_values(&[client]).set(1.0);
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            self.last_success
+                .with_label_values(&[client])
+                .set(now.as_secs_f64());
+        } else {
+            self.client_up.with_label_values(&[client]).set(0.0);
+        }
+    }
+
+    /// Count a failed read of a register or coil
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The ExporterMetrics struct
+    /// * `client` - The name of the client
+    /// * `register` - The name of the register or coil
+    /// * `kind` - The kind of the error
+    pub fn inc_read_error(&self, client: &str, register: &str, kind: &str) {
+        self.read_errors
+            .with_label_values(&[client, register, kind])
+            .inc();
+    }
+
+    /// Count a write to a register or coil
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The ExporterMetrics struct
+    /// * `client` - The name of the client
+    /// * `result` - WRITE_RESULT_OK or the kind of the error
+    pub fn inc_write(&self, client: &str, result: &str) {
+        self.writes.with_label_values(&[client, result]).inc();
+    }
+
+    /// Remove all metrics of a client
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The ExporterMetrics struct
+    /// * `client` - The name of the client
+    pub fn remove_client(&self, client: &str) {
+        remove_client_series(&self.client_up, client);
+        remove_client_series(&self.poll_duration, client);
+        remove_client_series(&self.read_errors, client);
+        remove_client_series(&self.writes, client);
+        remove_client_series(&self.last_success, client);
+    }
+}
+
+// Remove every series of the vector which has the label client set to the name of the client
+fn remove_client_series<T: MetricVecBuilder>(vec: &MetricVec<T>, client: &str) {
+    for family in vec.collect() {
+        for metric in family.get_metric() {
+            let labels: HashMap<&str, &str> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            if labels.get("client") == Some(&client) {
+                let _ = vec.remove(&labels);
+            }
+        }
+    }
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_exporter {
+    use super::*;
+    use prometheus::Encoder;
+
+    fn encode(registry: &Registry) -> String {
+        let mut buffer = Vec::new();
+        prometheus::TextEncoder::new()
+            .encode(&registry.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_exporter_metrics() {
+        let registry = Registry::new();
+        let metrics = ExporterMetrics::new(&registry);
+        metrics.observe_poll("meter", Duration::from_millis(20), false);
+        metrics.inc_read_error("meter", "power", "timeout");
+        metrics.inc_read_error("meter", "power", "timeout");
+        metrics.inc_write("meter", WRITE_RESULT_OK);
+        let text = encode(&registry);
+        assert!(text.contains(r#"modbus_client_up{client="meter"} 0"#));
+        assert!(text.contains(r#"modbus_poll_duration_seconds_count{client="meter"} 1"#));
+        assert!(text.contains(
+            r#"modbus_read_errors_total{client="meter",kind="timeout",register="power"} 2"#
+        ));
+        assert!(text.contains(r#"modbus_write_total{client="meter",result="ok"} 1"#));
+        assert!(!text.contains("modbus_last_success_timestamp{"));
+        metrics.observe_poll("meter", Duration::from_millis(20), true);
+        let text = encode(&registry);
+        assert!(text.contains(r#"modbus_client_up{client="meter"} 1"#));
+        assert!(text.contains(r#"modbus_last_success_timestamp{client="meter"}"#));
+    }
+
+    #[test]
+    fn test_exporter_metrics_remove_client() {
+        let registry = Registry::new();
+        let metrics = ExporterMetrics::new(&registry);
+        for client in ["meter", "inverter"] {
+            metrics.observe_poll(client, Duration::from_millis(20), true);
+            metrics.inc_read_error(client, "power", "exception");
+            metrics.inc_write(client, "timeout");
+        }
+        metrics.remove_client("meter");
+        let text = encode(&registry);
+        assert!(!text.contains(r#"client="meter""#));
+        assert!(text.contains(r#"modbus_client_up{client="inverter"} 1"#));
+        assert!(text.contains(r#"modbus_write_total{client="inverter",result="timeout"} 1"#));
+    }
+}
@@ -1,20 +1,83 @@
-use prometheus::{Registry};
+use prometheus::core::{Collector, Desc};
+use prometheus::proto::{Metric, MetricFamily};
+use prometheus::{GaugeVec, Opts, Registry};
+use std::collections::{BTreeMap, HashSet};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::{collections::HashMap, sync::{Arc}};
 use tokio::sync::Mutex;
-use crate::clients::{Clients, Client};
+use crate::clients::staleness::{STALE_POLICY_NAN, STALE_POLICY_REMOVE};
+use crate::clients::{Clients, Client, Coil, Register};
 use crate::errors::impls::ErrorRuntime;
+use exporter::ExporterMetrics;
+
+pub mod exporter;
+
+/// Metrics with labels. All registers are exported as modbus_register_value, all coils as modbus_coil_value
+pub const METRICS_MODE_LABELED: &str = "labeled";
+/// Metrics without labels named {client}_{register}. Compatibility mode for existing dashboards
+pub const METRICS_MODE_COMPAT: &str = "compat";
+/// Names of the labels which are set by the server. Static labels of the config can not use them
+pub const RESERVED_LABELS: [&str; 9] = [
+    "client",
+    "register",
+    "coil",
+    "objecttype",
+    "address",
+    "unit_id",
+    "unit",
+    "bitfield",
+    "state",
+];
+
+// The registers and coils whose metrics were changed by an update of a client
+#[derive(Default)]
+struct ItemChanges<'a> {
+    removed_registers: Vec<&'a Register>,
+    removed_coils: Vec<&'a Coil>,
+    added_registers: Vec<&'a Register>,
+    added_coils: Vec<&'a Coil>,
+}
 
 // The struct hold the registry and the metrics. Metrics are stored in a vector and can be added or reduced.
 #[derive(Clone, Debug)]
 pub struct PrometheusMetrics {
     pub registry: Registry,
+    /// One gauge per register and coil. Named {client}_{register} in both modes
     pub counters: HashMap<String, prometheus::Gauge>,
+    metrics_mode: String,
+    /// The labeled metrics of every client
+    collectors: HashMap<String, ClientCollector>,
+    /// Metrics about the exporter itself, e.g. if the clients are reachable
+    pub exporter: ExporterMetrics,
+    /// Add the time of the last update as timestamp to the series of the registers and coils
+    timestamps: bool,
+    /// Time of the last update of every gauge in counters
+    last_updated: HashMap<String, SystemTime>,
+    /// Gauges which are removed from the exposition until their next update
+    stale: HashSet<String>,
+    /// Gauges of the bitfields of every register. They become stale together with their register
+    bitfields: HashMap<String, Vec<String>>,
+    /// State sets of the registers with states. They become stale together with their register
+    states: HashMap<String, StateSet>,
+    /// Device info metric of every client with string registers
+    infos: HashMap<String, DeviceInfo>,
 }
 impl PrometheusMetrics {
-    pub fn new() -> Self {
+    pub fn new(metrics_mode: &str, timestamps: bool) -> Self {
+        let registry = Registry::new();
+        let exporter = ExporterMetrics::new(&registry);
         Self {
-            registry: Registry::new(),
+            registry,
             counters: HashMap::new(),
+            metrics_mode: metrics_mode.to_string(),
+            collectors: HashMap::new(),
+            exporter,
+            timestamps,
+            last_updated: HashMap::new(),
+            stale: HashSet::new(),
+            bitfields: HashMap::new(),
+            states: HashMap::new(),
+            infos: HashMap::new(),
         }
     }
 
@@ -25,88 +88,1330 @@ impl PrometheusMetrics {
             log::warn!("No clients initialized. Please initialize clients first.");
             return Ok(());
         } else {
-            for (client_name, client) in clients.clients.iter() {
-                for register in client.registers.iter() {
-                    let tmp_name = format!("{}_{}", client_name, register.name);
-                    let tmp_help = format!("{} {}", register.datatype, register.objecttype);
-                    let tmp_gauge = match prometheus::Gauge::new(&tmp_name, &tmp_help){
-                        Ok(gauge) => gauge,
-                        Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
-                    };
-                    if let Err(_) = self.registry.register(Box::new(tmp_gauge.clone())){
-                        return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
+            for client in clients.clients.values() {
+                self.register_client(client)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn register_client(&mut self, client: &Client) -> Result<(), ErrorRuntime>{
+        // The labeled metrics of a client are children of its collector. The compat metrics are registered one by one
+        if self.metrics_mode == METRICS_MODE_LABELED {
+            self.register_collector(client)?;
+        } else {
+            self.register_info_compat(client)?;
+        }
+        for register in client.registers.iter() {
+            self.register_register(client, register)?;
+        }
+        for coil in client.coils.iter() {
+            self.register_coil(client, coil)?;
+        }
+
+        log::debug!("New registry: {:?}", self.registry);
+
+        Ok(())
+    }
+
+    pub fn unregister_client(&mut self, client: &Client) -> Result<(), ErrorRuntime>{
+        self.exporter.remove_client(&client.name);
+        self.unregister_client_metrics(client)
+    }
+
+    /// Update the metrics of a client whose config was replaced
+    ///
+    /// Only the metrics of removed, added or changed registers and coils are unregistered or registered, all other
+    /// metrics keep their values. If the label names of the labeled metrics change, all series are registered again
+    /// and the series of unchanged registers and coils keep their values.
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The PrometheusMetrics struct
+    /// * `old` - The replaced client
+    /// * `new` - The new client with the same name
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), ErrorRuntime>` - The first error. The metrics of the old client are restored on errors
+    pub fn update_client(&mut self, old: &Client, new: &Client) -> Result<(), ErrorRuntime> {
+        if self.metrics_mode == METRICS_MODE_LABELED {
+            let collector = ClientCollector::new(new)?;
+            let label_names_changed = match self.collectors.get(&old.name) {
+                Some(old_collector) => !old_collector.has_same_label_names(&collector),
+                None => true,
+            };
+            if label_names_changed {
+                let replaced = self.replace_client(old, new);
+                if replaced.is_err() {
+                    self.restore_client_labeled(old, new);
+                }
+                return replaced;
+            }
+        }
+        let strings_changed = self.metrics_mode == METRICS_MODE_COMPAT
+            && get_string_names(old) != get_string_names(new);
+        let texts = self.get_info_texts(&old.name);
+        if strings_changed {
+            self.unregister_info_compat(old)?;
+            if let Err(e) = self.register_info_compat(new) {
+                self.restore_info_compat(old, new, texts);
+                return Err(e);
+            }
+            self.restore_info_texts(new, texts.clone());
+        }
+        let mut changes = ItemChanges::default();
+        let updated = self.update_items(old, new, &mut changes);
+        if updated.is_err() {
+            self.undo_item_changes(old, new, changes);
+            if strings_changed {
+                self.restore_info_compat(old, new, texts);
+            }
+        }
+        updated
+    }
+
+    pub fn update_gauge(&mut self, name: &str, value: f64) {
+        // write value to hash map entry
+        if let Some(metric) = self.counters.get_mut(name) {
+            metric.set(value);
+            self.last_updated
+                .insert(name.to_string(), SystemTime::now());
+            self.stale.remove(name);
+        }
+    }
+
+    /// Update the text of a string register in the device info metric of its client
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The PrometheusMetrics struct
+    /// * `client` - The name of the client
+    /// * `register` - The name of the string register
+    /// * `text` - The decoded text of the register
+    pub fn update_info(&mut self, client: &str, register: &str, text: &str) {
+        if let Some(info) = self.infos.get_mut(client) {
+            info.set_label(register, text);
+        }
+    }
+
+    /// Update the state set of a register
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The PrometheusMetrics struct
+    /// * `name` - The name of the gauge of the register: {client}_{register}
+    /// * `value` - The integer value of the register. The series of the matching state is set to 1, all others to 0
+    pub fn update_state(&mut self, name: &str, value: Option<i64>) {
+        if let Some(state_set) = self.states.get(name) {
+            for (state_value, gauge) in state_set.gauges.iter() {
+                let current = Some(*state_value) == value;
+                gauge.set(if current { 1.0 } else { 0.0 });
+            }
+        }
+    }
+
+    /// Mark the value of a register or coil as stale. The next update of the gauge ends the stale state
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The PrometheusMetrics struct
+    /// * `name` - The name of the gauge: {client}_{register}
+    /// * `policy` - The stale policy of the client. nan sets the gauge to NaN, remove hides it from the exposition
+    pub fn mark_stale(&mut self, name: &str, policy: &str) {
+        let gauge = match self.counters.get(name) {
+            Some(gauge) => gauge,
+            None => return,
+        };
+        match policy {
+            // The NaN is a new sample, so it must not carry the timestamp of the last value
+            STALE_POLICY_NAN => {
+                gauge.set(f64::NAN);
+                self.last_updated.remove(name);
+                if let Some(state_set) = self.states.get(name) {
+                    for (_, gauge) in state_set.gauges.iter() {
+                        gauge.set(f64::NAN);
                     }
-                    self.counters.insert(tmp_name, tmp_gauge);
                 }
-                for coil in client.coils.iter() {
-                    let tmp_name = format!("{}_{}", client.name, coil.name);
-                    let tmp_help = format!("{}", coil.objecttype);
-                    let tmp_gauge = match prometheus::Gauge::new(&tmp_name, &tmp_help){
-                        Ok(gauge) => gauge,
-                        Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
+            }
+            STALE_POLICY_REMOVE => {
+                self.stale.insert(name.to_string());
+            }
+            _ => {}
+        }
+        for bitfield_name in self.bitfields.get(name).cloned().unwrap_or_default() {
+            self.mark_stale(&bitfield_name, policy);
+        }
+    }
+
+    /// Gather all metrics of the registry for the exposition
+    ///
+    /// # Arguments
+    ///
+    /// * `self` - The PrometheusMetrics struct
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<MetricFamily>` - The metric families without the stale series. If timestamps are enabled, the series
+    ///   of the registers and coils carry the time of their last update
+    pub fn gather(&self) -> Vec<MetricFamily> {
+        let mut families = self.registry.gather();
+        if self.stale.is_empty() && !self.timestamps {
+            return families;
+        }
+        for family in families.iter_mut() {
+            let family_name = family.get_name().to_string();
+            let metrics: Vec<Metric> = family
+                .take_metric()
+                .into_iter()
+                .filter_map(|mut metric| {
+                    let key = match self.get_series_key(&family_name, &metric) {
+                        Some(key) => key,
+                        None => return Some(metric),
                     };
-                    if let Err(_) = self.registry.register(Box::new(tmp_gauge.clone())){
-                        return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
+                    if self.stale.contains(&key) {
+                        return None;
                     }
-                    self.counters.insert(tmp_name, tmp_gauge);
+                    match self.last_updated.get(&key) {
+                        Some(last_updated) if self.timestamps => {
+                            let timestamp =
+                                last_updated.duration_since(UNIX_EPOCH).unwrap_or_default();
+                            metric.set_timestamp_ms(timestamp.as_millis() as i64);
+                        }
+                        _ => {}
+                    }
+                    Some(metric)
+                })
+                .collect();
+            family.set_metric(metrics.into());
+        }
+        // Families without any series can not be encoded
+        families.retain(|family| !family.get_metric().is_empty());
+        families
+    }
+
+    // Get the key of the gauge in counters for a gathered series of a register or coil
+    fn get_series_key(&self, family_name: &str, metric: &Metric) -> Option<String> {
+        if self.metrics_mode == METRICS_MODE_COMPAT {
+            if metric.get_label().is_empty() && self.counters.contains_key(family_name) {
+                return Some(family_name.to_string());
+            }
+            // The series of a state set share the key of their register
+            return family_name
+                .strip_suffix("_state")
+                .filter(|name| self.states.contains_key(*name))
+                .map(String::from);
+        }
+        let label = |name: &str| {
+            metric
+                .get_label()
+                .iter()
+                .find(|label| label.get_name() == name)
+                .map(|label| label.get_value().to_string())
+        };
+        match family_name {
+            "modbus_register_value" | "modbus_register_state" => {
+                Some(format!("{}_{}", label("client")?, label("register")?))
+            }
+            "modbus_coil_value" => Some(format!("{}_{}", label("client")?, label("coil")?)),
+            "modbus_register_bitfield_value" => Some(format!(
+                "{}_{}_{}",
+                label("client")?,
+                label("register")?,
+                label("bitfield")?
+            )),
+            _ => None,
+        }
+    }
+
+    // Register all metrics of a client again, because the label names of its labeled metrics changed.
+    // The series of the registers and coils which did not change keep their values
+    // Unregister the changed registers and coils of the old client and register the ones of the new client.
+    // Every successful step is recorded in changes, so a failed update can be undone
+    fn update_items<'a>(
+        &mut self,
+        old: &'a Client,
+        new: &'a Client,
+        changes: &mut ItemChanges<'a>,
+    ) -> Result<(), ErrorRuntime> {
+        for register in old.registers.iter() {
+            if self.is_register_changed(old, new, register) {
+                self.unregister_register(old, register)?;
+                changes.removed_registers.push(register);
+            }
+        }
+        for coil in old.coils.iter() {
+            if self.is_coil_changed(old, new, coil) {
+                self.unregister_coil(old, coil)?;
+                changes.removed_coils.push(coil);
+            }
+        }
+        for register in new.registers.iter() {
+            if self.is_register_changed(new, old, register) {
+                self.register_register(new, register)?;
+                changes.added_registers.push(register);
+            }
+        }
+        for coil in new.coils.iter() {
+            if self.is_coil_changed(new, old, coil) {
+                self.register_coil(new, coil)?;
+                changes.added_coils.push(coil);
+            }
+        }
+        Ok(())
+    }
+
+    // Undo the steps of a failed update_items. Only recorded steps are undone, so metrics of other clients with the
+    // same name as a failed item are kept
+    fn undo_item_changes(&mut self, old: &Client, new: &Client, changes: ItemChanges) {
+        let mut undone = Vec::new();
+        for register in changes.added_registers {
+            undone.push(self.unregister_register(new, register));
+        }
+        for coil in changes.added_coils {
+            undone.push(self.unregister_coil(new, coil));
+        }
+        for register in changes.removed_registers {
+            undone.push(self.register_register(old, register));
+        }
+        for coil in changes.removed_coils {
+            undone.push(self.register_coil(old, coil));
+        }
+        if undone.iter().any(|undone| undone.is_err()) {
+            log::error!("The metrics of client {} could not be restored", old.name);
+        }
+    }
+
+    // Register the device info metric of the old client again after a failed update in the compat mode
+    fn restore_info_compat(&mut self, old: &Client, new: &Client, texts: BTreeMap<String, String>) {
+        let _ = self.unregister_info_compat(new);
+        if self.register_info_compat(old).is_err() {
+            log::error!(
+                "The device info of client {} could not be restored",
+                old.name
+            );
+        }
+        self.restore_info_texts(old, texts);
+    }
+
+    // Register all metrics of the old client again after a failed replace_client. The collector of the client holds
+    // all its series, so removing it removes the metrics of both configs
+    fn restore_client_labeled(&mut self, old: &Client, new: &Client) {
+        for client in [old, new] {
+            for register in client.registers.iter() {
+                let key = format!("{}_{}", client.name, register.name);
+                for bitfield_key in get_bitfield_names(client, register) {
+                    self.counters.remove(&bitfield_key);
+                    self.last_updated.remove(&bitfield_key);
+                    self.stale.remove(&bitfield_key);
+                }
+                self.counters.remove(&key);
+                self.last_updated.remove(&key);
+                self.stale.remove(&key);
+                self.states.remove(&key);
+                self.bitfields.remove(&key);
+            }
+            for coil in client.coils.iter() {
+                let key = format!("{}_{}", client.name, coil.name);
+                self.counters.remove(&key);
+                self.last_updated.remove(&key);
+                self.stale.remove(&key);
+            }
+        }
+        self.infos.remove(&old.name);
+        if let Some(collector) = self.collectors.remove(&old.name) {
+            let _ = self.registry.unregister(Box::new(collector));
+        }
+        if self.register_client(old).is_err() {
+            log::error!("The metrics of client {} could not be restored", old.name);
+        }
+    }
+
+    fn replace_client(&mut self, old: &Client, new: &Client) -> Result<(), ErrorRuntime> {
+        let mut keys = Vec::new();
+        let mut state_keys = Vec::new();
+        for register in new.registers.iter() {
+            if !self.is_register_changed(new, old, register) {
+                keys.push(format!("{}_{}", new.name, register.name));
+                keys.extend(get_bitfield_names(new, register));
+                state_keys.push(format!("{}_{}", new.name, register.name));
+            }
+        }
+        for coil in new.coils.iter() {
+            if !self.is_coil_changed(new, old, coil) {
+                keys.push(format!("{}_{}", new.name, coil.name));
+            }
+        }
+        let values: Vec<(String, f64, Option<SystemTime>, bool)> = keys
+            .into_iter()
+            .filter_map(|key| {
+                let value = self.counters.get(&key)?.get();
+                let last_updated = self.last_updated.get(&key).copied();
+                let stale = self.stale.contains(&key);
+                Some((key, value, last_updated, stale))
+            })
+            .collect();
+        let state_values: Vec<(String, Vec<f64>)> = state_keys
+            .into_iter()
+            .filter_map(|key| {
+                let state_set = self.states.get(&key)?;
+                let values = state_set.gauges.iter().map(|(_, gauge)| gauge.get());
+                Some((key, values.collect()))
+            })
+            .collect();
+        let texts = self.get_info_texts(&old.name);
+        self.unregister_client_metrics(old)?;
+        self.register_client(new)?;
+        for (key, value, last_updated, stale) in values {
+            if let Some(gauge) = self.counters.get(&key) {
+                gauge.set(value);
+            }
+            if let Some(last_updated) = last_updated {
+                self.last_updated.insert(key.clone(), last_updated);
+            }
+            if stale {
+                self.stale.insert(key);
+            }
+        }
+        for (key, values) in state_values {
+            if let Some(state_set) = self.states.get(&key) {
+                for ((_, gauge), value) in state_set.gauges.iter().zip(values) {
+                    gauge.set(value);
                 }
             }
         }
+        self.restore_info_texts(new, texts);
         Ok(())
     }
 
-    pub fn register_client(&mut self, client: &Client) -> Result<(), ErrorRuntime>{
-        // add all registers to the registry
+    // Unregister the metrics of all registers and coils of a client
+    fn unregister_client_metrics(&mut self, client: &Client) -> Result<(), ErrorRuntime> {
         for register in client.registers.iter() {
-            let tmp_name = format!("{}_{}", client.name, register.name);
-            let tmp_help = format!("{} {}", register.datatype, register.objecttype);
-            let tmp_gauge = match prometheus::Gauge::new(&tmp_name, &tmp_help){
+            self.unregister_register(client, register)?;
+        }
+        for coil in client.coils.iter() {
+            self.unregister_coil(client, coil)?;
+        }
+        if self.metrics_mode == METRICS_MODE_COMPAT {
+            return self.unregister_info_compat(client);
+        }
+        self.infos.remove(&client.name);
+        let collector = match self.collectors.remove(&client.name) {
+            Some(collector) => collector,
+            None => return Err(ErrorRuntime::PrometheusErrorGaugeRemove),
+        };
+        if self.registry.unregister(Box::new(collector)).is_err() {
+            return Err(ErrorRuntime::PrometheusErrorRegistryUnregister);
+        }
+        Ok(())
+    }
+
+    // Check if the metrics of a register differ from the metrics of the register with the same name of the other
+    // client. In the labeled mode the labels of the clients are part of the metrics
+    fn is_register_changed(&self, client: &Client, other: &Client, register: &Register) -> bool {
+        match other.get_register_by_name(&register.name) {
+            Some(other_register) => {
+                !register.has_same_config(other_register)
+                    || (self.metrics_mode == METRICS_MODE_LABELED
+                        && get_register_labels(client, register)
+                            != get_register_labels(other, other_register))
+            }
+            None => true,
+        }
+    }
+
+    // Same as is_register_changed for coils
+    fn is_coil_changed(&self, client: &Client, other: &Client, coil: &Coil) -> bool {
+        match other.get_coil_by_name(&coil.name) {
+            Some(other_coil) => {
+                !coil.has_same_config(other_coil)
+                    || (self.metrics_mode == METRICS_MODE_LABELED
+                        && get_coil_labels(client, coil) != get_coil_labels(other, other_coil))
+            }
+            None => true,
+        }
+    }
+
+    // Register one collector per client. Every register and coil is a child of the gauge vectors of the client
+    fn register_collector(&mut self, client: &Client) -> Result<(), ErrorRuntime> {
+        let collector = ClientCollector::new(client)?;
+        if self.registry.register(Box::new(collector.clone())).is_err() {
+            return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
+        }
+        if !get_string_names(client).is_empty() {
+            let labels = BTreeMap::from([("client".to_string(), client.name.clone())]);
+            self.infos.insert(
+                client.name.clone(),
+                DeviceInfo::new(collector.info.clone(), labels),
+            );
+        }
+        self.collectors.insert(client.name.clone(), collector);
+        Ok(())
+    }
+
+    // Register the device info metric {client}_device_info of a client with string registers
+    fn register_info_compat(&mut self, client: &Client) -> Result<(), ErrorRuntime> {
+        let string_names = get_string_names(client);
+        if string_names.is_empty() {
+            return Ok(());
+        }
+        let info_vec = match GaugeVec::new(
+            Opts::new(
+                format!("{}_device_info", client.name),
+                format!("String registers of {}", client.name),
+            ),
+            &string_names,
+        ) {
+            Ok(vec) => vec,
+            Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
+        };
+        if self.registry.register(Box::new(info_vec.clone())).is_err() {
+            return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
+        }
+        let labels = BTreeMap::new();
+        self.infos
+            .insert(client.name.clone(), DeviceInfo::new(info_vec, labels));
+        Ok(())
+    }
+
+    fn unregister_info_compat(&mut self, client: &Client) -> Result<(), ErrorRuntime> {
+        if let Some(info) = self.infos.remove(&client.name) {
+            if self.registry.unregister(Box::new(info.vec)).is_err() {
+                return Err(ErrorRuntime::PrometheusErrorRegistryUnregister);
+            }
+        }
+        Ok(())
+    }
+
+    // Texts of the string registers of a client in its device info metric
+    fn get_info_texts(&self, client: &str) -> BTreeMap<String, String> {
+        match self.infos.get(client) {
+            Some(info) => info.labels.clone(),
+            None => BTreeMap::new(),
+        }
+    }
+
+    // Set the texts of the string registers of the client, which are still string registers
+    fn restore_info_texts(&mut self, client: &Client, texts: BTreeMap<String, String>) {
+        let string_names = get_string_names(client);
+        for (register, text) in texts {
+            if string_names.contains(&register.as_str()) {
+                self.update_info(&client.name, &register, &text);
+            }
+        }
+    }
+
+    // Register the metrics of a register: its gauge, the gauges of its bitfields and its state set.
+    // String registers are labels of the device info metric of their client
+    fn register_register(&mut self, client: &Client, register: &Register) -> Result<(), ErrorRuntime> {
+        let names = get_bitfield_names(client, register);
+        if !names.is_empty() {
+            self.bitfields
+                .insert(format!("{}_{}", client.name, register.name), names);
+        }
+        if register.is_string() {
+            return Ok(());
+        }
+        if self.metrics_mode == METRICS_MODE_LABELED {
+            return self.register_register_labeled(client, register);
+        }
+        let tmp_name = format!("{}_{}", client.name, register.name);
+        let tmp_help = match &register.unit {
+            Some(unit) => format!("{} {} in {}", register.datatype, register.objecttype, unit),
+            None => format!("{} {}", register.datatype, register.objecttype),
+        };
+        let tmp_gauge = match prometheus::Gauge::new(&tmp_name, &tmp_help) {
+            Ok(gauge) => gauge,
+            Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
+        };
+        if self.registry.register(Box::new(tmp_gauge.clone())).is_err() {
+            return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
+        }
+        // Every bitfield of the register is a gauge of its own
+        for (bitfield_name, bitfield) in get_bitfield_names(client, register)
+            .into_iter()
+            .zip(register.bitfields.iter().flatten())
+        {
+            let bitfield_help = format!(
+                "Bits {} to {} of {}",
+                bitfield.bit,
+                bitfield.bit + bitfield.length - 1,
+                tmp_name
+            );
+            let bitfield_gauge = match prometheus::Gauge::new(&bitfield_name, &bitfield_help) {
                 Ok(gauge) => gauge,
                 Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
             };
-            if let Err(_) = self.registry.register(Box::new(tmp_gauge.clone())){
+            if self
+                .registry
+                .register(Box::new(bitfield_gauge.clone()))
+                .is_err()
+            {
                 return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
             }
-            self.counters.insert(tmp_name, tmp_gauge);
+            self.counters.insert(bitfield_name, bitfield_gauge);
         }
-        // register all coils to the registry
-        for coil in client.coils.iter() {
-            let tmp_name = format!("{}_{}", client.name, coil.name);
-            let tmp_help = format!("{}", coil.objecttype);
-            let tmp_gauge = match prometheus::Gauge::new(&tmp_name, &tmp_help){
-                Ok(gauge) => gauge,
+        // The states are one metric with the label state: {client}_{register}_state
+        if let Some(states) = &register.states {
+            let state_vec = match GaugeVec::new(
+                Opts::new(
+                    format!("{}_state", tmp_name),
+                    format!("States of {}", tmp_name),
+                ),
+                &["state"],
+            ) {
+                Ok(vec) => vec,
                 Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
             };
-            if let Err(_) = self.registry.register(Box::new(tmp_gauge.clone())){
+            if self.registry.register(Box::new(state_vec.clone())).is_err() {
                 return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
             }
-            self.counters.insert(tmp_name, tmp_gauge);
+            let labels = BTreeMap::new();
+            self.states
+                .insert(tmp_name.clone(), StateSet::new(state_vec, states, &labels)?);
         }
+        self.counters.insert(tmp_name, tmp_gauge);
+        Ok(())
+    }
 
-        log::debug!("New registry: {:?}", self.registry);
+    fn register_register_labeled(
+        &mut self,
+        client: &Client,
+        register: &Register,
+    ) -> Result<(), ErrorRuntime> {
+        let collector = match self.collectors.get(&client.name) {
+            Some(collector) => collector,
+            None => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
+        };
+        let key = format!("{}_{}", client.name, register.name);
+        let labels = get_register_labels(client, register);
+        let gauge = get_gauge_with_labels(&collector.registers, &labels)?;
+        let mut gauges = vec![(key.clone(), gauge)];
+        for (bitfield_name, bitfield) in get_bitfield_names(client, register)
+            .into_iter()
+            .zip(register.bitfields.iter().flatten())
+        {
+            let mut labels = labels.clone();
+            labels.insert("bitfield".to_string(), bitfield.name.clone());
+            let gauge = get_gauge_with_labels(&collector.bitfields, &labels)?;
+            gauges.push((bitfield_name, gauge));
+        }
+        let state_set = match &register.states {
+            Some(states) => Some(StateSet::new(collector.states.clone(), states, &labels)?),
+            None => None,
+        };
+        self.counters.extend(gauges);
+        if let Some(state_set) = state_set {
+            self.states.insert(key, state_set);
+        }
+        Ok(())
+    }
 
+    // Unregister the metrics of a register and forget the time of its last update
+    fn unregister_register(&mut self, client: &Client, register: &Register) -> Result<(), ErrorRuntime> {
+        let tmp_name = format!("{}_{}", client.name, register.name);
+        for bitfield_key in self.bitfields.remove(&tmp_name).unwrap_or_default() {
+            self.last_updated.remove(&bitfield_key);
+            self.stale.remove(&bitfield_key);
+        }
+        self.last_updated.remove(&tmp_name);
+        self.stale.remove(&tmp_name);
+        if register.is_string() {
+            return Ok(());
+        }
+        if self.metrics_mode == METRICS_MODE_LABELED {
+            if let Some(collector) = self.collectors.get(&client.name) {
+                let labels = get_register_labels(client, register);
+                remove_gauge_with_labels(&collector.registers, &labels);
+                for bitfield in register.bitfields.iter().flatten() {
+                    let mut labels = labels.clone();
+                    labels.insert("bitfield".to_string(), bitfield.name.clone());
+                    remove_gauge_with_labels(&collector.bitfields, &labels);
+                }
+                for state in register.states.iter().flat_map(|states| states.values()) {
+                    let mut labels = labels.clone();
+                    labels.insert("state".to_string(), state.clone());
+                    remove_gauge_with_labels(&collector.states, &labels);
+                }
+            }
+            self.counters.remove(&tmp_name);
+            self.states.remove(&tmp_name);
+            for bitfield_name in get_bitfield_names(client, register) {
+                self.counters.remove(&bitfield_name);
+            }
+            return Ok(());
+        }
+        let tmp_gauge = match self.counters.remove(&tmp_name) {
+            Some(gauge) => gauge,
+            None => return Err(ErrorRuntime::PrometheusErrorGaugeRemove),
+        };
+        if self.registry.unregister(Box::new(tmp_gauge)).is_err() {
+            return Err(ErrorRuntime::PrometheusErrorRegistryUnregister);
+        }
+        for bitfield_name in get_bitfield_names(client, register) {
+            if let Some(bitfield_gauge) = self.counters.remove(&bitfield_name) {
+                if self.registry.unregister(Box::new(bitfield_gauge)).is_err() {
+                    return Err(ErrorRuntime::PrometheusErrorRegistryUnregister);
+                }
+            }
+        }
+        if let Some(state_set) = self.states.remove(&tmp_name) {
+            if self.registry.unregister(Box::new(state_set.vec)).is_err() {
+                return Err(ErrorRuntime::PrometheusErrorRegistryUnregister);
+            }
+        }
         Ok(())
     }
 
-    pub fn unregister_client(&mut self, client: &Client) -> Result<(), ErrorRuntime>{
-        // Delete all registers from the registry
-        for register in client.registers.iter() {
-            let tmp_name = format!("{}_{}", client.name, register.name);
-            let tmp_gauge = match self.counters.remove(&tmp_name){
-                Some(gauge) => gauge,
-                None => return Err(ErrorRuntime::PrometheusErrorGaugeRemove),
+    fn register_coil(&mut self, client: &Client, coil: &Coil) -> Result<(), ErrorRuntime> {
+        let tmp_name = format!("{}_{}", client.name, coil.name);
+        if self.metrics_mode == METRICS_MODE_LABELED {
+            let gauge = match self.collectors.get(&client.name) {
+                Some(collector) => {
+                    get_gauge_with_labels(&collector.coils, &get_coil_labels(client, coil))?
+                }
+                None => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
             };
-            if let Err(_) = self.registry.unregister(Box::new(tmp_gauge.clone())){
-                return Err(ErrorRuntime::PrometheusErrorRegistryUnregister);
+            self.counters.insert(tmp_name, gauge);
+            return Ok(());
+        }
+        let tmp_help = coil.objecttype.to_string();
+        let tmp_gauge = match prometheus::Gauge::new(&tmp_name, &tmp_help) {
+            Ok(gauge) => gauge,
+            Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
+        };
+        if self.registry.register(Box::new(tmp_gauge.clone())).is_err() {
+            return Err(ErrorRuntime::PrometheusErrorRegistryRegister);
+        }
+        self.counters.insert(tmp_name, tmp_gauge);
+        Ok(())
+    }
+
+    fn unregister_coil(&mut self, client: &Client, coil: &Coil) -> Result<(), ErrorRuntime> {
+        let tmp_name = format!("{}_{}", client.name, coil.name);
+        self.last_updated.remove(&tmp_name);
+        self.stale.remove(&tmp_name);
+        let tmp_gauge = match self.counters.remove(&tmp_name) {
+            Some(gauge) => gauge,
+            None => return Err(ErrorRuntime::PrometheusErrorGaugeRemove),
+        };
+        if self.metrics_mode == METRICS_MODE_LABELED {
+            if let Some(collector) = self.collectors.get(&client.name) {
+                remove_gauge_with_labels(&collector.coils, &get_coil_labels(client, coil));
             }
+            return Ok(());
+        }
+        if self.registry.unregister(Box::new(tmp_gauge)).is_err() {
+            return Err(ErrorRuntime::PrometheusErrorRegistryUnregister);
         }
         Ok(())
     }
+}
 
-    pub fn update_gauge(&mut self, name: &str, value: f64) {
-        // write value to hash map entry
-        if let Some(metric) = self.counters.get_mut(name) {
-            metric.set(value);
+// Labels of the labeled metrics of a register
+fn get_register_labels(client: &Client, register: &Register) -> BTreeMap<String, String> {
+    let mut labels = get_static_labels(client, &register.labels);
+    labels.insert("client".to_string(), client.name.clone());
+    labels.insert("register".to_string(), register.name.clone());
+    labels.insert("objecttype".to_string(), register.objecttype.clone());
+    labels.insert("address".to_string(), register.address.to_string());
+    labels.insert(
+        "unit_id".to_string(),
+        client.resolve_unit_id(register.unit_id).to_string(),
+    );
+    if let Some(unit) = &register.unit {
+        labels.insert("unit".to_string(), unit.clone());
+    }
+    labels
+}
+
+// Labels of the labeled metric of a coil
+fn get_coil_labels(client: &Client, coil: &Coil) -> BTreeMap<String, String> {
+    let mut labels = get_static_labels(client, &coil.labels);
+    labels.insert("client".to_string(), client.name.clone());
+    labels.insert("coil".to_string(), coil.name.clone());
+    labels.insert("objecttype".to_string(), coil.objecttype.clone());
+    labels.insert("address".to_string(), coil.address.to_string());
+    labels.insert(
+        "unit_id".to_string(),
+        client.resolve_unit_id(coil.unit_id).to_string(),
+    );
+    labels
+}
+
+// Names of the string registers of a client. They are the label names of the device info metric
+fn get_string_names(client: &Client) -> Vec<&str> {
+    client
+        .registers
+        .iter()
+        .filter(|register| register.is_string())
+        .map(|register| register.name.as_str())
+        .collect()
+}
+
+// Names of the gauges of the bitfields of a register: {client}_{register}_{bitfield}
+fn get_bitfield_names(client: &Client, register: &Register) -> Vec<String> {
+    register
+        .bitfields
+        .iter()
+        .flatten()
+        .map(|bitfield| format!("{}_{}_{}", client.name, register.name, bitfield.name))
+        .collect()
+}
+
+// Static labels of a register or coil. They override the static labels of the client
+fn get_static_labels(
+    client: &Client,
+    item_labels: &Option<BTreeMap<String, String>>,
+) -> BTreeMap<String, String> {
+    let mut labels = client.labels.clone().unwrap_or_default();
+    if let Some(item_labels) = item_labels {
+        labels.extend(item_labels.clone());
+    }
+    labels
+}
+
+/// ClientCollector struct
+///
+/// The labeled metrics of one client. Every client has its own gauge vectors, because the static labels
+/// and therefore the label names differ between the clients. The registry only allows one set of label names
+/// per metric name, so the collector is registered with a descriptor of its own and the metric families of all
+/// clients are merged when the registry is gathered.
+///
+#[derive(Clone)]
+struct ClientCollector {
+    desc: Desc,
+    registers: GaugeVec,
+    coils: GaugeVec,
+    bitfields: GaugeVec,
+    states: GaugeVec,
+    info: GaugeVec,
+}
+impl ClientCollector {
+    fn new(client: &Client) -> Result<Self, ErrorRuntime> {
+        let desc = match Desc::new(
+            format!("modbus_client_{}", client.name),
+            format!("Labeled metrics of modbus client {}", client.name),
+            Vec::new(),
+            HashMap::new(),
+        ) {
+            Ok(desc) => desc,
+            Err(_) => return Err(ErrorRuntime::PrometheusErrorGaugeNew),
+        };
+        let mut register_labels = get_label_names(
+            client,
+            "register",
+            client.registers.iter().map(|register| &register.labels),
+        );
+        // The unit is only a label of clients with units, so the series of other clients stay the same
+        if client
+            .registers
+            .iter()
+            .any(|register| register.unit.is_some())
+        {
+            register_labels.push("unit".to_string());
+        }
+        let coil_labels =
+            get_label_names(client, "coil", client.coils.iter().map(|coil| &coil.labels));
+        let mut bitfield_labels = register_labels.clone();
+        bitfield_labels.push("bitfield".to_string());
+        let mut state_labels = register_labels.clone();
+        state_labels.push("state".to_string());
+        let registers = GaugeVec::new(
+            Opts::new("modbus_register_value", "Value of a modbus register"),
+            &register_labels
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>(),
+        );
+        let coils = GaugeVec::new(
+            Opts::new(
+                "modbus_coil_value",
+                "Value of a modbus coil or discrete input",
+            ),
+            &coil_labels
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>(),
+        );
+        let bitfields = GaugeVec::new(
+            Opts::new(
+                "modbus_register_bitfield_value",
+                "Value of a bit or a range of bits of a modbus register",
+            ),
+            &bitfield_labels
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>(),
+        );
+        let states = GaugeVec::new(
+            Opts::new(
+                "modbus_register_state",
+                "State of a modbus register. 1 for the current state, 0 for the other states",
+            ),
+            &state_labels
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>(),
+        );
+        let mut info_labels = vec!["client"];
+        info_labels.extend(get_string_names(client));
+        let info = GaugeVec::new(
+            Opts::new(
+                "modbus_device_info",
+                "String registers of a modbus client, e.g. serial number or firmware version",
+            ),
+            &info_labels,
+        );
+        match (registers, coils, bitfields, states, info) {
+            (Ok(registers), Ok(coils), Ok(bitfields), Ok(states), Ok(info)) => Ok(Self {
+                desc,
+                registers,
+                coils,
+                bitfields,
+                states,
+                info,
+            }),
+            _ => Err(ErrorRuntime::PrometheusErrorGaugeNew),
+        }
+    }
+
+    // Check if the gauge vectors of two collectors have the same label names
+    fn has_same_label_names(&self, other: &ClientCollector) -> bool {
+        let label_names = |collector: &ClientCollector| {
+            [
+                &collector.registers,
+                &collector.coils,
+                &collector.bitfields,
+                &collector.states,
+                &collector.info,
+            ]
+            .map(|vec| vec.desc()[0].variable_labels.clone())
+        };
+        label_names(self) == label_names(other)
+    }
+}
+impl Collector for ClientCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        vec![&self.desc]
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let mut families = self.registers.collect();
+        families.extend(self.coils.collect());
+        families.extend(self.bitfields.collect());
+        families.extend(self.states.collect());
+        families.extend(self.info.collect());
+        families
+    }
+}
+impl std::fmt::Debug for ClientCollector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClientCollector")
+            .field("desc", &self.desc)
+            .finish()
+    }
+}
+
+// Get the gauge of one register, coil or state
+fn get_gauge_with_labels(
+    vec: &GaugeVec,
+    labels: &BTreeMap<String, String>,
+) -> Result<prometheus::Gauge, ErrorRuntime> {
+    match vec.get_metric_with_label_values(&get_label_values(vec, labels)) {
+        Ok(gauge) => Ok(gauge),
+        Err(_) => Err(ErrorRuntime::PrometheusErrorGaugeNew),
+    }
+}
+
+// Remove the gauge of one register, coil or state. Gauges which do not exist are ignored
+fn remove_gauge_with_labels(vec: &GaugeVec, labels: &BTreeMap<String, String>) {
+    let _ = vec.remove_label_values(&get_label_values(vec, labels));
+}
+
+// Values of the label names of a gauge vector. Labels which are only set on other items of the client stay empty
+fn get_label_values<'a>(vec: &'a GaugeVec, labels: &'a BTreeMap<String, String>) -> Vec<&'a str> {
+    let desc = &vec.desc()[0];
+    desc.variable_labels
+        .iter()
+        .map(|name| labels.get(name).map(String::as_str).unwrap_or(""))
+        .collect()
+}
+
+/// StateSet struct
+///
+/// The states of one register in the style of an OpenMetrics state set. Every state is a series with the label
+/// state, which is 1 for the current state of the register and 0 for all other states.
+///
+#[derive(Clone, Debug)]
+struct StateSet {
+    /// The gauge vector of the series. Registered on its own in the compat mode, part of the collector otherwise
+    vec: GaugeVec,
+    /// The value of every state and its series
+    gauges: Vec<(i64, prometheus::Gauge)>,
+}
+impl StateSet {
+    fn new(
+        vec: GaugeVec,
+        states: &BTreeMap<i64, String>,
+        labels: &BTreeMap<String, String>,
+    ) -> Result<Self, ErrorRuntime> {
+        let mut gauges = Vec::new();
+        for (value, name) in states.iter() {
+            let mut labels = labels.clone();
+            labels.insert("state".to_string(), name.clone());
+            gauges.push((*value, get_gauge_with_labels(&vec, &labels)?));
+        }
+        Ok(Self { vec, gauges })
+    }
+}
+
+/// DeviceInfo struct
+///
+/// The string registers of a client as info metric: one series with the value 1 and the texts as labels.
+/// The series is replaced whenever a text changes.
+///
+#[derive(Clone, Debug)]
+struct DeviceInfo {
+    /// The gauge vector of the series. Registered on its own in the compat mode, part of the collector otherwise
+    vec: GaugeVec,
+    /// The labels of the current series
+    labels: BTreeMap<String, String>,
+}
+impl DeviceInfo {
+    fn new(vec: GaugeVec, labels: BTreeMap<String, String>) -> Self {
+        Self { vec, labels }
+    }
+
+    // Replace the series with a series with the new text of a string register
+    fn set_label(&mut self, name: &str, text: &str) {
+        if self.labels.get(name).map(String::as_str) == Some(text) {
+            return;
+        }
+        remove_gauge_with_labels(&self.vec, &self.labels);
+        self.labels.insert(name.to_string(), text.to_string());
+        if let Ok(gauge) = get_gauge_with_labels(&self.vec, &self.labels) {
+            gauge.set(1.0);
         }
     }
 }
+
+// Label names of the registers or coils of a client: the labels set by the server and all static labels
+fn get_label_names<'a>(
+    client: &Client,
+    item_label: &str,
+    item_labels: impl Iterator<Item = &'a Option<BTreeMap<String, String>>>,
+) -> Vec<String> {
+    let mut names: Vec<String> = ["client", item_label, "objecttype", "address", "unit_id"]
+        .iter()
+        .map(|name| name.to_string())
+        .collect();
+    let mut static_names: Vec<String> = client
+        .labels
+        .iter()
+        .flatten()
+        .map(|(name, _)| name.clone())
+        .collect();
+    for labels in item_labels.flatten() {
+        static_names.extend(labels.keys().cloned());
+    }
+    static_names.sort();
+    static_names.dedup();
+    names.extend(static_names);
+    names
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_prometheus {
+    use super::*;
+
+    fn labeled_client(name: &str) -> Client {
+        let client_json = format!(
+            r#"{{
+                "name": "{}",
+                "ip_address": "127.0.0.1",
+                "port": 502,
+                "protocol": "tcp",
+                "unit_id": 1,
+                "labels": {{ "site": "Building A" }},
+                "registers": [
+                    {{ "name": "power", "objecttype": "input", "address": 12, "length": 1, "datatype": "int16",
+                       "factor": 0, "value": 0, "labels": {{ "phase": "l1" }} }},
+                    {{ "name": "energy", "objecttype": "holding", "address": 20, "length": 1, "datatype": "int16",
+                       "factor": 0, "value": 0, "unit_id": 3 }}
+                ],
+                "coils": [
+                    {{ "name": "relay", "objecttype": "coil", "address": 1, "value": false,
+                       "labels": {{ "site": "Building B" }} }}
+                ]
+            }}"#,
+            name
+        );
+        Client::new(client_json).unwrap()
+    }
+
+    fn encode(metrics: &PrometheusMetrics) -> String {
+        let mut buffer = Vec::new();
+        prometheus::TextEncoder::new()
+            .encode(&metrics.gather(), &mut buffer)
+            .unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    use prometheus::Encoder;
+
+    #[test]
+    fn test_labeled_metrics() {
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        metrics.register_client(&labeled_client("meter")).unwrap();
+        metrics.update_gauge("meter_power", 1.5);
+        metrics.update_gauge("meter_relay", 1.0);
+        let text = encode(&metrics);
+        assert!(text.contains(r#"modbus_register_value{address="12",client="meter",objecttype="input",phase="l1",register="power",site="Building A",unit_id="1"} 1.5"#));
+        assert!(text.contains(r#"modbus_register_value{address="20",client="meter",objecttype="holding",phase="",register="energy",site="Building A",unit_id="3"} 0"#));
+        assert!(text.contains(r#"modbus_coil_value{address="1",client="meter",coil="relay",objecttype="coil",site="Building B",unit_id="1"} 1"#));
+        assert!(!text.contains("meter_power"));
+    }
+
+    #[test]
+    fn test_labeled_metrics_of_clients_with_different_labels() {
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        let meter = labeled_client("meter");
+        let mut plain = labeled_client("plain");
+        plain.labels = None;
+        plain.registers[0].labels = None;
+        metrics.register_client(&meter).unwrap();
+        metrics.register_client(&plain).unwrap();
+        let text = encode(&metrics);
+        assert_eq!(
+            text.matches("# TYPE modbus_register_value gauge").count(),
+            1
+        );
+        assert!(text.contains(r#"modbus_register_value{address="12",client="plain",objecttype="input",register="power",unit_id="1"} 0"#));
+        metrics.unregister_client(&meter).unwrap();
+        let text = encode(&metrics);
+        assert!(!text.contains(r#"client="meter""#));
+        assert!(!metrics.counters.contains_key("meter_power"));
+        assert!(!metrics.counters.contains_key("meter_relay"));
+        // The client can be registered again
+        metrics.register_client(&meter).unwrap();
+    }
+
+    #[test]
+    fn test_stale_metrics() {
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        metrics.register_client(&labeled_client("meter")).unwrap();
+        metrics.update_gauge("meter_power", 1.5);
+        metrics.mark_stale("meter_power", STALE_POLICY_REMOVE);
+        metrics.mark_stale("meter_energy", STALE_POLICY_NAN);
+        metrics.mark_stale("meter_relay", "keep");
+        let text = encode(&metrics);
+        assert!(!text.contains(r#"register="power""#));
+        assert!(text.contains(r#"register="energy",site="Building A",unit_id="3"} NaN"#));
+        assert!(text.contains(r#"coil="relay""#));
+        // The next update ends the stale state
+        metrics.update_gauge("meter_power", 2.5);
+        let text = encode(&metrics);
+        assert!(text.contains(r#"register="power",site="Building A",unit_id="1"} 2.5"#));
+    }
+
+    #[test]
+    fn test_metrics_with_timestamps() {
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, true);
+        metrics.register_client(&labeled_client("meter")).unwrap();
+        metrics.update_gauge("meter_power", 1.5);
+        let timestamp = metrics.last_updated["meter_power"]
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis();
+        let text = encode(&metrics);
+        assert!(text.contains(&format!(
+            r#"register="power",site="Building A",unit_id="1"}} 1.5 {}"#,
+            timestamp
+        )));
+        // Series which were never updated have no timestamp
+        assert!(text.contains(r#"register="energy",site="Building A",unit_id="3"} 0"#));
+    }
+
+    #[test]
+    fn test_metrics_with_units() {
+        let mut client = labeled_client("meter");
+        client.registers[0].unit = Some("watts".to_string());
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        metrics.register_client(&client).unwrap();
+        let text = encode(&metrics);
+        assert!(text.contains(r#"register="power",site="Building A",unit="watts",unit_id="1"} 0"#));
+        assert!(text.contains(r#"register="energy",site="Building A",unit="",unit_id="3"} 0"#));
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_COMPAT, false);
+        metrics.register_client(&client).unwrap();
+        let text = encode(&metrics);
+        assert!(text.contains("# HELP meter_power int16 input in watts"));
+        assert!(text.contains("# HELP meter_energy int16 holding\n"));
+    }
+
+    #[test]
+    fn test_state_metrics() {
+        let mut client = labeled_client("meter");
+        client.registers[1].states = Some(BTreeMap::from([
+            (0, "off".to_string()),
+            (3, "heating".to_string()),
+        ]));
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        metrics.register_client(&client).unwrap();
+        metrics.update_gauge("meter_energy", 3.0);
+        metrics.update_state("meter_energy", Some(3));
+        let text = encode(&metrics);
+        assert!(text.contains(r#"modbus_register_state{address="20",client="meter",objecttype="holding",phase="",register="energy",site="Building A",state="heating",unit_id="3"} 1"#));
+        assert!(text.contains(r#"modbus_register_state{address="20",client="meter",objecttype="holding",phase="",register="energy",site="Building A",state="off",unit_id="3"} 0"#));
+        // The state set becomes stale together with its register
+        metrics.mark_stale("meter_energy", STALE_POLICY_REMOVE);
+        assert!(!encode(&metrics).contains("modbus_register_state"));
+        metrics.unregister_client(&client).unwrap();
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_COMPAT, false);
+        metrics.register_client(&client).unwrap();
+        metrics.update_state("meter_energy", Some(0));
+        let text = encode(&metrics);
+        assert!(text.contains(r#"meter_energy_state{state="off"} 1"#));
+        assert!(text.contains(r#"meter_energy_state{state="heating"} 0"#));
+        metrics.mark_stale("meter_energy", STALE_POLICY_NAN);
+        assert!(encode(&metrics).contains(r#"meter_energy_state{state="off"} NaN"#));
+        metrics.unregister_client(&client).unwrap();
+        assert!(!encode(&metrics).contains("meter_energy_state"));
+    }
+    #[test]
+    fn test_device_info_metrics() {
+        let mut client = labeled_client("meter");
+        client.registers[0].datatype = "string".to_string();
+        client.registers[0].name = "serial".to_string();
+        client.registers[1].datatype = "string".to_string();
+        client.registers[1].name = "firmware".to_string();
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        metrics.register_client(&client).unwrap();
+        metrics.update_info("meter", "serial", "SN-12");
+        metrics.update_info("meter", "firmware", "1.0");
+        let text = encode(&metrics);
+        assert!(
+            text.contains(r#"modbus_device_info{client="meter",firmware="1.0",serial="SN-12"} 1"#)
+        );
+        assert!(!text.contains("modbus_register_value"));
+        // A new text replaces the series
+        metrics.update_info("meter", "firmware", "1.1");
+        let text = encode(&metrics);
+        assert!(
+            text.contains(r#"modbus_device_info{client="meter",firmware="1.1",serial="SN-12"} 1"#)
+        );
+        assert!(!text.contains(r#"firmware="1.0""#));
+        metrics.unregister_client(&client).unwrap();
+        assert!(!encode(&metrics).contains("modbus_device_info"));
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_COMPAT, false);
+        metrics.register_client(&client).unwrap();
+        metrics.update_info("meter", "serial", "SN-12");
+        assert!(encode(&metrics).contains(r#"meter_device_info{firmware="",serial="SN-12"} 1"#));
+        metrics.unregister_client(&client).unwrap();
+        assert!(!encode(&metrics).contains("meter_device_info"));
+    }
+    #[test]
+    fn test_update_client() {
+        for metrics_mode in [METRICS_MODE_COMPAT, METRICS_MODE_LABELED] {
+            let old = labeled_client("meter");
+            let mut new = labeled_client("meter");
+            new.registers[1].address = 21;
+            new.coils[0].name = "pump".to_string();
+            let mut metrics = PrometheusMetrics::new(metrics_mode, false);
+            metrics.register_client(&old).unwrap();
+            metrics.update_gauge("meter_power", 1.5);
+            metrics.update_gauge("meter_energy", 2.5);
+            metrics.update_client(&old, &new).unwrap();
+            // Only the changed register and the replaced coil are registered again
+            assert_eq!(metrics.counters["meter_power"].get(), 1.5);
+            assert_eq!(metrics.counters["meter_energy"].get(), 0.0);
+            assert!(!metrics.counters.contains_key("meter_relay"));
+            assert!(metrics.counters.contains_key("meter_pump"));
+            let text = encode(&metrics);
+            assert!(!text.contains("relay"));
+            assert!(text.contains("pump"));
+            // All metrics of the new client are removed, so it can be registered again
+            metrics.unregister_client(&new).unwrap();
+            assert!(metrics.counters.is_empty());
+            metrics.register_client(&new).unwrap();
+        }
+    }
+    #[test]
+    fn test_update_client_failed() {
+        let old = labeled_client("meter");
+        let mut other = labeled_client("meter_new");
+        other.coils.clear();
+        let mut new = labeled_client("meter");
+        new.registers[0].name = "new_power".to_string();
+        new.registers[1].address = 21;
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_COMPAT, false);
+        metrics.register_client(&old).unwrap();
+        metrics.register_client(&other).unwrap();
+        metrics.update_gauge("meter_new_power", 3.0);
+        // meter_new_power is a metric of the other client
+        assert!(metrics.update_client(&old, &new).is_err());
+        // The metrics of the old client are restored and the other client keeps its metric
+        assert!(metrics.counters.contains_key("meter_power"));
+        assert!(metrics.counters.contains_key("meter_energy"));
+        let text = encode(&metrics);
+        assert!(text.contains("meter_new_power 3"));
+        assert!(text.contains("meter_power 0"));
+        metrics.unregister_client(&old).unwrap();
+        metrics.unregister_client(&other).unwrap();
+        assert!(metrics.counters.is_empty());
+    }
+    #[test]
+    fn test_update_client_with_new_label_names() {
+        let old = labeled_client("meter");
+        let mut new = labeled_client("meter");
+        new.coils[0].labels = Some(BTreeMap::from([("floor".to_string(), "2".to_string())]));
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        metrics.register_client(&old).unwrap();
+        metrics.update_gauge("meter_power", 1.5);
+        metrics.update_gauge("meter_relay", 1.0);
+        metrics.update_client(&old, &new).unwrap();
+        // The label names of the coils changed, so all series are new. Unchanged registers keep their values
+        let text = encode(&metrics);
+        assert!(text.contains(r#"modbus_register_value{address="12",client="meter",objecttype="input",phase="l1",register="power",site="Building A",unit_id="1"} 1.5"#));
+        assert!(text.contains(r#"modbus_coil_value{address="1",client="meter",coil="relay",floor="2",objecttype="coil",site="Building A",unit_id="1"} 0"#));
+    }
+    #[test]
+    fn test_bitfield_metrics() {
+        let mut client = labeled_client("meter");
+        client.registers[1].bitfields = Some(vec![crate::clients::Bitfield {
+            name: "alarm".to_string(),
+            bit: 3,
+            length: 1,
+        }]);
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_LABELED, false);
+        metrics.register_client(&client).unwrap();
+        metrics.update_gauge("meter_energy_alarm", 1.0);
+        let text = encode(&metrics);
+        assert!(text.contains(r#"modbus_register_bitfield_value{address="20",bitfield="alarm",client="meter",objecttype="holding",phase="",register="energy",site="Building A",unit_id="3"} 1"#));
+        // Bitfields become stale together with their register
+        metrics.mark_stale("meter_energy", STALE_POLICY_REMOVE);
+        assert!(!encode(&metrics).contains("modbus_register_bitfield_value"));
+        metrics.unregister_client(&client).unwrap();
+        assert!(!metrics.counters.contains_key("meter_energy_alarm"));
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_COMPAT, false);
+        metrics.register_client(&client).unwrap();
+        assert!(encode(&metrics).contains("# HELP meter_energy_alarm Bits 3 to 3 of meter_energy"));
+        metrics.unregister_client(&client).unwrap();
+        assert!(!encode(&metrics).contains("meter_energy_alarm"));
+    }
+
+    #[test]
+    fn test_compat_metrics() {
+        let mut metrics = PrometheusMetrics::new(METRICS_MODE_COMPAT, false);
+        metrics.register_client(&labeled_client("meter")).unwrap();
+        metrics.update_gauge("meter_power", 2.0);
+        let text = encode(&metrics);
+        assert!(text.contains("meter_power 2"));
+        assert!(!text.contains("modbus_register_value"));
+        metrics.mark_stale("meter_power", STALE_POLICY_REMOVE);
+        let text = encode(&metrics);
+        assert!(!text.contains("meter_power"));
+        assert!(text.contains("meter_energy 0"));
+    }
+}
@@ -1,13 +1,68 @@
+use crate::audit::{self, AuditContext, AuditEntry, AuditFilter, AuditLog};
+use crate::clients::live_values::{CoilValue, RegisterValue};
+use crate::clients::read_data::{self, Pollers};
+use crate::clients::{write_guard, write_plan};
 use crate::clients::{self as Clients, Client};
+use crate::connections::{Connection, ConnectionManager};
 use crate::errors::impls::ErrorRuntime as CustomErrors;
+use crate::errors::impls::ErrorRuntimeNoRejection;
+use crate::prometheus::exporter::WRITE_RESULT_OK;
 use crate::prometheus::PrometheusMetrics;
 use crate::utils;
 use prometheus::Encoder;
-use std::{collections::HashMap, sync::Arc};
+use reply::{Envelope, ErrorEnvelope};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
 use tokio::sync::Mutex;
-use tokio_modbus::prelude::Writer;
+use utoipa::ToSchema;
 use warp::{http::StatusCode, Rejection, Reply};
 
+pub mod openapi;
+pub mod reply;
+
+/// WriteResult struct
+///
+/// The data of the response of a successful write
+///
+#[derive(Serialize, Debug, ToSchema)]
+pub struct WriteResult {
+    pub client: String,
+    /// Name of the register, bitfield or coil
+    pub item: String,
+    /// The written value as given in the request
+    pub value: String,
+    /// The value read back from the device. Only set if the write was verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_back: Option<String>,
+}
+
+/// WriteValues struct
+///
+/// The json body of PUT /clients/{name}/set-values
+/// ```json
+/// { "registers": { "setpoint": 21.5, "operating_mode": "heating" }, "coils": { "pump": true } }
+/// ```
+#[derive(Deserialize, Debug, Default, ToSchema)]
+pub struct WriteValues {
+    /// {register_name: value}. Numbers, state names or the text of string registers
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub registers: BTreeMap<String, serde_json::Value>,
+    /// {coil_name: true or false}
+    #[serde(default)]
+    pub coils: BTreeMap<String, bool>,
+    /// Confirms the write of registers and coils with requires_confirmation
+    #[serde(default)]
+    pub confirm: bool,
+    /// Read all values back after the write. Overrides verify_write of the registers and coils
+    #[serde(default)]
+    pub verify: Option<bool>,
+}
+
 
 /// Create a new client via: POST <ip_address>:3030/clients with a json body
 //// ```json
@@ -61,9 +116,40 @@ use warp::{http::StatusCode, Rejection, Reply};
 ///     ]
 ///   }
 /// ```
+#[utoipa::path(
+    post,
+    path = "/clients",
+    tag = "clients",
+    request_body = Client,
+    responses(
+        (status = 201, description = "The created client", body = Envelope<Client>),
+        (status = 409, description = "client_exists", body = ErrorEnvelope),
+        (status = 422, description = "Invalid client config, e.g. register_length_not_valid, label_not_valid, name_not_valid", body = ErrorEnvelope),
+        (status = 500, description = "config_storage_error, metrics_error, json_serialize_error", body = ErrorEnvelope),
+    )
+)]
 pub async fn create_client(
     registry: Arc<Mutex<PrometheusMetrics>>,
     clients: Arc<Mutex<Clients::Clients>>,
+    pollers: Arc<Mutex<Pollers>>,
+    audit: AuditContext,
+    client_input: Client,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let entries = vec![AuditEntry::new(
+        audit::AUDIT_ACTION_CREATE_CLIENT,
+        &client_input.name,
+        None,
+    )];
+    let created = store_client(registry, clients, pollers, client_input).await;
+    audit.record(entries, get_result_code(&created)).await;
+    created
+}
+
+// Store the config of a new client and start polling it
+async fn store_client(
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    pollers: Arc<Mutex<Pollers>>,
     client_input: Client,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     // Check if the Configuration (Client) is not already present. Reject if it is. Then client can only be updated or deleted
@@ -77,6 +163,10 @@ pub async fn create_client(
     if let Err(e) = utils::check_client_strings(&serde_json::to_value(&client_input).unwrap()) {
         return Err(warp::reject::custom(e));
     }
+    // Check if protocol, serial settings, unit ids, datatypes and objecttypes are supported
+    if let Err(e) = client_input.verify() {
+        return Err(warp::reject::custom(e));
+    }
     // Store the config to local FS
     if let Err(e) = utils::write_config(&client_input, &config_path) {
         return Err(warp::reject::custom(e));
@@ -85,24 +175,47 @@ pub async fn create_client(
     if let Err(e) = registry.lock().await.register_client(&client_input) {
         return Err(warp::reject::custom(e));
     }
-    // Add the config to the Clients struct
-    clients.lock().await.add_client(client_name, client_input);
+    // Add the config to the Clients struct and start polling the client
+    let created = reply::data_with_status(&client_input, StatusCode::CREATED);
+    clients
+        .lock()
+        .await
+        .add_client(client_name.clone(), client_input);
+    pollers.lock().await.start(&client_name);
 
-    Ok(warp::reply::reply())
+    Ok(created)
 }
 
-// GET /clients - get all clients as string
+// GET /clients - get the names of all clients
+#[utoipa::path(
+    get,
+    path = "/clients",
+    tag = "clients",
+    responses(
+        (status = 200, description = "The sorted names of all clients", body = Envelope<Vec<String>>),
+    )
+)]
 pub async fn get_clients(
     clients: Arc<Mutex<Clients::Clients>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let mut clients_string = String::new();
-    for (name, _) in clients.lock().await.clients.iter() {
-        clients_string.push_str(&format!("{}\n", name));
-    }
-    Ok(warp::reply::html(clients_string))
+    let mut names: Vec<String> = clients.lock().await.clients.keys().cloned().collect();
+    names.sort();
+    Ok(reply::data(names))
 }
 
 // GET /clients/{name} - get client by name
+#[utoipa::path(
+    get,
+    path = "/clients/{name}",
+    tag = "clients",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+    ),
+    responses(
+        (status = 200, description = "The config of the client with the last read raw values", body = Envelope<Client>),
+        (status = 404, description = "client_not_found", body = ErrorEnvelope),
+    )
+)]
 pub async fn get_client(
     client: String,
     clients: Arc<Mutex<Clients::Clients>>,
@@ -112,43 +225,579 @@ pub async fn get_client(
             client.clone(),
         ))));
     };
-    Ok(warp::reply::json(
+    Ok(reply::data(
         clients.lock().await.clients.get(&client).unwrap(),
     ))
 }
 
+// GET /clients/{name}/registers?fresh=true - get the live values of all registers of a client.
+// With fresh=true the registers are read from the modbus client first, otherwise the last polled values are returned
+#[utoipa::path(
+    get,
+    path = "/clients/{name}/registers",
+    tag = "values",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+        ("fresh" = Option<bool>, Query, description = "Read from the modbus client before answering"),
+    ),
+    responses(
+        (status = 200, description = "The live values of all registers", body = Envelope<Vec<RegisterValue>>),
+        (status = 400, description = "value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout", body = ErrorEnvelope),
+    )
+)]
+pub async fn get_registers(
+    client: String,
+    params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut client_config = get_client_config(&client, &clients).await?;
+    if is_fresh_read(&params)? {
+        client_config.coils.clear();
+        read_fresh(client_config, &clients, &registry, &connections).await?;
+    }
+    let clients = clients.lock().await;
+    let client = get_shared_client(&client, &clients)?;
+    let now = SystemTime::now();
+    let values: Vec<RegisterValue> = client
+        .registers
+        .iter()
+        .map(|register| RegisterValue::new(client, register, now))
+        .collect();
+    Ok(reply::data(&values))
+}
+
+// GET /clients/{name}/registers/{register}?fresh=true - get the live value of one register
+#[utoipa::path(
+    get,
+    path = "/clients/{name}/registers/{register}",
+    tag = "values",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+        ("register" = String, Path, description = "Name of the register"),
+        ("fresh" = Option<bool>, Query, description = "Read from the modbus client before answering"),
+    ),
+    responses(
+        (status = 200, description = "The live value of the register", body = Envelope<RegisterValue>),
+        (status = 400, description = "value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found, register_not_found", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout", body = ErrorEnvelope),
+    )
+)]
+pub async fn get_register(
+    client: String,
+    register: String,
+    params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut client_config = get_client_config(&client, &clients).await?;
+    if client_config.get_register_by_name(&register).is_none() {
+        return Err(warp::reject::custom(CustomErrors::ClientRegisterNotFound(
+            Some(register.clone()),
+        )));
+    }
+    if is_fresh_read(&params)? {
+        client_config.registers.retain(|item| item.name == register);
+        client_config.coils.clear();
+        read_fresh(client_config, &clients, &registry, &connections).await?;
+    }
+    let clients = clients.lock().await;
+    let client = get_shared_client(&client, &clients)?;
+    match client.get_register_by_name(&register) {
+        Some(item) => Ok(reply::data(RegisterValue::new(
+            client,
+            item,
+            SystemTime::now(),
+        ))),
+        None => Err(warp::reject::custom(CustomErrors::ClientRegisterNotFound(
+            Some(register.clone()),
+        ))),
+    }
+}
+
+// GET /clients/{name}/coils?fresh=true - get the live values of all coils and discrete inputs of a client
+#[utoipa::path(
+    get,
+    path = "/clients/{name}/coils",
+    tag = "values",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+        ("fresh" = Option<bool>, Query, description = "Read from the modbus client before answering"),
+    ),
+    responses(
+        (status = 200, description = "The live values of all coils and discrete inputs", body = Envelope<Vec<CoilValue>>),
+        (status = 400, description = "value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout", body = ErrorEnvelope),
+    )
+)]
+pub async fn get_coils(
+    client: String,
+    params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut client_config = get_client_config(&client, &clients).await?;
+    if is_fresh_read(&params)? {
+        client_config.registers.clear();
+        read_fresh(client_config, &clients, &registry, &connections).await?;
+    }
+    let clients = clients.lock().await;
+    let client = get_shared_client(&client, &clients)?;
+    let now = SystemTime::now();
+    let values: Vec<CoilValue> = client
+        .coils
+        .iter()
+        .map(|coil| CoilValue::new(client, coil, now))
+        .collect();
+    Ok(reply::data(&values))
+}
+
+// GET /clients/{name}/coils/{coil}?fresh=true - get the live value of one coil or discrete input
+#[utoipa::path(
+    get,
+    path = "/clients/{name}/coils/{coil}",
+    tag = "values",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+        ("coil" = String, Path, description = "Name of the coil or discrete input"),
+        ("fresh" = Option<bool>, Query, description = "Read from the modbus client before answering"),
+    ),
+    responses(
+        (status = 200, description = "The live value of the coil", body = Envelope<CoilValue>),
+        (status = 400, description = "value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found, coil_not_found", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout", body = ErrorEnvelope),
+    )
+)]
+pub async fn get_coil(
+    client: String,
+    coil: String,
+    params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let mut client_config = get_client_config(&client, &clients).await?;
+    if client_config.get_coil_by_name(&coil).is_none() {
+        return Err(warp::reject::custom(CustomErrors::ClientCoilNotFound(
+            Some(coil.clone()),
+        )));
+    }
+    if is_fresh_read(&params)? {
+        client_config.registers.clear();
+        client_config.coils.retain(|item| item.name == coil);
+        read_fresh(client_config, &clients, &registry, &connections).await?;
+    }
+    let clients = clients.lock().await;
+    let client = get_shared_client(&client, &clients)?;
+    match client.get_coil_by_name(&coil) {
+        Some(item) => Ok(reply::data(CoilValue::new(client, item, SystemTime::now()))),
+        None => Err(warp::reject::custom(CustomErrors::ClientCoilNotFound(
+            Some(coil.clone()),
+        ))),
+    }
+}
+
+// Copy the config of a client, so the clients are not locked while reading from the modbus client
+async fn get_client_config(
+    client: &str,
+    clients: &Arc<Mutex<Clients::Clients>>,
+) -> Result<Client, Rejection> {
+    match clients.lock().await.clients.get(client) {
+        Some(client_config) => Ok(client_config.clone()),
+        None => Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
+            client.to_string(),
+        )))),
+    }
+}
+
+// The client might have been deleted while it was read
+fn get_shared_client<'a>(
+    client: &str,
+    clients: &'a Clients::Clients,
+) -> Result<&'a Client, Rejection> {
+    match clients.clients.get(client) {
+        Some(shared_client) => Ok(shared_client),
+        None => Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
+            client.to_string(),
+        )))),
+    }
+}
+
+// Check the optional parameter fresh of the read routes. Defaults to the last polled values
+fn is_fresh_read(params: &HashMap<String, String>) -> Result<bool, Rejection> {
+    match params.get("fresh") {
+        Some(fresh) => match fresh.parse::<bool>() {
+            Ok(fresh) => Ok(fresh),
+            Err(_) => Err(warp::reject::custom(CustomErrors::ValueNotParsableToBool(
+                Some(fresh.clone()),
+            ))),
+        },
+        None => Ok(false),
+    }
+}
+
+// Parameters of the write routes which are no registers or coils
+const WRITE_FLAGS: [&str; 2] = ["confirm", "verify"];
+
+// Take an optional parameter of the write routes like confirm or verify, so it is not mistaken for a register or coil
+fn take_flag(params: &mut HashMap<String, String>, name: &str) -> Result<Option<bool>, Rejection> {
+    match params.remove(name) {
+        Some(flag) => match flag.parse::<bool>() {
+            Ok(flag) => Ok(Some(flag)),
+            Err(_) => Err(warp::reject::custom(CustomErrors::ValueNotParsableToBool(
+                Some(flag),
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+// The text of a value of the set-values body. Strings are taken without quotes
+fn get_value_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(text) => text.clone(),
+        value => value.to_string(),
+    }
+}
+
+// The audit entries of the items of a write request with their last polled values
+async fn get_audit_entries(
+    clients: &Arc<Mutex<Clients::Clients>>,
+    client: &str,
+    action: &str,
+    items: impl Iterator<Item = (String, String)>,
+) -> Vec<AuditEntry> {
+    let clients = clients.lock().await;
+    let client_config = clients.clients.get(client);
+    items
+        .map(|(name, value)| {
+            let mut entry = AuditEntry::new(action, client, Some(name.clone()));
+            entry.old_value =
+                client_config.and_then(|client| audit::get_old_value(client, action, &name));
+            entry.new_value = Some(value);
+            entry
+        })
+        .collect()
+}
+
+// The audit entry of a write route with query parameters. Only the first parameter besides the flags is written
+async fn get_param_audit_entries(
+    clients: &Arc<Mutex<Clients::Clients>>,
+    client: &str,
+    action: &str,
+    params: &HashMap<String, String>,
+) -> Vec<AuditEntry> {
+    let items = params
+        .iter()
+        .filter(|(name, _)| !WRITE_FLAGS.contains(&name.as_str()))
+        .take(1)
+        .map(|(name, value)| (name.clone(), value.clone()));
+    get_audit_entries(clients, client, action, items).await
+}
+
+// The result of an operation for the audit log: ok or the error code of the response
+fn get_result_code<T>(result: &Result<T, Rejection>) -> &'static str {
+    match result {
+        Ok(_) => audit::AUDIT_RESULT_OK,
+        Err(rejection) => rejection
+            .find::<CustomErrors>()
+            .map_or("unknown_error", CustomErrors::get_code),
+    }
+}
+
+// Read the registers and coils of the client config from the modbus client right away
+async fn read_fresh(
+    mut client_config: Client,
+    clients: &Arc<Mutex<Clients::Clients>>,
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    connections: &Arc<Mutex<ConnectionManager>>,
+) -> Result<(), Rejection> {
+    match read_data::read_now(&mut client_config, registry, clients, connections).await {
+        Ok(_) => Ok(()),
+        Err(ErrorRuntimeNoRejection::ConnectTimeout) => Err(warp::reject::custom(
+            CustomErrors::ClientConnectTimeout(Some(client_config.get_endpoint())),
+        )),
+        Err(_) => Err(warp::reject::custom(CustomErrors::ClientNotAbleToConnect(
+            Some(client_config.get_endpoint()),
+        ))),
+    }
+}
+
 // DELETE /clients/{name}  - delete one client by name
+#[utoipa::path(
+    delete,
+    path = "/clients/{name}",
+    tag = "clients",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+    ),
+    responses(
+        (status = 200, description = "The deleted client", body = Envelope<Client>),
+        (status = 404, description = "client_not_found", body = ErrorEnvelope),
+        (status = 500, description = "config_storage_error, metrics_error, json_serialize_error", body = ErrorEnvelope),
+    )
+)]
 pub async fn delete_client(
     client: String,
     clients: Arc<Mutex<Clients::Clients>>,
     registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    pollers: Arc<Mutex<Pollers>>,
+    audit: AuditContext,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let entries = vec![AuditEntry::new(
+        audit::AUDIT_ACTION_DELETE_CLIENT,
+        &client,
+        None,
+    )];
+    let deleted = remove_client(client, clients, registry, connections, pollers).await;
+    audit.record(entries, get_result_code(&deleted)).await;
+    deleted
+}
+
+// Stop polling a client and remove its config and metrics
+async fn remove_client(
+    client: String,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    pollers: Arc<Mutex<Pollers>>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     log::info!("Trying to delete client via DELETE /clients/{}.", &client);
     let config_path = clients.lock().await.get_config_path().to_owned();
     // Check if client exists
-    if let None = clients.lock().await.clients.get(&client) {
-        return Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
-            client.clone(),
-        ))));
+    let deleted_client = match clients.lock().await.clients.get(&client) {
+        Some(deleted_client) => deleted_client.clone(),
+        None => {
+            return Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
+                client.clone(),
+            ))))
+        }
     };
     // Unregister all client metrics from the registry
-    if let Err(e) = registry
-        .lock()
-        .await
-        .unregister_client(&clients.lock().await.clients.get(&client).unwrap().clone())
-    {
+    if let Err(e) = registry.lock().await.unregister_client(&deleted_client) {
         return Err(warp::reject::custom(e));
     }
-    // Remove the client from the Clients struct
+    // Stop polling the client, remove it from the Clients struct and close its connection
+    pollers.lock().await.stop(&client);
     clients.lock().await.delete_client(&client);
+    ConnectionManager::remove_connection(&connections, &client).await;
     // Remove the config from the local FS
     if let Err(e) = utils::delete_config(&client, &config_path) {
         return Err(warp::reject::custom(e));
     }
-    Ok(warp::reply::reply())
+    Ok(reply::data(&deleted_client))
+}
+
+// PUT /clients/{name} - replace the config of a client with a json body
+#[utoipa::path(
+    put,
+    path = "/clients/{name}",
+    tag = "clients",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+    ),
+    request_body = Client,
+    responses(
+        (status = 200, description = "The updated client", body = Envelope<Client>),
+        (status = 404, description = "client_not_found", body = ErrorEnvelope),
+        (status = 409, description = "client_name_not_changeable", body = ErrorEnvelope),
+        (status = 422, description = "Invalid client config, e.g. register_length_not_valid, label_not_valid, name_not_valid", body = ErrorEnvelope),
+        (status = 500, description = "config_storage_error, metrics_error, json_serialize_error", body = ErrorEnvelope),
+    )
+)]
+pub async fn update_client(
+    client: String,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    pollers: Arc<Mutex<Pollers>>,
+    audit: AuditContext,
+    client_input: Client,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Trying to update client via PUT /clients/{}.", &client);
+    let entries = vec![AuditEntry::new(
+        audit::AUDIT_ACTION_UPDATE_CLIENT,
+        &client,
+        None,
+    )];
+    let updated = replace_client(
+        client,
+        client_input,
+        clients,
+        registry,
+        connections,
+        pollers,
+    )
+    .await;
+    audit.record(entries, get_result_code(&updated)).await;
+    updated
+}
+
+// PATCH /clients/{name} - change parts of the config of a client with a json merge patch
+#[utoipa::path(
+    patch,
+    path = "/clients/{name}",
+    tag = "clients",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+    ),
+    request_body(content = Object, description = "JSON merge patch (RFC 7386) of the client config"),
+    responses(
+        (status = 200, description = "The patched client", body = Envelope<Client>),
+        (status = 404, description = "client_not_found", body = ErrorEnvelope),
+        (status = 409, description = "client_name_not_changeable", body = ErrorEnvelope),
+        (status = 422, description = "client_patch_not_valid or an invalid client config", body = ErrorEnvelope),
+        (status = 500, description = "config_storage_error, metrics_error, json_serialize_error", body = ErrorEnvelope),
+    )
+)]
+pub async fn patch_client(
+    client: String,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    pollers: Arc<Mutex<Pollers>>,
+    audit: AuditContext,
+    patch: serde_json::Value,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    log::info!("Trying to patch client via PATCH /clients/{}.", &client);
+    let entries = vec![AuditEntry::new(
+        audit::AUDIT_ACTION_UPDATE_CLIENT,
+        &client,
+        None,
+    )];
+    let patched = merge_client(client, clients, registry, connections, pollers, patch).await;
+    audit.record(entries, get_result_code(&patched)).await;
+    patched
+}
+
+// Apply a json merge patch to the config of a client
+async fn merge_client(
+    client: String,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    pollers: Arc<Mutex<Pollers>>,
+    patch: serde_json::Value,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let old_client = match clients.lock().await.clients.get(&client) {
+        Some(old_client) => old_client.clone(),
+        None => {
+            return Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
+                client.clone(),
+            ))))
+        }
+    };
+    let mut client_json = match serde_json::to_value(&old_client) {
+        Ok(client_json) => client_json,
+        Err(_) => return Err(warp::reject::custom(CustomErrors::JSONSerializeError)),
+    };
+    utils::merge_patch(&mut client_json, &patch);
+    let client_input: Client = match serde_json::from_value(client_json) {
+        Ok(client_input) => client_input,
+        Err(e) => {
+            return Err(warp::reject::custom(CustomErrors::ClientPatchNotValid(
+                Some(e.to_string()),
+            )))
+        }
+    };
+    replace_client(
+        client,
+        client_input,
+        clients,
+        registry,
+        connections,
+        pollers,
+    )
+    .await
+}
+
+// Replace the config of an existing client. Only the metrics of the changed registers and coils are registered again
+async fn replace_client(
+    client: String,
+    mut client_input: Client,
+    clients: Arc<Mutex<Clients::Clients>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    pollers: Arc<Mutex<Pollers>>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let config_path = clients.lock().await.get_config_path().to_owned();
+    let old_client = match clients.lock().await.clients.get(&client) {
+        Some(old_client) => old_client.clone(),
+        None => {
+            return Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
+                client.clone(),
+            ))))
+        }
+    };
+    // The name is the name of the config file and of the metrics
+    if client_input.name != client {
+        return Err(warp::reject::custom(CustomErrors::ClientNameNotChangeable(
+            Some(client.clone()),
+        )));
+    }
+    if let Err(e) = utils::check_client_strings(&serde_json::to_value(&client_input).unwrap()) {
+        return Err(warp::reject::custom(e));
+    }
+    if let Err(e) = client_input.verify() {
+        return Err(warp::reject::custom(e));
+    }
+    // Stop polling, so the old config does not update the metrics while they are changed
+    pollers.lock().await.stop(&client);
+    let updated = registry
+        .lock()
+        .await
+        .update_client(&old_client, &client_input);
+    if let Err(e) = updated {
+        pollers.lock().await.start(&client);
+        return Err(warp::reject::custom(e));
+    }
+    // Replace the local config file last, so it is only changed if the metrics could be updated
+    if let Err(e) = utils::write_config(&client_input, &config_path) {
+        let restored = registry
+            .lock()
+            .await
+            .update_client(&client_input, &old_client);
+        if restored.is_err() {
+            log::error!("The metrics of client {} could not be restored", client);
+        }
+        pollers.lock().await.start(&client);
+        return Err(warp::reject::custom(e));
+    }
+    client_input.keep_values(&old_client);
+    let updated_client = reply::data(&client_input);
+    clients
+        .lock()
+        .await
+        .add_client(client.clone(), client_input);
+    // The connection settings might have changed, so the poller opens a new connection
+    ConnectionManager::remove_connection(&connections, &client).await;
+    pollers.lock().await.start(&client);
+    Ok(updated_client)
 }
 
 // GET /metrics
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Prometheus text format of all registers, coils and exporter metrics", body = String, content_type = "text/plain"),
+        (status = 500, description = "metrics_error", body = ErrorEnvelope),
+    )
+)]
 pub async fn metrics_handler(
     registry: Arc<Mutex<PrometheusMetrics>>,
 ) -> Result<impl Reply, Rejection> {
@@ -156,7 +805,10 @@ pub async fn metrics_handler(
     let mut buffer = Vec::new();
 
     // Gather the metrics.
-    if let Err(_) = encoder.encode(&registry.lock().await.registry.gather(), &mut buffer) {
+    if encoder
+        .encode(&registry.lock().await.gather(), &mut buffer)
+        .is_err()
+    {
         return Err(warp::reject::custom(
             crate::errors::impls::ErrorRuntime::PrometheusErrorRegistry,
         ));
@@ -176,18 +828,297 @@ pub async fn metrics_handler(
     Ok(res)
 }
 
-// PUT /clients/{name}/set-register?{register name }={value} - set a value for a key in a client
+// GET /audit?from={unix ms}&to={unix ms}&client={name}&limit={n} - get the entries of the audit log
+#[utoipa::path(
+    get,
+    path = "/audit",
+    tag = "audit",
+    params(
+        ("from" = Option<u64>, Query, description = "Only entries at or after this unix time in milliseconds"),
+        ("to" = Option<u64>, Query, description = "Only entries at or before this unix time in milliseconds"),
+        ("client" = Option<String>, Query, description = "Only entries of this client"),
+        ("limit" = Option<u16>, Query, description = "Only the newest entries up to this number"),
+    ),
+    responses(
+        (status = 200, description = "The matching entries, oldest first", body = Envelope<Vec<AuditEntry>>),
+        (status = 400, description = "value_not_parsable", body = ErrorEnvelope),
+        (status = 500, description = "audit_log_error", body = ErrorEnvelope),
+    )
+)]
+pub async fn get_audit(
+    mut params: HashMap<String, String>,
+    audit_log: Arc<Mutex<AuditLog>>,
+) -> Result<impl Reply, Rejection> {
+    let get_timestamp = |value: String| match value.parse::<u64>() {
+        Ok(timestamp_ms) => Ok(timestamp_ms),
+        Err(_) => Err(warp::reject::custom(
+            CustomErrors::ValueNotParsableToTimestamp(Some(value)),
+        )),
+    };
+    let filter = AuditFilter {
+        from_ms: params.remove("from").map(get_timestamp).transpose()?,
+        to_ms: params.remove("to").map(get_timestamp).transpose()?,
+        client: params.remove("client"),
+        limit: match params.remove("limit").map(|limit| limit.parse::<u16>()) {
+            Some(Ok(limit)) => Some(limit as usize),
+            Some(Err(_)) => {
+                return Err(warp::reject::custom(CustomErrors::ValueNotParsableToU16(
+                    Some("limit".to_string()),
+                )))
+            }
+            None => None,
+        },
+    };
+    match audit_log.lock().await.query(&filter) {
+        Ok(entries) => Ok(reply::data(entries)),
+        Err(e) => Err(warp::reject::custom(e)),
+    }
+}
+
+// PUT /clients/{name}/set-register?{register name }={value} - set a value for a key in a client.
+// The value is encoded with the datatype, byte order and scaling of the register
+#[utoipa::path(
+    put,
+    path = "/clients/{name}/set-register",
+    tag = "writes",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+        ("values" = HashMap<String, String>, Query, style = Form, explode,
+            description = "{register_name}={value}. A number of the datatype, the final value of scaled registers, a state name or the text of string registers"),
+        ("confirm" = Option<bool>, Query, description = "Confirms the write of registers and coils with requires_confirmation"),
+        ("verify" = Option<bool>, Query, description = "Read the value back after the write. Overrides verify_write of the register or coil"),
+    ),
+    responses(
+        (status = 200, description = "The written value", body = Envelope<WriteResult>),
+        (status = 400, description = "no_parameters_provided, value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found, register_not_found", body = ErrorEnvelope),
+        (status = 409, description = "interlock_active", body = ErrorEnvelope),
+        (status = 422, description = "register_not_writable, value_out_of_range, read_only, value_below_min, value_above_max, value_not_allowed", body = ErrorEnvelope),
+        (status = 428, description = "confirmation_required", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect, register_write_failed, write_not_verified", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout, client_write_timeout", body = ErrorEnvelope),
+    )
+)]
 pub async fn write_register(
     client: String,
     params: HashMap<String, String>,
     clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    audit: AuditContext,
+) -> Result<impl Reply, Rejection> {
+    let entries = get_param_audit_entries(
+        &clients,
+        &client,
+        audit::AUDIT_ACTION_WRITE_REGISTER,
+        &params,
+    )
+    .await;
+    let written = set_register(client, params, clients, connections, registry).await;
+    audit.record(entries, get_result_code(&written)).await;
+    written
+}
+
+// Write one register of the set-register route
+async fn set_register(
+    client: String,
+    mut params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
 ) -> Result<impl Reply, Rejection> {
     // Check if parameters are provided and Get parameter
+    let confirmed = take_flag(&mut params, "confirm")?.unwrap_or(false);
+    let verify = take_flag(&mut params, "verify")?;
     if params.is_empty() {
         return Err(warp::reject::custom(CustomErrors::NoParametersProvided));
     }
     let param = params.iter().next().unwrap();
-    // Check if the value can be parsed as u16
+    // Check if client exist and encode the value before connecting
+    let client_config = get_client_config(&client, &clients).await?;
+    let (unit_id, address, words) =
+        encode_register_write(&client_config, param.0, param.1, confirmed)?;
+    // Write through the connection of the client, which is shared with the poller
+    let connection = connections.lock().await.get_connection(&client);
+    let mut connection = connection.lock().await;
+    connect_for_write(&client_config, &mut connection, &registry).await?;
+    // Single registers keep using FC06, multi word datatypes are written at once with FC16
+    let written = match words.len() {
+        1 => connection.write_register(unit_id, address, words[0]).await,
+        _ => connection.write_registers(unit_id, address, &words).await,
+    };
+    registry.lock().await.exporter.inc_write(
+        &client,
+        written.as_ref().map_or_else(|e| e.get_kind(), |_| WRITE_RESULT_OK),
+    );
+    match written {
+        Ok(_) => log::info!("Successfully wrote to register {}", param.0),
+        Err(ErrorRuntimeNoRejection::WriteTimeout) => {
+            return Err(warp::reject::custom(CustomErrors::ClientWriteTimeout(
+                Some(param.0.clone()),
+            )))
+        }
+        Err(_) => {
+            return Err(warp::reject::custom(
+                CustomErrors::ClientRegisterWriteGenericError,
+            ))
+        }
+    }
+    // Store the written value, the value read back overwrites it if the write is verified
+    let register = client_config.get_register_by_name(param.0).unwrap();
+    store_written(
+        &client_config,
+        &[(register, &words)],
+        &[],
+        &registry,
+        &clients,
+    )
+    .await;
+    // Read the register back if the write is verified
+    let read_back = match verify.or(register.verify_write).unwrap_or(false) {
+        true => {
+            wait_verify_delay([register.verify_delay_ms].into_iter()).await;
+            let written = [(register, words, None)];
+            let read_back = read_back_registers(
+                &client_config,
+                &written,
+                &mut connection,
+                &registry,
+                &clients,
+            )
+            .await;
+            check_read_back(read_back)?.pop()
+        }
+        false => None,
+    };
+    Ok(reply::data(WriteResult {
+        client: client.clone(),
+        item: param.0.clone(),
+        value: param.1.clone(),
+        read_back,
+    }))
+}
+
+// Check if the register exists and is writable and encode the value with its datatype, byte order and scaling.
+// The value must meet the write constraints of the register. Returns the unit id, the address and the words to write
+fn encode_register_write(
+    client: &Client,
+    name: &str,
+    value: &str,
+    confirmed: bool,
+) -> Result<(u8, u16, Vec<u16>), Rejection> {
+    let register = match client.get_register_by_name(name) {
+        Some(register) => register,
+        None => {
+            return Err(warp::reject::custom(CustomErrors::ClientRegisterNotFound(
+                Some(name.to_string()),
+            )))
+        }
+    };
+    // Only holding registers are writable
+    if client.is_register_input(name) {
+        return Err(warp::reject::custom(
+            CustomErrors::ClientRegisterNotWritable(Some(name.to_string())),
+        ));
+    }
+    let words = register
+        .encode_input(value, &client.get_byteorder())
+        .map_err(warp::reject::custom)?;
+    write_guard::check_register_write(client, register, Some(value), confirmed)
+        .map_err(warp::reject::custom)?;
+    Ok((
+        client.resolve_unit_id(register.unit_id),
+        register.address,
+        words,
+    ))
+}
+
+// Connect to the modbus client before a write. Failed connects are counted as failed writes
+async fn connect_for_write(
+    client_config: &Client,
+    connection: &mut Connection,
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+) -> Result<(), Rejection> {
+    let connected = connection.connect(client_config).await;
+    if let Err(e) = &connected {
+        registry
+            .lock()
+            .await
+            .exporter
+            .inc_write(&client_config.name, e.get_kind());
+    }
+    match connected {
+        Ok(_) => Ok(()),
+        Err(ErrorRuntimeNoRejection::ConnectTimeout) => Err(warp::reject::custom(
+            CustomErrors::ClientConnectTimeout(Some(client_config.get_endpoint())),
+        )),
+        Err(_) => Err(warp::reject::custom(CustomErrors::ClientNotAbleToConnect(
+            Some(client_config.get_endpoint()),
+        ))),
+    }
+}
+
+// PUT /clients/{name}/set-bitfield?{register name}_{bitfield name}={value} - set the bits of a bitfield in a register.
+// The other bits of the register are kept. Uses a mask write (FC22) if the register is configured for it, otherwise
+// the register is read and written again while the connection is locked
+#[utoipa::path(
+    put,
+    path = "/clients/{name}/set-bitfield",
+    tag = "writes",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+        ("values" = HashMap<String, String>, Query, style = Form, explode,
+            description = "{register_name}_{bitfield_name}={value}"),
+        ("confirm" = Option<bool>, Query, description = "Confirms the write of registers and coils with requires_confirmation"),
+        ("verify" = Option<bool>, Query, description = "Read the field back after the write. Overrides verify_write of the register"),
+    ),
+    responses(
+        (status = 200, description = "The written value", body = Envelope<WriteResult>),
+        (status = 400, description = "no_parameters_provided, value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found, bitfield_not_found", body = ErrorEnvelope),
+        (status = 409, description = "interlock_active", body = ErrorEnvelope),
+        (status = 422, description = "register_not_writable, value_out_of_range, read_only", body = ErrorEnvelope),
+        (status = 428, description = "confirmation_required", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect, register_write_failed, write_not_verified", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout, client_write_timeout", body = ErrorEnvelope),
+    )
+)]
+pub async fn write_bitfield(
+    client: String,
+    params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    audit: AuditContext,
+) -> Result<impl Reply, Rejection> {
+    let entries = get_param_audit_entries(
+        &clients,
+        &client,
+        audit::AUDIT_ACTION_WRITE_BITFIELD,
+        &params,
+    )
+    .await;
+    let written = set_bitfield(client, params, clients, connections, registry).await;
+    audit.record(entries, get_result_code(&written)).await;
+    written
+}
+
+// Write one bitfield of the set-bitfield route
+async fn set_bitfield(
+    client: String,
+    mut params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+) -> Result<impl Reply, Rejection> {
+    // Check if parameters are provided and Get parameter
+    let confirmed = take_flag(&mut params, "confirm")?.unwrap_or(false);
+    let verify = take_flag(&mut params, "verify")?;
+    if params.is_empty() {
+        return Err(warp::reject::custom(CustomErrors::NoParametersProvided));
+    }
+    let param = params.iter().next().unwrap();
+    // Check if the value of the bitfield can be parsed as an unsigned integer. Bitfields are not scaled
     let value = match param.1.parse::<u16>() {
         Ok(v) => v,
         Err(_) => {
@@ -197,96 +1128,191 @@ pub async fn write_register(
         }
     };
     // Check if client exist
-    if let None = clients.lock().await.clients.get(&client) {
-        return Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
-            client.clone(),
-        ))));
+    let client_config = match clients.lock().await.clients.get(&client) {
+        Some(client_config) => client_config.clone(),
+        None => {
+            return Err(warp::reject::custom(CustomErrors::ClientNotFound(Some(
+                client.clone(),
+            ))))
+        }
     };
-    // Check if the register to write is a register in the client.registers
-    if let None = clients
-        .lock()
-        .await
-        .clients
-        .get(&client)
-        .unwrap()
-        .get_register_by_name(param.0)
-    {
-        return Err(warp::reject::custom(CustomErrors::ClientRegisterNotFound(
-            Some(param.0.clone()),
-        )));
+    // Check if the bitfield exists and its register is a writable 16 bit register
+    let (register, bitfield) = match client_config.get_bitfield_by_name(param.0) {
+        Some((register, bitfield)) => (register.clone(), bitfield.clone()),
+        None => {
+            return Err(warp::reject::custom(CustomErrors::ClientBitfieldNotFound(
+                Some(param.0.clone()),
+            )))
+        }
     };
-    // Check if the register in writable = is holding register
-    if clients
-        .lock()
-        .await
-        .clients
-        .get(&client)
-        .unwrap()
-        .is_register_input(param.0)
-    {
+    if register.objecttype == "input" {
+        return Err(warp::reject::custom(
+            CustomErrors::ClientRegisterNotWritable(Some(register.name.clone())),
+        ));
+    }
+    if register.get_length() > 1 {
         return Err(warp::reject::custom(
-            CustomErrors::ClientRegisterNotWritable(Some(param.0.clone())),
+            CustomErrors::ClientRegisterMultiWordNotWritable(Some(register.name.clone())),
         ));
+    }
+    write_guard::check_register_write(&client_config, &register, None, confirmed)
+        .map_err(warp::reject::custom)?;
+    // Check if the value fits into the bitfield
+    let or_mask = match bitfield.set_value(0, value as u64) {
+        Some(or_mask) => or_mask,
+        None => {
+            return Err(warp::reject::custom(
+                CustomErrors::ClientRegisterValueOutOfRange(Some(param.0.clone())),
+            ))
+        }
     };
-    // Get the address of the register
-    let address = clients
-        .lock()
-        .await
-        .clients
-        .get(&client)
-        .unwrap()
-        .get_register_by_name(param.0)
-        .unwrap()
-        .address;
-    // Context for modbus client
-    let mut ctx_tmp =
-        match utils::create_ctx(clients.lock().await.clients.get(&client).unwrap()).await {
-            Ok(ctx) => ctx,
-            Err(_) => {
-                return Err(warp::reject::custom(CustomErrors::ClientNotAbleToConnect(
-                    Some(
-                        clients
-                            .lock()
-                            .await
-                            .clients
-                            .get(&client)
-                            .unwrap()
-                            .get_ip_address(),
-                    ),
-                )));
+    let unit_id = client_config.resolve_unit_id(register.unit_id);
+    let byteorder = client_config.get_byteorder();
+    // Write through the connection of the client, which is shared with the poller
+    let connection = connections.lock().await.get_connection(&client);
+    let mut connection = connection.lock().await;
+    connect_for_write(&client_config, &mut connection, &registry).await?;
+    // The masks are encoded in the byte order of the register like the value itself. Returns the new raw value
+    // of the register: the value read before the write or the last polled value with the bitfield set
+    let written = if register.mask_write == Some(true) {
+        let and_mask = !register.encode_value(bitfield.get_mask(), &byteorder)[0];
+        let or_mask = register.encode_value(or_mask, &byteorder)[0];
+        connection
+            .mask_write_register(unit_id, register.address, and_mask, or_mask)
+            .await
+            .map(|_| {
+                register
+                    .last_updated
+                    .and_then(|_| bitfield.set_value(register.value, value as u64))
+            })
+    } else {
+        match connection
+            .read_registers(unit_id, &register.objecttype, register.address, 1)
+            .await
+        {
+            Ok(words) => {
+                let mut current = register.clone();
+                current.set_value_from_words(&words, &byteorder);
+                let raw_value = bitfield.set_value(current.value, value as u64).unwrap();
+                let words = register.encode_value(raw_value, &byteorder);
+                connection
+                    .write_register(unit_id, register.address, words[0])
+                    .await
+                    .map(|_| Some(raw_value))
             }
-        };
-    // Try to write register
-    match ctx_tmp.write_single_register(address, value).await {
-        Ok(_) => {
-            log::info!("Successfully wrote to input register {}", param.0);
-            ctx_tmp.disconnect().await.unwrap();
-            return Ok(warp::reply::with_status(
-                "Wrote register!".to_string(),
-                StatusCode::OK,
-            ));
+            Err(e) => Err(e),
+        }
+    };
+    registry.lock().await.exporter.inc_write(
+        &client,
+        written.as_ref().map_or_else(|e| e.get_kind(), |_| WRITE_RESULT_OK),
+    );
+    let raw_value = match written {
+        Ok(raw_value) => raw_value,
+        Err(ErrorRuntimeNoRejection::WriteTimeout) | Err(ErrorRuntimeNoRejection::ReadTimeout) => {
+            return Err(warp::reject::custom(CustomErrors::ClientWriteTimeout(
+                Some(param.0.clone()),
+            )))
         }
         Err(_) => {
-            ctx_tmp.disconnect().await.unwrap();
             return Err(warp::reject::custom(
                 CustomErrors::ClientRegisterWriteGenericError,
-            ));
+            ))
         }
+    };
+    log::info!("Successfully wrote to bitfield {}", param.0);
+    // Store the new value of the register, so the metrics and the routes show it before the next poll
+    if let Some(raw_value) = raw_value {
+        let words = register.encode_value(raw_value, &byteorder);
+        store_written(
+            &client_config,
+            &[(&register, &words)],
+            &[],
+            &registry,
+            &clients,
+        )
+        .await;
     }
+    // Read the register back if the write is verified. Only the bits of the field are compared
+    let read_back = match verify.or(register.verify_write).unwrap_or(false) {
+        true => {
+            wait_verify_delay([register.verify_delay_ms].into_iter()).await;
+            let words = register.encode_value(or_mask, &byteorder);
+            let written = [(&register, words, Some(&bitfield))];
+            let read_back = read_back_registers(
+                &client_config,
+                &written,
+                &mut connection,
+                &registry,
+                &clients,
+            )
+            .await;
+            check_read_back(read_back)?.pop()
+        }
+        false => None,
+    };
+    Ok(reply::data(WriteResult {
+        client: client.clone(),
+        item: param.0.clone(),
+        value: param.1.clone(),
+        read_back,
+    }))
 }
 
 // PUT /clients/{name}/set-coil?{coil name }={value} - set a value for a key in a client
+#[utoipa::path(
+    put,
+    path = "/clients/{name}/set-coil",
+    tag = "writes",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+        ("values" = HashMap<String, String>, Query, style = Form, explode,
+            description = "{coil_name}={value} with true or false"),
+        ("confirm" = Option<bool>, Query, description = "Confirms the write of registers and coils with requires_confirmation"),
+        ("verify" = Option<bool>, Query, description = "Read the value back after the write. Overrides verify_write of the register or coil"),
+    ),
+    responses(
+        (status = 200, description = "The written value", body = Envelope<WriteResult>),
+        (status = 400, description = "no_parameters_provided, value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found, coil_not_found", body = ErrorEnvelope),
+        (status = 409, description = "interlock_active", body = ErrorEnvelope),
+        (status = 422, description = "coil_not_writable, read_only", body = ErrorEnvelope),
+        (status = 428, description = "confirmation_required", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect, coil_write_failed, write_not_verified", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout, client_write_timeout", body = ErrorEnvelope),
+    )
+)]
 pub async fn write_coil(
     client: String,
     params: HashMap<String, String>,
     clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    audit: AuditContext,
+) -> Result<impl Reply, Rejection> {
+    let entries =
+        get_param_audit_entries(&clients, &client, audit::AUDIT_ACTION_WRITE_COIL, &params).await;
+    let written = set_coil(client, params, clients, connections, registry).await;
+    audit.record(entries, get_result_code(&written)).await;
+    written
+}
+
+// Write one coil of the set-coil route
+async fn set_coil(
+    client: String,
+    mut params: HashMap<String, String>,
+    clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
 ) -> Result<impl Reply, Rejection> {
     // Get parameter
+    let confirmed = take_flag(&mut params, "confirm")?.unwrap_or(false);
+    let verify = take_flag(&mut params, "verify")?;
     if params.is_empty() {
         return Err(warp::reject::custom(CustomErrors::NoParametersProvided));
     }
     let param = params.iter().next().unwrap();
-    // Check if the value can be parsed as u16
+    // Check if the value can be parsed as bool, true or false
     let value = match param.1.parse::<bool>() {
         Ok(v) => v,
         Err(_) => {
@@ -327,49 +1353,1346 @@ pub async fn write_coil(
             Some(param.0.clone()),
         )));
     };
-    // Get the address of the coil
-    let address = clients
-        .lock()
-        .await
-        .clients
-        .get(&client)
-        .unwrap()
-        .get_coil_by_name(param.0)
-        .unwrap()
-        .address;
-    // Context for modbus client
-    let mut ctx_tmp =
-        match utils::create_ctx(clients.lock().await.clients.get(&client).unwrap()).await {
-            Ok(ctx) => ctx,
-            Err(_) => {
-                return Err(warp::reject::custom(CustomErrors::ClientNotAbleToConnect(
-                    Some(
-                        clients
-                            .lock()
-                            .await
-                            .clients
-                            .get(&client)
-                            .unwrap()
-                            .get_ip_address(),
-                    ),
-                )));
-            }
-        };
+    // Check the write constraints and get the address and unit id of the coil. The unit id falls back to
+    // the unit id of the client
+    let client_config = get_client_config(&client, &clients).await?;
+    let coil = client_config.get_coil_by_name(param.0).unwrap();
+    write_guard::check_coil_write(&client_config, coil, value, confirmed)
+        .map_err(warp::reject::custom)?;
+    let address = coil.address;
+    let unit_id = client_config.resolve_unit_id(coil.unit_id);
+    // Write through the connection of the client, which is shared with the poller
+    let connection = connections.lock().await.get_connection(&client);
+    let mut connection = connection.lock().await;
+    connect_for_write(&client_config, &mut connection, &registry).await?;
     // Try to write coil
-    match ctx_tmp.write_single_coil(address, value).await {
-        Ok(_) => {
-            log::info!("Successfully wrote to coil {}", param.0);
-            ctx_tmp.disconnect().await.unwrap();
-            return Ok(warp::reply::with_status(
-                "Wrote coil!".to_string(),
-                StatusCode::OK,
-            ));
+    let written = connection.write_coil(unit_id, address, value).await;
+    registry.lock().await.exporter.inc_write(
+        &client,
+        written
+            .as_ref()
+            .map_or_else(|e| e.get_kind(), |_| WRITE_RESULT_OK),
+    );
+    match written {
+        Ok(_) => log::info!("Successfully wrote to coil {}", param.0),
+        Err(ErrorRuntimeNoRejection::WriteTimeout) => {
+            return Err(warp::reject::custom(CustomErrors::ClientWriteTimeout(
+                Some(param.0.clone()),
+            )))
         }
         Err(_) => {
-            ctx_tmp.disconnect().await.unwrap();
             return Err(warp::reject::custom(
+                CustomErrors::ClientCoilWriteGenericError,
+            ))
+        }
+    }
+    // Store the written value, the value read back overwrites it if the write is verified
+    store_written(&client_config, &[], &[(coil, value)], &registry, &clients).await;
+    // Read the coil back if the write is verified
+    let read_back = match verify.or(coil.verify_write).unwrap_or(false) {
+        true => {
+            wait_verify_delay([coil.verify_delay_ms].into_iter()).await;
+            let written = [(coil, value)];
+            let read_back = read_back_coils(
+                &client_config,
+                &written,
+                &mut connection,
+                &registry,
+                &clients,
+            )
+            .await;
+            check_read_back(read_back)?.pop()
+        }
+        false => None,
+    };
+    Ok(reply::data(WriteResult {
+        client: client.clone(),
+        item: param.0.clone(),
+        value: param.1.clone(),
+        read_back,
+    }))
+}
+
+// PUT /clients/{name}/set-values - write many registers and coils of a client with a json body.
+// All values are checked before the first write. Consecutive registers and coils are written with one request
+#[utoipa::path(
+    put,
+    path = "/clients/{name}/set-values",
+    tag = "writes",
+    params(
+        ("name" = String, Path, description = "Name of the client"),
+    ),
+    request_body = WriteValues,
+    responses(
+        (status = 200, description = "The written values", body = Envelope<Vec<WriteResult>>),
+        (status = 400, description = "no_parameters_provided, value_not_parsable", body = ErrorEnvelope),
+        (status = 404, description = "client_not_found, register_not_found, coil_not_found", body = ErrorEnvelope),
+        (status = 409, description = "interlock_active", body = ErrorEnvelope),
+        (status = 422, description = "register_not_writable, coil_not_writable, value_out_of_range, read_only, value_below_min, value_above_max, value_not_allowed, body_not_valid", body = ErrorEnvelope),
+        (status = 428, description = "confirmation_required", body = ErrorEnvelope),
+        (status = 502, description = "client_not_able_to_connect, register_write_failed, coil_write_failed, write_not_verified", body = ErrorEnvelope),
+        (status = 504, description = "client_connect_timeout, client_write_timeout", body = ErrorEnvelope),
+    )
+)]
+pub async fn write_values(
+    client: String,
+    clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    audit: AuditContext,
+    values: WriteValues,
+) -> Result<impl Reply, Rejection> {
+    let registers = values
+        .registers
+        .iter()
+        .map(|(name, value)| (name.clone(), get_value_text(value)));
+    let mut entries = get_audit_entries(
+        &clients,
+        &client,
+        audit::AUDIT_ACTION_WRITE_REGISTER,
+        registers,
+    )
+    .await;
+    let coils = values
+        .coils
+        .iter()
+        .map(|(name, value)| (name.clone(), value.to_string()));
+    entries
+        .extend(get_audit_entries(&clients, &client, audit::AUDIT_ACTION_WRITE_COIL, coils).await);
+    let (written, results) = set_values(client, clients, connections, registry, values).await;
+    audit.record_each(entries, &results).await;
+    written
+}
+
+// Write the registers and coils of the set-values route. Returns the response and the audit result of each item
+async fn set_values(
+    client: String,
+    clients: Arc<Mutex<Clients::Clients>>,
+    connections: Arc<Mutex<ConnectionManager>>,
+    registry: Arc<Mutex<PrometheusMetrics>>,
+    values: WriteValues,
+) -> (Result<impl Reply, Rejection>, Vec<&'static str>) {
+    // The audit result of each item, registers first. Items which were not written take the result of the response
+    let mut item_results = vec![None; values.registers.len() + values.coils.len()];
+    let written = async {
+        if values.registers.is_empty() && values.coils.is_empty() {
+            return Err(warp::reject::custom(CustomErrors::NoParametersProvided));
+        }
+        let client_config = get_client_config(&client, &clients).await?;
+        // Interlocks are checked against the values after the write, so the request can not change their conditions
+        let register_inputs: Vec<(String, String)> = values
+            .registers
+            .iter()
+            .map(|(name, value)| (name.clone(), get_value_text(value)))
+            .collect();
+        let pending = write_guard::apply_pending_values(
+            &client_config,
+            register_inputs
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+            values
+                .coils
+                .iter()
+                .map(|(name, value)| (name.as_str(), *value)),
+        );
+        // Encode all values first, so an invalid value does not leave the client half written
+        let mut results = Vec::new();
+        let mut registers = Vec::new();
+        // The index of the result and the words of the registers to write
+        let mut register_indexes = HashMap::new();
+        // The registers and coils to read back with the index of their result
+        let mut verified_registers = Vec::new();
+        let mut verified_coils = Vec::new();
+        for (name, value) in register_inputs {
+            let (unit_id, address, words) =
+                encode_register_write(&pending, &name, &value, values.confirm)?;
+            let register = client_config.get_register_by_name(&name).unwrap();
+            if values.verify.or(register.verify_write).unwrap_or(false) {
+                verified_registers.push((results.len(), (register, words.clone(), None)));
+            }
+            register_indexes.insert(name.clone(), (results.len(), words.clone()));
+            registers.push((name.clone(), unit_id, address, words));
+            results.push(WriteResult {
+                client: client.clone(),
+                item: name.clone(),
+                value,
+                read_back: None,
+            });
+        }
+        let mut coils = Vec::new();
+        let mut coil_indexes = HashMap::new();
+        for (name, value) in &values.coils {
+            let coil = match client_config.get_coil_by_name(name) {
+                Some(coil) => coil,
+                None => {
+                    return Err(warp::reject::custom(CustomErrors::ClientCoilNotFound(
+                        Some(name.clone()),
+                    )))
+                }
+            };
+            if !client_config.is_coil_input(name) {
+                return Err(warp::reject::custom(CustomErrors::ClientCoilNotInput(
+                    Some(name.clone()),
+                )));
+            }
+            write_guard::check_coil_write(&pending, coil, *value, values.confirm)
+                .map_err(warp::reject::custom)?;
+            if values.verify.or(coil.verify_write).unwrap_or(false) {
+                verified_coils.push((results.len(), (coil, *value)));
+            }
+            let unit_id = client_config.resolve_unit_id(coil.unit_id);
+            coil_indexes.insert(name.clone(), results.len());
+            coils.push((name.clone(), unit_id, coil.address, vec![*value]));
+            results.push(WriteResult {
+                client: client.clone(),
+                item: name.clone(),
+                value: value.to_string(),
+                read_back: None,
+            });
+        }
+        // Write through the connection of the client, which is shared with the poller
+        let connection = connections.lock().await.get_connection(&client);
+        let mut connection = connection.lock().await;
+        connect_for_write(&client_config, &mut connection, &registry).await?;
+        for block in write_plan::plan_writes(registers, write_plan::MAX_WRITE_REGISTERS) {
+            let written = match block.values.len() {
+                1 => {
+                    connection
+                        .write_register(block.unit_id, block.address, block.values[0])
+                        .await
+                }
+                _ => {
+                    connection
+                        .write_registers(block.unit_id, block.address, &block.values)
+                        .await
+                }
+            };
+            check_block_written(
+                &client,
+                &block.items,
+                written,
                 CustomErrors::ClientRegisterWriteGenericError,
-            ));
+                &registry,
+            )
+            .await?;
+            let mut written = Vec::new();
+            for name in &block.items {
+                let (index, words) = &register_indexes[name];
+                item_results[*index] = Some(audit::AUDIT_RESULT_OK);
+                written.push((
+                    client_config.get_register_by_name(name).unwrap(),
+                    words.as_slice(),
+                ));
+            }
+            store_written(&client_config, &written, &[], &registry, &clients).await;
+        }
+        for block in write_plan::plan_writes(coils, write_plan::MAX_WRITE_COILS) {
+            let written = match block.values.len() {
+                1 => {
+                    connection
+                        .write_coil(block.unit_id, block.address, block.values[0])
+                        .await
+                }
+                _ => {
+                    connection
+                        .write_coils(block.unit_id, block.address, &block.values)
+                        .await
+                }
+            };
+            check_block_written(
+                &client,
+                &block.items,
+                written,
+                CustomErrors::ClientCoilWriteGenericError,
+                &registry,
+            )
+            .await?;
+            let mut written = Vec::new();
+            for (name, value) in block.items.iter().zip(block.values) {
+                item_results[coil_indexes[name]] = Some(audit::AUDIT_RESULT_OK);
+                written.push((client_config.get_coil_by_name(name).unwrap(), value));
+            }
+            store_written(&client_config, &[], &written, &registry, &clients).await;
+        }
+        // Read the verified registers and coils back after the longest delay of them
+        if !verified_registers.is_empty() || !verified_coils.is_empty() {
+            let delays = verified_registers
+                .iter()
+                .map(|(_, (register, _, _))| register.verify_delay_ms)
+                .chain(
+                    verified_coils
+                        .iter()
+                        .map(|(_, (coil, _))| coil.verify_delay_ms),
+                );
+            wait_verify_delay(delays).await;
+            let (indexes, written): (Vec<usize>, Vec<_>) = verified_registers.into_iter().unzip();
+            let read_back = read_back_registers(
+                &client_config,
+                &written,
+                &mut connection,
+                &registry,
+                &clients,
+            )
+            .await;
+            let mut read_back: Vec<_> = indexes.into_iter().zip(read_back).collect();
+            let (indexes, written): (Vec<usize>, Vec<_>) = verified_coils.into_iter().unzip();
+            let coils_read_back = read_back_coils(
+                &client_config,
+                &written,
+                &mut connection,
+                &registry,
+                &clients,
+            )
+            .await;
+            read_back.extend(indexes.into_iter().zip(coils_read_back));
+            // Every item is read back, the response names the first item which did not take its value
+            let mut not_taken = None;
+            for (index, value) in read_back {
+                match value {
+                    Ok(value) => results[index].read_back = Some(value),
+                    Err(read_back) => {
+                        item_results[index] =
+                            Some(CustomErrors::ClientWriteNotVerified(None).get_code());
+                        not_taken.get_or_insert(read_back);
+                    }
+                }
+            }
+            if let Some(read_back) = not_taken {
+                return Err(warp::reject::custom(CustomErrors::ClientWriteNotVerified(
+                    Some(read_back),
+                )));
+            }
+        }
+        Ok(reply::data(results))
+    }
+    .await;
+    let result = get_result_code(&written);
+    let item_results = item_results
+        .into_iter()
+        .map(|item_result| item_result.unwrap_or(result))
+        .collect();
+    (written, item_results)
+}
+
+// Store the written values of registers and coils, so the metrics and the routes show them before the next poll
+async fn store_written(
+    client_config: &Client,
+    registers: &[(&Clients::Register, &[u16])],
+    coils: &[(&Clients::Coil, bool)],
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    clients: &Arc<Mutex<Clients::Clients>>,
+) {
+    let byteorder = client_config.get_byteorder();
+    let registers: Vec<_> = registers
+        .iter()
+        .map(|(register, words)| {
+            let mut written = (*register).clone();
+            written.set_value_from_words(words, &byteorder);
+            written
+        })
+        .collect();
+    let coils: Vec<_> = coils
+        .iter()
+        .map(|(coil, value)| {
+            let mut written = (*coil).clone();
+            written.value = *value;
+            written
+        })
+        .collect();
+    read_data::store_read_back(&client_config.name, &registers, &coils, registry, clients).await;
+}
+
+// Give the device time to apply the written values. Waits for the longest verify delay of the items
+async fn wait_verify_delay(delays: impl Iterator<Item = Option<u64>>) {
+    let delay = delays
+        .map(|delay| delay.unwrap_or(Clients::DEFAULT_VERIFY_DELAY_MS))
+        .max()
+        .unwrap_or(0);
+    tokio::time::sleep(Duration::from_millis(delay)).await;
+}
+
+// Read registers back after a write and compare them with the written words. Written bitfields only compare the bits
+// of the field. The values read back are stored right away. Returns for each register the value in the form of the
+// write request, the value of the field for bitfields, or why the register did not take its value
+async fn read_back_registers(
+    client_config: &Client,
+    written: &[(&Clients::Register, Vec<u16>, Option<&Clients::Bitfield>)],
+    connection: &mut Connection,
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    clients: &Arc<Mutex<Clients::Clients>>,
+) -> Vec<Result<String, String>> {
+    let byteorder = client_config.get_byteorder();
+    let mut read_back = Vec::new();
+    let mut values = Vec::new();
+    // The registers after a failed read are not read anymore
+    let mut read_failed = false;
+    for (register, words, bitfield) in written {
+        let not_read = format!("register {} could not be read back", register.name);
+        if read_failed {
+            values.push(Err(not_read));
+            continue;
+        }
+        let unit_id = client_config.resolve_unit_id(register.unit_id);
+        let read = connection
+            .read_registers(
+                unit_id,
+                &register.objecttype,
+                register.address,
+                register.get_length(),
+            )
+            .await;
+        let read_words = match read {
+            Ok(read_words) => read_words,
+            Err(_) => {
+                read_failed = true;
+                values.push(Err(not_read));
+                continue;
+            }
+        };
+        let mut read_register = (*register).clone();
+        read_register.set_value_from_words(&read_words, &byteorder);
+        let (value, is_taken) = match bitfield {
+            Some(bitfield) => {
+                let mut expected = (*register).clone();
+                expected.set_value_from_words(words, &byteorder);
+                let value = bitfield.get_value(read_register.value);
+                let is_taken = value == bitfield.get_value(expected.value);
+                (value.to_string(), is_taken)
+            }
+            None => (
+                read_register.get_input_value().unwrap_or_default(),
+                read_words == *words,
+            ),
+        };
+        values.push(match is_taken {
+            true => Ok(value),
+            false => Err(format!("register {} holds {}", register.name, value)),
+        });
+        read_back.push(read_register);
+    }
+    read_data::store_read_back(&client_config.name, &read_back, &[], registry, clients).await;
+    values
+}
+
+// Read coils back after a write and compare them with the written values. Works like read_back_registers
+async fn read_back_coils(
+    client_config: &Client,
+    written: &[(&Clients::Coil, bool)],
+    connection: &mut Connection,
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+    clients: &Arc<Mutex<Clients::Clients>>,
+) -> Vec<Result<String, String>> {
+    let mut read_back = Vec::new();
+    let mut values = Vec::new();
+    let mut read_failed = false;
+    for (coil, value) in written {
+        let not_read = format!("coil {} could not be read back", coil.name);
+        if read_failed {
+            values.push(Err(not_read));
+            continue;
+        }
+        let unit_id = client_config.resolve_unit_id(coil.unit_id);
+        let read = connection
+            .read_coils(unit_id, &coil.objecttype, coil.address, 1)
+            .await;
+        let read_value = match read.as_deref() {
+            Ok([read_value]) => *read_value,
+            _ => {
+                read_failed = true;
+                values.push(Err(not_read));
+                continue;
+            }
+        };
+        values.push(match read_value == *value {
+            true => Ok(read_value.to_string()),
+            false => Err(format!("coil {} is {}", coil.name, read_value)),
+        });
+        let mut read_coil = (*coil).clone();
+        read_coil.value = read_value;
+        read_back.push(read_coil);
+    }
+    read_data::store_read_back(&client_config.name, &[], &read_back, registry, clients).await;
+    values
+}
+
+// The values read back after a write, or write_not_verified with the first item which did not take its value
+fn check_read_back(read_back: Vec<Result<String, String>>) -> Result<Vec<String>, Rejection> {
+    read_back
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|not_taken| {
+            warp::reject::custom(CustomErrors::ClientWriteNotVerified(Some(not_taken)))
+        })
+}
+
+// Count the write of a block. A failed block stops the bulk write, the blocks before stay written
+async fn check_block_written(
+    client: &str,
+    items: &[String],
+    written: Result<(), ErrorRuntimeNoRejection>,
+    generic_error: CustomErrors,
+    registry: &Arc<Mutex<PrometheusMetrics>>,
+) -> Result<(), Rejection> {
+    registry.lock().await.exporter.inc_write(
+        client,
+        written
+            .as_ref()
+            .map_or_else(|e| e.get_kind(), |_| WRITE_RESULT_OK),
+    );
+    match written {
+        Ok(_) => {
+            log::info!("Successfully wrote to {}", items.join(", "));
+            Ok(())
+        }
+        Err(ErrorRuntimeNoRejection::WriteTimeout) => Err(warp::reject::custom(
+            CustomErrors::ClientWriteTimeout(Some(items.join(", "))),
+        )),
+        Err(_) => Err(warp::reject::custom(generic_error)),
+    }
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_routes {
+    use super::*;
+    use crate::connections::RequestPolicy;
+    use crate::utils::simulator::*;
+
+    async fn setup(
+        device: &str,
+        mask_write: bool,
+    ) -> (
+        Arc<Mutex<Clients::Clients>>,
+        Arc<Mutex<ConnectionManager>>,
+        Arc<Mutex<PrometheusMetrics>>,
+    ) {
+        let client = Client::new(format!(
+            r#"{{
+                "name": "test_client_rtu",
+                "protocol": "rtu",
+                "serial": {{ "device": "{}", "baud_rate": 19200 }},
+                "registers": [
+                    {{ "name": "status", "objecttype": "holding", "address": 16, "length": 1, "datatype": "uint16",
+                       "factor": 0, "value": 0, "mask_write": {},
+                       "bitfields": [ {{ "name": "mode", "bit": 4, "length": 3 }} ] }},
+                    {{ "name": "operating_mode", "objecttype": "holding", "address": 17, "length": 1,
+                       "datatype": "uint16", "factor": 0, "value": 0, "states": {{ "0": "off", "3": "heating" }} }}
+                ],
+                "coils": []
+            }}"#,
+            device, mask_write
+        ))
+        .unwrap();
+        let clients = Arc::new(Mutex::new(Clients::Clients::new("")));
+        clients.lock().await.add_client(client.name.clone(), client);
+        let connections = Arc::new(Mutex::new(ConnectionManager::new(RequestPolicy::default())));
+        let registry = Arc::new(Mutex::new(PrometheusMetrics::new(
+            crate::prometheus::METRICS_MODE_LABELED,
+            false,
+        )));
+        (clients, connections, registry)
+    }
+
+    // The audit log of the tests which do not check it
+    fn audit_context() -> AuditContext {
+        let path = std::env::temp_dir().join(format!("modbus_audit_routes_{}", std::process::id()));
+        AuditContext {
+            log: Arc::new(Mutex::new(AuditLog::new(path.to_str().unwrap(), 1024, 1))),
+            caller: "127.0.0.1".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_bitfield() {
+        for mask_write in [false, true] {
+            let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+            let (clients, connections, registry) = setup(&device, mask_write).await;
+            // The last polled value is the base of the stored value after a mask write
+            if let Some(client) = clients.lock().await.clients.get_mut("test_client_rtu") {
+                client.registers[0].value = 0x10;
+                client.registers[0].last_updated = Some(std::time::SystemTime::now());
+            }
+            let params = HashMap::from([("status_mode".to_string(), "5".to_string())]);
+            let result = write_bitfield(
+                "test_client_rtu".to_string(),
+                params,
+                clients.clone(),
+                connections.clone(),
+                registry.clone(),
+                audit_context(),
+            )
+            .await;
+            assert!(result.is_ok());
+            // The register returned its address 0x10 before. Bit 4 is replaced by the field
+            assert_eq!(simulated_slave.memory.lock().unwrap().holding[&0x10], 0x50);
+            // The new value is stored right away
+            let client = get_client_config("test_client_rtu", &clients)
+                .await
+                .unwrap();
+            assert_eq!(client.registers[0].value, 0x50);
+            // Values which do not fit into the field are rejected
+            let params = HashMap::from([("status_mode".to_string(), "8".to_string())]);
+            let result = write_bitfield(
+                "test_client_rtu".to_string(),
+                params,
+                clients,
+                connections,
+                registry,
+                audit_context(),
+            )
+            .await;
+            assert!(result.is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_register_state() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        let params = HashMap::from([("operating_mode".to_string(), "heating".to_string())]);
+        let result = write_register(
+            "test_client_rtu".to_string(),
+            params,
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+        )
+        .await;
+        let written = reply_data(result.unwrap()).await;
+        assert_eq!(written["item"], "operating_mode");
+        assert_eq!(written["value"], "heating");
+        assert_eq!(simulated_slave.memory.lock().unwrap().holding[&0x11], 3);
+        // The written value is stored without verify
+        let client = get_client_config("test_client_rtu", &clients)
+            .await
+            .unwrap();
+        let register = client.get_register_by_name("operating_mode").unwrap();
+        assert_eq!(register.value, 3);
+        assert!(register.last_updated.is_some());
+        // Unknown state names are no values either
+        let params = HashMap::from([("operating_mode".to_string(), "cooling".to_string())]);
+        let result = write_register(
+            "test_client_rtu".to_string(),
+            params,
+            clients,
+            connections,
+            registry,
+            audit_context(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_register_multi_word() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        clients
+            .lock()
+            .await
+            .clients
+            .get_mut("test_client_rtu")
+            .unwrap()
+            .registers
+            .push(
+                serde_json::from_value(serde_json::json!({
+                    "name": "setpoint", "objecttype": "holding", "address": 18, "length": 2,
+                    "datatype": "float32", "factor": 0, "value": 0
+                }))
+                .unwrap(),
+            );
+        let params = HashMap::from([("setpoint".to_string(), "21.5".to_string())]);
+        let result = write_register(
+            "test_client_rtu".to_string(),
+            params,
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+        )
+        .await;
+        assert!(result.is_ok());
+        {
+            let memory = simulated_slave.memory.lock().unwrap();
+            assert_eq!(memory.holding[&18], 0x41AC);
+            assert_eq!(memory.holding[&19], 0x0000);
+        }
+        // Values which do not fit into the datatype are rejected before connecting
+        let params = HashMap::from([("status".to_string(), "-1".to_string())]);
+        let result = write_register(
+            "test_client_rtu".to_string(),
+            params,
+            clients,
+            connections,
+            registry,
+            audit_context(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_values() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        for (name, address) in [("pump", 3), ("valve", 4), ("fan", 8)] {
+            clients
+                .lock()
+                .await
+                .clients
+                .get_mut("test_client_rtu")
+                .unwrap()
+                .coils
+                .push(Clients::Coil {
+                    name: name.to_string(),
+                    objecttype: "coil".to_string(),
+                    address,
+                    ..Default::default()
+                });
+        }
+        let values: WriteValues = serde_json::from_value(serde_json::json!({
+            "registers": { "status": 7, "operating_mode": "heating" },
+            "coils": { "pump": true, "valve": true, "fan": true }
+        }))
+        .unwrap();
+        let requests = simulated_slave.memory.lock().unwrap().requests;
+        let reply = write_values(
+            "test_client_rtu".to_string(),
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+            values,
+        )
+        .await
+        .unwrap();
+        let written = reply_data(reply).await;
+        assert_eq!(written.as_array().unwrap().len(), 5);
+        assert_eq!(written[0]["item"], "operating_mode");
+        assert_eq!(written[0]["value"], "heating");
+        assert_eq!(written[1]["value"], "7");
+        {
+            let memory = simulated_slave.memory.lock().unwrap();
+            assert_eq!(memory.holding[&16], 7);
+            assert_eq!(memory.holding[&17], 3);
+            assert!(memory.coils[&3] && memory.coils[&4] && memory.coils[&8]);
+            // One request for both registers, one for pump and valve and one for the fan
+            assert_eq!(memory.requests - requests, 3);
+        }
+        // Nothing is written if one of the values is not valid
+        let values: WriteValues = serde_json::from_value(serde_json::json!({
+            "registers": { "status": 9, "operating_mode": "cooling" }
+        }))
+        .unwrap();
+        let result = write_values(
+            "test_client_rtu".to_string(),
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+            values,
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(simulated_slave.memory.lock().unwrap().holding[&16], 7);
+        let result = write_values(
+            "test_client_rtu".to_string(),
+            clients,
+            connections,
+            registry,
+            audit_context(),
+            WriteValues::default(),
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_constraints() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        {
+            let mut clients = clients.lock().await;
+            let client = clients.clients.get_mut("test_client_rtu").unwrap();
+            client.registers[1].allowed_values = Some(vec![0.0, 3.0]);
+            client.registers[1].requires_confirmation = Some(true);
+            client.coils.push(
+                serde_json::from_value(serde_json::json!({
+                    "name": "pump", "objecttype": "coil", "address": 3, "value": false,
+                    "interlocks": [{ "register": "status", "operator": "<", "value": 50 }]
+                }))
+                .unwrap(),
+            );
+            assert!(client.verify().is_ok());
+        }
+        let write = |params: Vec<(&str, &str)>| {
+            write_register(
+                "test_client_rtu".to_string(),
+                params
+                    .into_iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                clients.clone(),
+                connections.clone(),
+                registry.clone(),
+                audit_context(),
+            )
+        };
+        let result = write(vec![("operating_mode", "heating")]).await;
+        assert_eq!(
+            result.err().unwrap().find::<CustomErrors>().unwrap().get_code(),
+            "confirmation_required"
+        );
+        let result = write(vec![("operating_mode", "1"), ("confirm", "true")]).await;
+        assert_eq!(
+            result.err().unwrap().find::<CustomErrors>().unwrap().get_code(),
+            "value_not_allowed"
+        );
+        let result = write(vec![("operating_mode", "heating"), ("confirm", "true")]).await;
+        assert!(result.is_ok());
+        assert_eq!(simulated_slave.memory.lock().unwrap().holding[&0x11], 3);
+        // The interlock of the pump needs a read of the status register
+        let params = HashMap::from([("pump".to_string(), "true".to_string())]);
+        let result = write_coil(
+            "test_client_rtu".to_string(),
+            params.clone(),
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+        )
+        .await;
+        assert_eq!(
+            result.err().unwrap().find::<CustomErrors>().unwrap().get_code(),
+            "interlock_active"
+        );
+        {
+            let mut clients = clients.lock().await;
+            let status = &mut clients.clients.get_mut("test_client_rtu").unwrap().registers[0];
+            status.value = 16;
+            status.last_updated = Some(SystemTime::now());
+        }
+        let result = write_coil(
+            "test_client_rtu".to_string(),
+            params,
+            clients,
+            connections,
+            registry,
+            audit_context(),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(simulated_slave.memory.lock().unwrap().coils[&3]);
+    }
+
+    #[tokio::test]
+    async fn test_write_values_interlock() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        {
+            let mut clients = clients.lock().await;
+            let client = clients.clients.get_mut("test_client_rtu").unwrap();
+            client.registers[0].value = 16;
+            client.registers[0].last_updated = Some(SystemTime::now());
+            client.coils.push(
+                serde_json::from_value(serde_json::json!({
+                    "name": "pump", "objecttype": "coil", "address": 3, "value": false,
+                    "interlocks": [{ "register": "status", "operator": "<", "value": 50 }]
+                }))
+                .unwrap(),
+            );
+        }
+        let write = |values: serde_json::Value| {
+            write_values(
+                "test_client_rtu".to_string(),
+                clients.clone(),
+                connections.clone(),
+                registry.clone(),
+                audit_context(),
+                serde_json::from_value(values).unwrap(),
+            )
+        };
+        // The status of the last poll allows the pump, the status of the request does not
+        let result = write(serde_json::json!({
+            "registers": { "status": 80 },
+            "coils": { "pump": true }
+        }))
+        .await;
+        assert_eq!(
+            result.err().unwrap().find::<CustomErrors>().unwrap().get_code(),
+            "interlock_active"
+        );
+        {
+            let memory = simulated_slave.memory.lock().unwrap();
+            assert!(!memory.holding.contains_key(&0x10));
+            assert!(!memory.coils.contains_key(&3));
+        }
+        // A status of the request which satisfies the interlock is allowed
+        let result = write(serde_json::json!({
+            "registers": { "status": 20 },
+            "coils": { "pump": true }
+        }))
+        .await;
+        assert!(result.is_ok());
+        let memory = simulated_slave.memory.lock().unwrap();
+        assert_eq!(memory.holding[&0x10], 20);
+        assert!(memory.coils[&3]);
+    }
+
+    #[tokio::test]
+    async fn test_write_verified() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        {
+            let mut clients = clients.lock().await;
+            let client = clients.clients.get_mut("test_client_rtu").unwrap();
+            client.registers[1].verify_delay_ms = Some(300);
+            client.coils.push(
+                serde_json::from_value(serde_json::json!({
+                    "name": "pump", "objecttype": "coil", "address": 3, "value": false,
+                    "verify_write": true, "verify_delay_ms": 0
+                }))
+                .unwrap(),
+            );
+        }
+        let params = HashMap::from([
+            ("operating_mode".to_string(), "heating".to_string()),
+            ("verify".to_string(), "true".to_string()),
+        ]);
+        let result = write_register(
+            "test_client_rtu".to_string(),
+            params.clone(),
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+        )
+        .await;
+        let written = reply_data(result.unwrap()).await;
+        assert_eq!(written["read_back"], "heating");
+        // The value read back is stored before the next poll
+        {
+            let clients = clients.lock().await;
+            let register = &clients.clients["test_client_rtu"].registers[1];
+            assert_eq!(register.value, 3);
+            assert_eq!(register.state.as_deref(), Some("heating"));
+            assert!(register.last_updated.is_some());
+        }
+        // The coil is verified by its config
+        let result = write_coil(
+            "test_client_rtu".to_string(),
+            HashMap::from([("pump".to_string(), "true".to_string())]),
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+        )
+        .await;
+        assert_eq!(reply_data(result.unwrap()).await["read_back"], "true");
+        // The device overwrites the register before it is read back
+        let memory = simulated_slave.memory.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            memory.lock().unwrap().holding.insert(0x11, 0);
+        });
+        let result = write_register(
+            "test_client_rtu".to_string(),
+            params,
+            clients.clone(),
+            connections,
+            registry,
+            audit_context(),
+        )
+        .await;
+        assert_eq!(
+            result.err().unwrap().find::<CustomErrors>().unwrap().get_code(),
+            "write_not_verified"
+        );
+        let clients = clients.lock().await;
+        assert_eq!(clients.clients["test_client_rtu"].registers[1].value, 0);
+    }
+
+    #[tokio::test]
+    async fn test_write_bitfield_verified() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, true).await;
+        let params = HashMap::from([
+            ("status_mode".to_string(), "5".to_string()),
+            ("verify".to_string(), "true".to_string()),
+        ]);
+        let result = write_bitfield(
+            "test_client_rtu".to_string(),
+            params.clone(),
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit_context(),
+        )
+        .await;
+        // Only the field is read back, the other bits of the register are not compared
+        assert_eq!(reply_data(result.unwrap()).await["read_back"], "5");
+        assert_eq!(
+            clients.lock().await.clients["test_client_rtu"].registers[0].value,
+            0x50
+        );
+        // The device clears the field before it is read back
+        let memory = simulated_slave.memory.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            memory.lock().unwrap().holding.insert(0x10, 0x01);
+        });
+        let result = write_bitfield(
+            "test_client_rtu".to_string(),
+            params,
+            clients,
+            connections,
+            registry,
+            audit_context(),
+        )
+        .await;
+        assert_eq!(
+            result.err().unwrap().find::<CustomErrors>().unwrap().get_code(),
+            "write_not_verified"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_write_audit() {
+        let (_slave, device, simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        let path = std::env::temp_dir().join(format!("modbus_audit_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        let audit_log = Arc::new(Mutex::new(AuditLog::new(path.to_str().unwrap(), 1024, 1)));
+        let audit = AuditContext {
+            log: audit_log.clone(),
+            caller: "10.0.0.7".to_string(),
+        };
+        {
+            let mut clients = clients.lock().await;
+            let register = &mut clients
+                .clients
+                .get_mut("test_client_rtu")
+                .unwrap()
+                .registers[1];
+            register.last_updated = Some(SystemTime::now());
+        }
+        for value in ["heating", "cooling"] {
+            let params = HashMap::from([
+                ("operating_mode".to_string(), value.to_string()),
+                ("confirm".to_string(), "true".to_string()),
+            ]);
+            let _ = write_register(
+                "test_client_rtu".to_string(),
+                params,
+                clients.clone(),
+                connections.clone(),
+                registry.clone(),
+                audit.clone(),
+            )
+            .await;
         }
+        // The device overwrites the status before it is read back, the operating mode stays written
+        let memory = simulated_slave.memory.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            memory.lock().unwrap().holding.insert(0x10, 0);
+        });
+        let values: WriteValues = serde_json::from_value(serde_json::json!({
+            "registers": { "operating_mode": "heating", "status": 7 },
+            "confirm": true,
+            "verify": true
+        }))
+        .unwrap();
+        let arrived_ms = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as u64;
+        let result = write_values(
+            "test_client_rtu".to_string(),
+            clients.clone(),
+            connections.clone(),
+            registry.clone(),
+            audit.clone(),
+            values,
+        )
+        .await;
+        assert!(result.is_err());
+        let pollers = Arc::new(Mutex::new(Pollers::new(
+            registry.clone(),
+            clients.clone(),
+            connections.clone(),
+            1000,
+        )));
+        let result = delete_client(
+            "meter".to_string(),
+            clients,
+            registry,
+            connections,
+            pollers,
+            audit,
+        )
+        .await;
+        assert!(result.is_err());
+        let params = HashMap::from([("client".to_string(), "test_client_rtu".to_string())]);
+        let reply = get_audit(params, audit_log.clone()).await.unwrap();
+        let entries = reply_data(reply).await;
+        assert_eq!(entries.as_array().unwrap().len(), 4);
+        assert_eq!(entries[0]["caller"], "10.0.0.7");
+        assert_eq!(entries[0]["action"], "write_register");
+        assert_eq!(entries[0]["item"], "operating_mode");
+        // The register was polled with the value 0, which is the state off
+        assert_eq!(entries[0]["old_value"], "off");
+        assert_eq!(entries[0]["new_value"], "heating");
+        assert_eq!(entries[0]["result"], "ok");
+        assert_eq!(entries[1]["result"], "value_not_parsable");
+        // Each value of a bulk write has its own result. The time is taken before the write and its verify delay
+        assert_eq!(entries[2]["item"], "operating_mode");
+        assert_eq!(entries[2]["result"], "ok");
+        assert_eq!(entries[3]["item"], "status");
+        assert_eq!(entries[3]["result"], "write_not_verified");
+        assert!(entries[3]["timestamp_ms"].as_u64().unwrap() < arrived_ms + 100);
+        let params = HashMap::from([("limit".to_string(), "1".to_string())]);
+        let entries = reply_data(get_audit(params, audit_log.clone()).await.unwrap()).await;
+        assert_eq!(entries[0]["action"], "delete_client");
+        assert_eq!(entries[0]["result"], "client_not_found");
+        let params = HashMap::from([("from".to_string(), "yesterday".to_string())]);
+        assert!(get_audit(params, audit_log).await.is_err());
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    // Get the data of a successful response out of the envelope
+    async fn reply_data(reply: impl warp::Reply) -> serde_json::Value {
+        let body = warp::hyper::body::to_bytes(reply.into_response().into_body())
+            .await
+            .unwrap();
+        let mut envelope: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(envelope["ok"], true);
+        envelope["data"].take()
+    }
+
+    #[tokio::test]
+    async fn test_get_registers() {
+        let (_slave, device, _simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        let client = clients.lock().await.clients["test_client_rtu"].clone();
+        registry.lock().await.register_client(&client).unwrap();
+        // Without a poll there is no value yet
+        let reply = get_register(
+            "test_client_rtu".to_string(),
+            "operating_mode".to_string(),
+            HashMap::new(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+        )
+        .await
+        .unwrap();
+        let value = reply_data(reply).await;
+        assert_eq!(value["value"], serde_json::Value::Null);
+        assert_eq!(value["quality"], "unknown");
+        // A fresh read asks the modbus client and stores the value
+        let params = HashMap::from([("fresh".to_string(), "true".to_string())]);
+        let reply = get_register(
+            "test_client_rtu".to_string(),
+            "operating_mode".to_string(),
+            params.clone(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+        )
+        .await
+        .unwrap();
+        let value = reply_data(reply).await;
+        assert_eq!(value["value"], 17.0);
+        assert_eq!(value["words"], serde_json::json!([17]));
+        assert_eq!(value["quality"], "good");
+        assert!(value["timestamp_ms"].is_u64());
+        let shared_client = clients.lock().await.clients["test_client_rtu"].clone();
+        assert_eq!(shared_client.registers[1].value, 17);
+        // Only the requested register was read
+        assert!(shared_client.registers[0].last_updated.is_none());
+        let reply = get_registers(
+            "test_client_rtu".to_string(),
+            params,
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+        )
+        .await
+        .unwrap();
+        let values = reply_data(reply).await;
+        assert_eq!(values[0]["name"], "status");
+        assert_eq!(values[0]["value"], 16.0);
+        assert_eq!(values[1]["value"], 17.0);
+        // Unknown registers and invalid parameters are rejected
+        let result = get_register(
+            "test_client_rtu".to_string(),
+            "power".to_string(),
+            HashMap::new(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+        )
+        .await;
+        assert!(result.is_err());
+        let params = HashMap::from([("fresh".to_string(), "yes".to_string())]);
+        let result = get_registers(
+            "test_client_rtu".to_string(),
+            params,
+            clients,
+            registry,
+            connections,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_coils() {
+        let (_slave, device, _simulated_slave) = spawn_rtu_simulated_slave();
+        let (clients, connections, registry) = setup(&device, false).await;
+        clients
+            .lock()
+            .await
+            .clients
+            .get_mut("test_client_rtu")
+            .unwrap()
+            .coils
+            .push(Clients::Coil {
+                name: "alarm".to_string(),
+                objecttype: "discrete".to_string(),
+                address: 3,
+                ..Default::default()
+            });
+        let params = HashMap::from([("fresh".to_string(), "true".to_string())]);
+        let reply = get_coil(
+            "test_client_rtu".to_string(),
+            "alarm".to_string(),
+            params.clone(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+        )
+        .await
+        .unwrap();
+        let value = reply_data(reply).await;
+        // Discrete inputs of the simulator are on for odd addresses
+        assert_eq!(value["value"], true);
+        assert_eq!(value["quality"], "good");
+        let reply = get_coils(
+            "test_client_rtu".to_string(),
+            HashMap::new(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+        )
+        .await
+        .unwrap();
+        let values = reply_data(reply).await;
+        assert_eq!(values.as_array().unwrap().len(), 1);
+        assert_eq!(values[0]["value"], true);
+        let result = get_coil(
+            "test_client_rtu".to_string(),
+            "pump".to_string(),
+            params,
+            clients,
+            registry,
+            connections,
+        )
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_client() {
+        let (_slave, device, _simulated_slave) = spawn_rtu_simulated_slave();
+        let (shared_clients, connections, registry) = setup(&device, false).await;
+        let client = shared_clients.lock().await.clients["test_client_rtu"].clone();
+        let config = std::env::temp_dir().join("modbus_prometheus_api_server_test_update");
+        std::fs::create_dir_all(&config).unwrap();
+        let clients = Arc::new(Mutex::new(Clients::Clients::new(config.to_str().unwrap())));
+        clients
+            .lock()
+            .await
+            .add_client(client.name.clone(), client.clone());
+        registry.lock().await.register_client(&client).unwrap();
+        let pollers = Arc::new(Mutex::new(Pollers::new(
+            registry.clone(),
+            clients.clone(),
+            connections.clone(),
+            1000,
+        )));
+        let patch = serde_json::json!({
+            "coils": [{ "name": "pump", "objecttype": "coil", "address": 3, "value": false }]
+        });
+        let result = patch_client(
+            client.name.clone(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+            pollers.clone(),
+            audit_context(),
+            patch,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(clients.lock().await.clients["test_client_rtu"]
+            .get_coil_by_name("pump")
+            .is_some());
+        assert!(registry
+            .lock()
+            .await
+            .counters
+            .contains_key("test_client_rtu_pump"));
+        let config_file = config.join("test_client_rtu.json");
+        assert!(std::fs::read_to_string(&config_file)
+            .unwrap()
+            .contains("pump"));
+        // The name can not be changed and the new config must be valid
+        let mut client_input = client.clone();
+        client_input.name = "other_client".to_string();
+        let result = update_client(
+            client.name.clone(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+            pollers.clone(),
+            audit_context(),
+            client_input,
+        )
+        .await;
+        assert!(result.is_err());
+        let patch = serde_json::json!({ "protocol": "udp" });
+        let result = patch_client(
+            client.name.clone(),
+            clients.clone(),
+            registry.clone(),
+            connections.clone(),
+            pollers.clone(),
+            audit_context(),
+            patch,
+        )
+        .await;
+        assert!(result.is_err());
+        // Replacing the client with its first config removes the coil again
+        let result = update_client(
+            client.name.clone(),
+            clients.clone(),
+            registry.clone(),
+            connections,
+            pollers.clone(),
+            audit_context(),
+            client,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert!(!registry
+            .lock()
+            .await
+            .counters
+            .contains_key("test_client_rtu_pump"));
+        pollers.lock().await.stop("test_client_rtu");
+        std::fs::remove_dir_all(&config).unwrap();
     }
 }
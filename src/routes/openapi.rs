@@ -0,0 +1,242 @@
+use super::reply::{ErrorBody, ErrorEnvelope};
+use super::{WriteResult, WriteValues};
+use crate::audit::AuditEntry;
+use crate::clients::live_values::{CoilValue, RegisterValue};
+use crate::clients::write_guard::Interlock;
+use crate::clients::{Bitfield, Client, Coil, Register, Serial};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityRequirement, SecurityScheme};
+use utoipa::openapi::{ContentBuilder, Ref, ResponseBuilder};
+use utoipa::{Modify, OpenApi};
+
+/// ApiDoc struct
+///
+/// OpenAPI 3 document of all routes in main.rs. New routes must be added to the paths,
+/// test_every_route_is_documented fails otherwise.
+///
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Modbus Prometheus API Server",
+        description = "Reads modbus clients, exports their registers and coils as prometheus metrics and writes them via REST"
+    ),
+    paths(
+        super::metrics_handler,
+        super::get_clients,
+        super::create_client,
+        super::get_client,
+        super::delete_client,
+        super::update_client,
+        super::patch_client,
+        super::get_registers,
+        super::get_register,
+        super::get_coils,
+        super::get_coil,
+        super::write_register,
+        super::write_bitfield,
+        super::write_coil,
+        super::write_values,
+        super::get_audit,
+        openapi_handler,
+        docs_handler,
+    ),
+    components(schemas(
+        Client,
+        Serial,
+        Register,
+        Bitfield,
+        Coil,
+        Interlock,
+        RegisterValue,
+        CoilValue,
+        WriteResult,
+        WriteValues,
+        AuditEntry,
+        ErrorEnvelope,
+        ErrorBody
+    )),
+    tags(
+        (name = "clients", description = "Create, read, update and delete modbus clients"),
+        (name = "values", description = "Live values of registers and coils"),
+        (name = "writes", description = "Write registers, bitfields and coils one by one or in bulk"),
+        (name = "audit", description = "Audit log of all writes and client changes"),
+        (name = "metrics", description = "Prometheus metrics"),
+        (name = "docs", description = "This document")
+    ),
+    modifiers(&SecurityAddon)
+)]
+pub struct ApiDoc;
+
+// Add the authentication to all routes. The routes need the scope read, write or admin, see README
+struct SecurityAddon;
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer_token",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)),
+        );
+        components.add_security_scheme(
+            "basic_auth",
+            SecurityScheme::Http(Http::new(HttpAuthScheme::Basic)),
+        );
+        openapi.security = Some(vec![
+            SecurityRequirement::new("bearer_token", Vec::<String>::new()),
+            SecurityRequirement::new("basic_auth", Vec::<String>::new()),
+        ]);
+        let error = |description: &str| {
+            ResponseBuilder::new()
+                .description(description)
+                .content(
+                    "application/json",
+                    ContentBuilder::new()
+                        .schema(Some(Ref::from_schema_name("ErrorEnvelope")))
+                        .build(),
+                )
+                .build()
+        };
+        for path_item in openapi.paths.paths.values_mut() {
+            let operations = [
+                &mut path_item.get,
+                &mut path_item.put,
+                &mut path_item.post,
+                &mut path_item.delete,
+                &mut path_item.patch,
+            ];
+            for operation in operations.into_iter().flatten() {
+                let responses = &mut operation.responses.responses;
+                responses.insert(
+                    "401".to_string(),
+                    error("authentication_required, authentication_not_valid").into(),
+                );
+                responses.insert("403".to_string(), error("scope_missing").into());
+            }
+        }
+    }
+}
+
+// Swagger UI from a CDN, showing the document of this server
+const DOCS_PAGE: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8" />
+  <title>Modbus Prometheus API Server</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css" />
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
+
+// GET /openapi.json - get the OpenAPI document of the server
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "docs",
+    responses(
+        (status = 200, description = "The OpenAPI 3 document of the server", body = Object),
+    )
+)]
+pub async fn openapi_handler() -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&ApiDoc::openapi()))
+}
+
+// GET /docs - browse the OpenAPI document with Swagger UI. Only served if api_docs_ui is set in setup.toml
+#[utoipa::path(
+    get,
+    path = "/docs",
+    tag = "docs",
+    responses(
+        (status = 200, description = "Swagger UI page", body = String, content_type = "text/html"),
+        (status = 404, description = "route_not_found if api_docs_ui is off", body = ErrorEnvelope),
+    )
+)]
+pub async fn docs_handler(enabled: bool) -> Result<impl warp::Reply, warp::Rejection> {
+    if !enabled {
+        return Err(warp::reject::not_found());
+    }
+    Ok(warp::reply::html(DOCS_PAGE))
+}
+
+// ----------------- TESTS -----------------
+#[cfg(test)]
+mod test_openapi {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    // Get method and path of every route in main.rs. Path parameters are written as {}
+    fn get_main_routes() -> BTreeSet<(String, String)> {
+        let main = include_str!("../main.rs");
+        let method_re = regex::Regex::new(r"^\w+ = warp::(\w+)\(\)").unwrap();
+        let segment_re =
+            regex::Regex::new(r#"warp::path\("([^"]+)"\)|warp::path::param::<\w+>\(\)"#).unwrap();
+        let mut routes = BTreeSet::new();
+        for statement in main.split("let ").filter(|s| s.contains(".and_then(")) {
+            let statement = statement.split(';').next().unwrap();
+            let method = match method_re.captures(statement) {
+                Some(captures) => captures[1].to_string(),
+                None => continue,
+            };
+            let path: String = segment_re
+                .captures_iter(statement)
+                .map(|captures| match captures.get(1) {
+                    Some(segment) => format!("/{}", segment.as_str()),
+                    None => "/{}".to_string(),
+                })
+                .collect();
+            routes.insert((method, path));
+        }
+        routes
+    }
+
+    fn get_documented_routes() -> BTreeSet<(String, String)> {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let param_re = regex::Regex::new(r"\{[^}]+\}").unwrap();
+        let mut routes = BTreeSet::new();
+        for (path, item) in document["paths"].as_object().unwrap() {
+            for method in item.as_object().unwrap().keys() {
+                routes.insert((method.clone(), param_re.replace_all(path, "{}").to_string()));
+            }
+        }
+        routes
+    }
+
+    #[test]
+    fn test_every_route_is_documented() {
+        let routes = get_main_routes();
+        assert!(routes.contains(&("get".to_string(), "/clients/{}/registers/{}".to_string())));
+        assert_eq!(routes, get_documented_routes());
+    }
+
+    #[test]
+    fn test_document() {
+        let document = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        assert!(document["openapi"].as_str().unwrap().starts_with("3."));
+        let schemas = &document["components"]["schemas"];
+        for schema in ["Client", "Register", "Coil", "ErrorEnvelope"] {
+            assert!(
+                schemas.get(schema).is_some(),
+                "schema {} is missing",
+                schema
+            );
+        }
+        // Values are not part of the config
+        assert!(schemas["Register"]["properties"]
+            .get("last_updated")
+            .is_none());
+        let responses = &document["paths"]["/clients/{name}"]["get"]["responses"];
+        assert!(responses.get("404").is_some());
+        // Every route requires authentication
+        let responses = &document["paths"]["/clients/{name}/set-register"]["put"]["responses"];
+        assert!(responses.get("401").is_some() && responses.get("403").is_some());
+        assert!(document["components"]["securitySchemes"]
+            .get("bearer_token")
+            .is_some());
+    }
+}
@@ -0,0 +1,85 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+use warp::http::StatusCode;
+use warp::reply::{Json, WithStatus};
+
+/// Envelope struct
+///
+/// Every JSON response of the API is wrapped in an envelope. Successful responses carry the data,
+/// failed responses an ErrorEnvelope with a stable error code:
+/// ```json
+/// { "ok": true, "data": { "client": "meter_1", "item": "setpoint", "value": "215" } }
+/// { "ok": false, "error": { "code": "client_not_found", "message": "Client meter_1 not found. ..." } }
+/// ```
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Envelope<T: Serialize> {
+    /// Always true
+    pub ok: bool,
+    pub data: T,
+}
+
+/// The envelope of a failed response
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorEnvelope {
+    /// Always false
+    pub ok: bool,
+    pub error: ErrorBody,
+}
+
+/// The error of a failed response
+#[derive(Serialize, Debug, ToSchema)]
+pub struct ErrorBody {
+    /// Stable machine readable code, e.g. client_not_found
+    pub code: String,
+    pub message: String,
+}
+
+/// Reply with data and the status 200
+///
+/// # Arguments
+///
+/// * `data` - The data of the response
+///
+/// # Returns
+///
+/// * `WithStatus<Json>` - The JSON envelope with the data
+pub fn data<T: Serialize>(data: T) -> WithStatus<Json> {
+    data_with_status(data, StatusCode::OK)
+}
+
+/// Reply with data and a status, e.g. 201 for created clients
+///
+/// # Arguments
+///
+/// * `data` - The data of the response
+/// * `status` - The HTTP status of the response
+///
+/// # Returns
+///
+/// * `WithStatus<Json>` - The JSON envelope with the data
+pub fn data_with_status<T: Serialize>(data: T, status: StatusCode) -> WithStatus<Json> {
+    let envelope = Envelope { ok: true, data };
+    warp::reply::with_status(warp::reply::json(&envelope), status)
+}
+
+/// Reply with an error
+///
+/// # Arguments
+///
+/// * `code` - The stable code of the error
+/// * `message` - The message of the error for humans
+/// * `status` - The HTTP status of the response
+///
+/// # Returns
+///
+/// * `WithStatus<Json>` - The JSON envelope with the error
+pub fn error(code: &str, message: String, status: StatusCode) -> WithStatus<Json> {
+    let envelope = ErrorEnvelope {
+        ok: false,
+        error: ErrorBody {
+            code: code.to_string(),
+            message,
+        },
+    };
+    warp::reply::with_status(warp::reply::json(&envelope), status)
+}
@@ -6,9 +6,41 @@ use serde_json::Value;
 use regex::Regex;
 use std::fs::{File, self};
 use std::io::Write;
+use tokio_serial::SerialStream;
 
+#[cfg(test)]
+pub mod simulator;
+
+/// Create a modbus context for the client. Depending on the client protocol the context
+/// is connected via tcp or attached to the serial line (rtu). The context talks to the unit id of the client
+///
+/// # Arguments
+///
+/// * `client` - The client to create the context for
+///
+/// # Returns
+///
+/// * `Ok(tokio_modbus::client::Context)` - The connected context
+/// * `Err(ErrorRuntimeNoRejection)` - If the settings of the client are invalid or the client could not be connected
 pub async fn create_ctx(
     client: &Client,
+) -> Result<tokio_modbus::client::Context, ErrorRuntimeNoRejection> {
+    match client.protocol.as_str() {
+        "tcp" => create_tcp_ctx(client).await,
+        "rtu" => create_rtu_ctx(client),
+        _ => {
+            log::error!(
+                "Protocol {} of client {} is not supported. Skip reading from this modbus client",
+                &client.protocol,
+                &client.name
+            );
+            Err(ErrorRuntimeNoRejection::ProtocolNotSupported)
+        }
+    }
+}
+
+async fn create_tcp_ctx(
+    client: &Client,
 ) -> Result<tokio_modbus::client::Context, ErrorRuntimeNoRejection> {
     log::debug!("Creating ipaddress: {}", &client.ip_address);
     let ipaddress = match client.ip_address.parse::<Ipv4Addr>() {
@@ -26,8 +58,14 @@ pub async fn create_ctx(
     };
     log::debug!("Creating socket address:");
     let socket_addr_v4 = SocketAddr::new(IpAddr::V4(ipaddress), client.port);
-    log::debug!("Connecting to client: {}", &socket_addr_v4);
-    let ctx = match tcp::connect(socket_addr_v4.to_owned()).await {
+    log::debug!(
+        "Connecting to client: {} with unit id: {}",
+        &socket_addr_v4,
+        client.get_unit_id()
+    );
+    let ctx = match tcp::connect_slave(socket_addr_v4.to_owned(), Slave(client.get_unit_id()))
+        .await
+    {
         Ok(ctx) => ctx,
         Err(_) => {
             log::error!(
@@ -40,7 +78,54 @@ pub async fn create_ctx(
     Ok(ctx)
 }
 
+fn create_rtu_ctx(client: &Client) -> Result<tokio_modbus::client::Context, ErrorRuntimeNoRejection> {
+    let serial = match &client.serial {
+        Some(serial) => serial,
+        None => {
+            log::error!(
+                "No serial settings for rtu client: {}. Skip reading from this modbus client",
+                &client.name
+            );
+            return Err(ErrorRuntimeNoRejection::InvalidSerialSettings);
+        }
+    };
+    let (parity, data_bits, stop_bits) =
+        match (serial.get_parity(), serial.get_data_bits(), serial.get_stop_bits()) {
+            (Some(parity), Some(data_bits), Some(stop_bits)) => (parity, data_bits, stop_bits),
+            _ => {
+                log::error!(
+                    "Invalid serial settings for rtu client: {}. Skip reading from this modbus client",
+                    &client.name
+                );
+                return Err(ErrorRuntimeNoRejection::InvalidSerialSettings);
+            }
+        };
+    log::debug!(
+        "Opening serial device: {} with {} baud",
+        &serial.device,
+        serial.baud_rate
+    );
+    let builder = tokio_serial::new(&serial.device, serial.baud_rate)
+        .parity(parity)
+        .data_bits(data_bits)
+        .stop_bits(stop_bits);
+    let port = match SerialStream::open(&builder) {
+        Ok(port) => port,
+        Err(_) => {
+            log::error!(
+                "Could not open serial device: {}. Skip reading from this modbus client",
+                &serial.device
+            );
+            return Err(ErrorRuntimeNoRejection::CouldNotConnect);
+        }
+    };
+    Ok(rtu::attach_slave(port, Slave(client.get_unit_id())))
+}
+
 /// Write JSON to local file. Filename <client name>.json
+///
+/// The JSON is written to a temporary file first, which then replaces the config file. So the config file is
+/// either the old or the new config, even if the server stops while writing.
 /// 
 /// # Arguments
 /// 
@@ -55,25 +140,59 @@ pub async fn create_ctx(
 pub fn write_config(client: &Client, config: &str) -> Result<(), ErrorRuntime> {
     let config_name = format!("{}.json", &client.name);
     let config_path = format!("{}/{}", config, &config_name);
+    let tmp_path = format!("{}.tmp", &config_path);
     
     let config_json = match serde_json::to_string_pretty(&client){
         Ok(config_json) => config_json,
         Err(_) => return Err(ErrorRuntime::JSONSerializeError),
     };
-    let mut config_file = match File::create(&config_path){
+    let mut config_file = match File::create(&tmp_path){
         Ok(config_file) => config_file,
         Err(_) => return Err(ErrorRuntime::FSFileCreateError),
     };
-    if let Err(_) = config_file.write_all(config_json.as_bytes()) {
+    if config_file
+        .write_all(config_json.as_bytes())
+        .and_then(|_| config_file.sync_all())
+        .and_then(|_| fs::rename(&tmp_path, &config_path))
+        .is_err()
+    {
+        let _ = fs::remove_file(&tmp_path);
         return Err(ErrorRuntime::FSFileCreateError);
     }
-    log::info!(
-        "Created new client via POST /clients. Stored the client config to {}",
-        &config_path);
+    log::info!("Stored the client config to {}", &config_path);
     Ok(())
 }
 
 
+/// Apply a JSON merge patch (RFC 7386) to a JSON value. Objects are merged recursively, null removes a field and
+/// all other values, including arrays, replace the value of the field.
+///
+/// # Arguments
+///
+/// * `target` - The JSON value to patch
+/// * `patch` - The merge patch
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let patch = match patch {
+        Value::Object(patch) => patch,
+        _ => {
+            *target = patch.clone();
+            return;
+        }
+    };
+    if !target.is_object() {
+        *target = Value::Object(serde_json::Map::new());
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.as_str()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
 /// Deletes one specific client config file from the main config path.
 /// 
 /// # Arguments
@@ -122,7 +241,9 @@ pub fn check_client_strings(input: &Value) -> Result<(), ErrorRuntime> {
         }
         Value::Object(obj) => {
             for (key, v) in obj {
-                if key != "ip_address" {
+                // ip addresses, serial device paths and prometheus label values can not follow the naming convention.
+                // Label names are checked by Client::verify
+                if key != "ip_address" && key != "device" && key != "labels" {
                     check_client_strings(&v)?;
                 }
             }
@@ -156,7 +277,7 @@ pub fn check_if_client_exist(client_file_name: &str, config: &str) -> Result<(),
     Ok(())
 }
 
-/// Based on the main config path, get all the config files in that path.
+/// Based on the main config path, get all the config files in that path. Only files ending with .json are config files.
 /// If full_path is true, the full path to the config file is returned. Else it will just return the filename.
 /// The config files should be JSON files with the client name as filename
 /// and should be stored in /etc/modbus-prometheus-api-server/config for linux and Mac OS
@@ -188,6 +309,10 @@ pub fn get_local_config_files(
             Ok(dir_entry) => dir_entry,
             Err(_) => return Err(ErrorRuntime::FSDirEntryError),
         };
+        // Skip temporary files of write_config
+        if dir_entry.path().extension() != Some(std::ffi::OsStr::new("json")) {
+            continue;
+        }
         if full_path {
             match dir_entry.path().to_str() {
                 Some(full_path) => config_files.push(full_path.to_string()),
@@ -205,6 +330,7 @@ pub fn get_local_config_files(
 
 #[cfg(test)]
 mod test_utils {
+    use super::simulator::*;
     use super::*;
     const TEST_CLIENT_JSON_OK: &str = r#"{
         "name": "test_client_tmp",
@@ -343,6 +469,30 @@ mod test_utils {
         assert_eq!(result.is_ok(), false);
     }
 
+    #[test]
+    fn test_merge_patch() {
+        let mut target = serde_json::json!({
+            "name": "meter",
+            "port": 502,
+            "labels": { "site": "a", "room": "1" },
+            "registers": [{ "name": "power" }]
+        });
+        let patch = serde_json::json!({
+            "port": 503,
+            "labels": { "room": null, "floor": "2" },
+            "registers": []
+        });
+        merge_patch(&mut target, &patch);
+        assert_eq!(
+            target,
+            serde_json::json!({
+                "name": "meter",
+                "port": 503,
+                "labels": { "site": "a", "floor": "2" },
+                "registers": []
+            })
+        );
+    }
     #[test]
     fn test_write_and_delete_config(){
         let client_json = serde_json::from_str(TEST_CLIENT_JSON_OK).unwrap();
@@ -350,7 +500,32 @@ mod test_utils {
         let config = "/etc/modbus-prometheus-api-server/config";
         let result = write_config(&client, config);
         assert_eq!(result.is_ok(), true);
+        // The temporary file replaced the config file
+        assert!(!std::path::Path::new(&format!("{}/{}.json.tmp", config, client.name)).exists());
         let result = delete_config(&client.name, config);
         assert_eq!(result.is_ok(), true);
     }
+
+    #[tokio::test]
+    async fn test_create_ctx_rtu_pty_pair() {
+        let (_slave, device, _) = spawn_rtu_simulated_slave();
+        let client = rtu_client(&device, "");
+        let mut ctx = create_ctx(&client).await.unwrap();
+        let data = ctx.read_holding_registers(3, 2).await.unwrap().unwrap();
+        assert_eq!(data, vec![3, 4]);
+        assert!(ctx.write_single_register(7, 42).await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_ctx_rtu_unit_id() {
+        let (_slave, device, _) = spawn_rtu_simulated_slave();
+        let client = rtu_client(&device, r#""unit_id": 17,"#);
+        let mut ctx = create_ctx(&client).await.unwrap();
+        let data = ctx.read_input_registers(0, 1).await.unwrap().unwrap();
+        assert_eq!(data, vec![17]);
+        // Override for a single register
+        ctx.set_slave(Slave(client.resolve_unit_id(Some(42))));
+        let data = ctx.read_input_registers(0, 1).await.unwrap().unwrap();
+        assert_eq!(data, vec![42]);
+    }
 }
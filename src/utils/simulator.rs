@@ -0,0 +1,123 @@
+use crate::clients::Client;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio_modbus::prelude::*;
+use tokio_serial::{SerialPort, SerialStream};
+
+/// Memory of the simulated slave
+///
+/// Holding registers which were never written return their own address, input registers return the
+/// unit id the request was sent to, coils which were never written are off and discrete inputs are on for odd addresses.
+#[derive(Debug, Default)]
+pub struct SimulatedMemory {
+    pub holding: HashMap<u16, u16>,
+    pub coils: HashMap<u16, bool>,
+    /// Number of requests the slave has answered
+    pub requests: usize,
+}
+
+/// Simulated modbus slave for tests against a real serial line
+#[derive(Clone, Debug, Default)]
+pub struct SimulatedSlave {
+    pub memory: Arc<Mutex<SimulatedMemory>>,
+}
+impl tokio_modbus::server::Service for SimulatedSlave {
+    type Request = SlaveRequest<'static>;
+    type Response = Response;
+    type Exception = ExceptionCode;
+    type Future = std::future::Ready<Result<Self::Response, Self::Exception>>;
+
+    fn call(&self, req: Self::Request) -> Self::Future {
+        let mut memory = self.memory.lock().unwrap();
+        memory.requests += 1;
+        let response = match req.request {
+            Request::ReadHoldingRegisters(address, quantity) => Ok(Response::ReadHoldingRegisters(
+                (address..address + quantity)
+                    .map(|address| *memory.holding.get(&address).unwrap_or(&address))
+                    .collect(),
+            )),
+            Request::ReadInputRegisters(_, quantity) => Ok(Response::ReadInputRegisters(vec![
+                    req.slave as u16;
+                    quantity as usize
+                ])),
+            Request::ReadCoils(address, quantity) => Ok(Response::ReadCoils(
+                (address..address + quantity)
+                    .map(|address| *memory.coils.get(&address).unwrap_or(&false))
+                    .collect(),
+            )),
+            Request::ReadDiscreteInputs(address, quantity) => Ok(Response::ReadDiscreteInputs(
+                (address..address + quantity)
+                    .map(|address| address % 2 == 1)
+                    .collect(),
+            )),
+            Request::WriteSingleRegister(address, value) => {
+                memory.holding.insert(address, value);
+                Ok(Response::WriteSingleRegister(address, value))
+            }
+            Request::WriteMultipleRegisters(address, values) => {
+                for (offset, value) in values.iter().enumerate() {
+                    memory.holding.insert(address + offset as u16, *value);
+                }
+                Ok(Response::WriteMultipleRegisters(
+                    address,
+                    values.len() as u16,
+                ))
+            }
+            Request::MaskWriteRegister(address, and_mask, or_mask) => {
+                let value = *memory.holding.get(&address).unwrap_or(&address);
+                memory
+                    .holding
+                    .insert(address, (value & and_mask) | (or_mask & !and_mask));
+                Ok(Response::MaskWriteRegister(address, and_mask, or_mask))
+            }
+            Request::WriteSingleCoil(address, value) => {
+                memory.coils.insert(address, value);
+                Ok(Response::WriteSingleCoil(address, value))
+            }
+            Request::WriteMultipleCoils(address, values) => {
+                for (offset, value) in values.iter().enumerate() {
+                    memory.coils.insert(address + offset as u16, *value);
+                }
+                Ok(Response::WriteMultipleCoils(address, values.len() as u16))
+            }
+            _ => Err(ExceptionCode::IllegalFunction),
+        };
+        std::future::ready(response)
+    }
+}
+
+/// Spawn the simulated slave on the master side of a pseudo terminal pair
+///
+/// # Returns
+///
+/// * `(SerialStream, String, SimulatedSlave)` - The slave side of the pair, which must be kept open,
+///   its device path and the simulated slave to inspect its memory
+pub fn spawn_rtu_simulated_slave() -> (SerialStream, String, SimulatedSlave) {
+    let (master, slave) = SerialStream::pair().unwrap();
+    let device = slave.name().unwrap();
+    let simulated_slave = SimulatedSlave::default();
+    let server = tokio_modbus::server::rtu::Server::new(master);
+    tokio::spawn(server.serve_forever(simulated_slave.clone()));
+    (slave, device, simulated_slave)
+}
+
+/// Create a rtu client for a serial device
+///
+/// # Arguments
+///
+/// * `device` - The device path of the serial line
+/// * `fields` - Additional JSON fields of the client, each followed by a comma
+pub fn rtu_client(device: &str, fields: &str) -> Client {
+    let client_json = format!(
+        r#"{{
+            "name": "test_client_rtu",
+            "protocol": "rtu",
+            "serial": {{ "device": "{}", "baud_rate": 19200 }},
+            {}
+            "registers": [],
+            "coils": []
+        }}"#,
+        device, fields
+    );
+    Client::new(client_json).unwrap()
+}